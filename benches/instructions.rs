@@ -0,0 +1,88 @@
+//! Measures how many instructions per second the CPU executes, running a loop
+//! that mixes addressing modes, arithmetic, branches, and stack operations.
+//!
+//! Run with `cargo bench`. Pass a number of instructions to override the
+//! default, e.g. `cargo bench -- 100000000`.
+//!
+//! There's no baseline built in, so to measure a change, run the benchmark on
+//! the commit before it too, e.g. in a worktree:
+//!
+//! ```text
+//! git worktree add ../baseline HEAD~1
+//! (cd ../baseline && cargo bench --bench instructions)
+//! cargo bench --bench instructions
+//! ```
+//!
+//! Rates vary by a few percent between runs, so compare the best of several.
+
+use std::{env, hint::black_box, time::Instant};
+
+use nes799::cpu::{mode::Mode, CPU};
+
+const DEFAULT_INSTRUCTIONS: u64 = 20_000_000;
+
+/// Number of untimed instructions to run first.
+const WARMUP: u64 = 1_000_000;
+
+/// Where the program is loaded and started, which its jumps depend on.
+const ORIGIN: u16 = 0x8000;
+/// Zero page pointer to the page the program writes through indirectly.
+const POINTER: u16 = 0x10;
+
+#[rustfmt::skip]
+const PROGRAM: &[u8] = &[
+    0xa2, 0x00,       // start: LDX #$00
+    0xa0, 0x10,       //        LDY #$10
+    0xbd, 0x00, 0x02, // loop:  LDA $0200,X
+    0x18,             //        CLC
+    0x69, 0x03,       //        ADC #$03
+    0x9d, 0x00, 0x02, //        STA $0200,X
+    0x91, 0x10,       //        STA ($10),Y
+    0x45, 0x20,       //        EOR $20
+    0x26, 0x21,       //        ROL $21
+    0x48,             //        PHA
+    0x68,             //        PLA
+    0x20, 0x1e, 0x80, //        JSR sub
+    0xe8,             //        INX
+    0xd0, 0xe9,       //        BNE loop
+    0x4c, 0x00, 0x80, //        JMP start
+    0xc8,             // sub:   INY
+    0x60,             //        RTS
+];
+
+/// Run the program for the given number of instructions on a CPU in the given
+/// mode, and return the number executed per second.
+fn measure(mode: Mode, instructions: u64) -> f64 {
+    let mut cpu = CPU::new();
+    cpu.mode = mode;
+    cpu.load_at(PROGRAM, ORIGIN);
+    cpu.poke(POINTER + 1, 0x03);
+    cpu.reset();
+    cpu.program_counter = ORIGIN;
+
+    for _ in 0..WARMUP {
+        cpu.step();
+    }
+
+    let start = Instant::now();
+    for _ in 0..instructions {
+        cpu.step();
+    }
+    let elapsed = start.elapsed();
+
+    black_box(&cpu);
+    instructions as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+    // `cargo bench` passes --bench, which is ignored along with other flags.
+    let instructions = env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_INSTRUCTIONS);
+
+    for mode in Mode::ALL {
+        let rate = measure(mode, instructions);
+        println!("{:8} {:>7.2}M instructions/s", mode, rate / 1_000_000.0);
+    }
+}
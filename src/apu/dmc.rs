@@ -0,0 +1,239 @@
+use std::io;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles, indexed by the lower four bits of $4010.
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// Delta modulation channel, which plays 1-bit delta-encoded samples read
+/// directly from CPU memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Dmc {
+    pub irq: bool,
+    irq_enabled: bool,
+    looping: bool,
+    timer: u16,
+    timer_period: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq: false,
+            irq_enabled: false,
+            looping: false,
+            timer: 0,
+            timer_period: RATE_TABLE[0],
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+}
+
+impl Dmc {
+    /// Write to one of the channel's four registers, numbered 0 to 3.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                self.timer_period = RATE_TABLE[usize::from(value & 0x0f)];
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.output_level = value & 0x7f,
+            2 => self.sample_address = 0xc000 | (u16::from(value) << 6),
+            3 => self.sample_length = (u16::from(value) << 4) + 1,
+            _ => unreachable!("the DMC has four registers"),
+        }
+    }
+
+    /// Enable or disable sample playback, restarting the sample if it had
+    /// finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Whether there are bytes of the current sample left to read.
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address the memory reader needs to fetch next, if the sample buffer
+    /// is empty and the sample isn't finished.
+    pub fn pending_read(&self) -> Option<u16> {
+        match self.sample_buffer {
+            None if self.bytes_remaining > 0 => Some(self.current_address),
+            _ => None,
+        }
+    }
+
+    /// Fill the sample buffer with a byte fetched by the memory reader, and
+    /// advance to the next byte of the sample.
+    pub fn fill(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clock the timer, moving the output level by one bit of the shift
+    /// register when it reaches zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+
+            match self.sample_buffer.take() {
+                Some(value) => {
+                    self.silence = false;
+                    self.shift_register = value;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// The current output level, from 0 to 127.
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+}
+
+impl SaveState for Dmc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.irq);
+        state.bool(self.irq_enabled);
+        state.bool(self.looping);
+        state.u16(self.timer);
+        state.u16(self.timer_period);
+        state.u8(self.output_level);
+        state.u16(self.sample_address);
+        state.u16(self.sample_length);
+        state.u16(self.current_address);
+        state.u16(self.bytes_remaining);
+        state.bool(self.sample_buffer.is_some());
+        state.u8(self.sample_buffer.unwrap_or_default());
+        state.u8(self.shift_register);
+        state.u8(self.bits_remaining);
+        state.bool(self.silence);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.irq = state.bool()?;
+        self.irq_enabled = state.bool()?;
+        self.looping = state.bool()?;
+        self.timer = state.u16()?;
+        self.timer_period = state.u16()?;
+        self.output_level = state.u8()?;
+        self.sample_address = state.u16()?;
+        self.sample_length = state.u16()?;
+        self.current_address = state.u16()?;
+        self.bytes_remaining = state.u16()?;
+        let buffered = state.bool()?;
+        let sample = state.u8()?;
+        self.sample_buffer = buffered.then_some(sample);
+        self.shift_register = state.u8()?;
+        self.bits_remaining = state.u8()?;
+        self.silence = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_reader() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, 0x80); // IRQ enabled
+        dmc.write(2, 0x01);
+        dmc.write(3, 0x00); // 1 byte long
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.pending_read(), Some(0xc040));
+        dmc.fill(0xff);
+
+        assert_eq!(dmc.pending_read(), None);
+        assert!(!dmc.active());
+        assert!(dmc.irq);
+    }
+
+    #[test]
+    fn test_output_level() {
+        let mut dmc = Dmc::default();
+        dmc.write(1, 0x40);
+        dmc.write(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.fill(0b0000_0011);
+
+        // Shift out the empty initial bits to load the sample buffer.
+        for _ in 0..8 {
+            dmc.timer = 0;
+            dmc.clock_timer();
+        }
+
+        for _ in 0..8 {
+            dmc.timer = 0;
+            dmc.clock_timer();
+        }
+
+        assert_eq!(dmc.output(), 0x40 + 2 + 2 - 2 * 6);
+    }
+}
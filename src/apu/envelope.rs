@@ -0,0 +1,121 @@
+use std::io;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Volume envelope shared by the pulse and noise channels, which either
+/// outputs a constant volume or a sawtooth decaying from 15 to 0.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Envelope {
+    pub start: bool,
+    pub looping: bool,
+    pub constant: bool,
+    pub volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Set the loop, constant volume, and volume/period bits from the lower six
+    /// bits of a channel control register.
+    pub fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0f;
+    }
+
+    /// Clock the envelope divider, restarting the decay if the start flag is set.
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    /// The current volume, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.start);
+        state.bool(self.looping);
+        state.bool(self.constant);
+        state.u8(self.volume);
+        state.u8(self.divider);
+        state.u8(self.decay);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.start = state.bool()?;
+        self.looping = state.bool()?;
+        self.constant = state.bool()?;
+        self.volume = state.u8()?;
+        self.divider = state.u8()?;
+        self.decay = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_constant_volume() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x1a);
+        envelope.clock();
+
+        assert_eq!(envelope.output(), 0x0a);
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x00);
+        envelope.start = true;
+
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        envelope.clock();
+        assert_eq!(envelope.output(), 14);
+
+        for _ in 0..20 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+    }
+
+    #[test]
+    fn test_decay_loop() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x20);
+        envelope.start = true;
+
+        for _ in 0..16 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+    }
+}
@@ -0,0 +1,63 @@
+use std::io;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Lengths loaded into the counter, indexed by the upper five bits written to
+/// a channel's length register.
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Counter that silences a channel once it has been clocked down to zero.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LengthCounter {
+    enabled: bool,
+    pub halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Enable or disable the counter, immediately clearing it when disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    /// Load the counter from the length table, if the channel is enabled.
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[usize::from(index & 0x1f)];
+        }
+    }
+
+    /// Decrement the counter unless it is halted or already zero.
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    /// Whether the counter is non-zero, allowing the channel to output sound.
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.bool(self.halt);
+        state.u8(self.counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.enabled = state.bool()?;
+        self.halt = state.bool()?;
+        self.counter = state.u8()?;
+        Ok(())
+    }
+}
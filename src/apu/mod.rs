@@ -0,0 +1,443 @@
+use std::io;
+
+use self::{
+    dmc::Dmc,
+    noise::Noise,
+    pulse::{Pulse, PulseChannel},
+    triangle::Triangle,
+};
+use crate::state::{SaveState, StateReader, StateWriter};
+
+mod dmc;
+mod envelope;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+/// NTSC CPU clock rate, in Hz.
+pub const CPU_FREQUENCY: u32 = 1_789_773;
+/// Output sample rate used unless another is configured.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// First address of the APU's memory-mapped registers.
+pub const REGISTERS: u16 = 0x4000;
+/// Last address of the channel registers.
+pub const REGISTERS_END: u16 = 0x4013;
+/// Channel enable (write) and status (read) register.
+pub const STATUS: u16 = 0x4015;
+/// Frame counter mode and IRQ inhibit register.
+pub const FRAME_COUNTER: u16 = 0x4017;
+
+/// Sequencer modes of the frame counter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrameMode {
+    /// Four steps per sequence, raising a frame IRQ at the end of each.
+    #[default]
+    FourStep,
+    /// Five steps per sequence, never raising a frame IRQ.
+    FiveStep,
+}
+
+/// Implementation of the 2A03's audio processing unit.
+#[derive(Debug, Clone)]
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_mode: FrameMode,
+    frame_cycle: u32,
+    frame_irq: bool,
+    irq_inhibit: bool,
+    cycle: u64,
+    sample_rate: u32,
+    sample_clock: u64,
+    sample_sum: f32,
+    sample_count: u32,
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Apu {
+    /// Create an APU that outputs samples at the given rate, in Hz.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            pulse1: Pulse::new(PulseChannel::One),
+            pulse2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_mode: FrameMode::default(),
+            frame_cycle: 0,
+            frame_irq: false,
+            irq_inhibit: false,
+            cycle: 0,
+            sample_rate,
+            sample_clock: 0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Change the output sample rate, discarding any partially mixed sample.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+    }
+
+    /// Samples mixed since they were last taken.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Take all of the samples mixed since they were last taken.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Write a value to one of the APU's registers.
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, value),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, value),
+            0x4008..=0x400b => self.triangle.write(addr - 0x4008, value),
+            0x400c..=0x400f => self.noise.write(addr - 0x400c, value),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, value),
+            STATUS => {
+                self.pulse1.length.set_enabled(value & 0x01 != 0);
+                self.pulse2.length.set_enabled(value & 0x02 != 0);
+                self.triangle.length.set_enabled(value & 0x04 != 0);
+                self.noise.length.set_enabled(value & 0x08 != 0);
+                self.dmc.set_enabled(value & 0x10 != 0);
+                self.dmc.irq = false;
+            }
+            FRAME_COUNTER => {
+                self.frame_mode = if value & 0x80 != 0 {
+                    FrameMode::FiveStep
+                } else {
+                    FrameMode::FourStep
+                };
+                self.irq_inhibit = value & 0x40 != 0;
+                self.frame_cycle = 0;
+
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+
+                if self.frame_mode == FrameMode::FiveStep {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the status register, which reports which channels are active and
+    /// which interrupts are pending, and acknowledges the frame interrupt.
+    pub fn read_status(&mut self) -> u8 {
+        let status = u8::from(self.pulse1.length.active())
+            | u8::from(self.pulse2.length.active()) << 1
+            | u8::from(self.triangle.length.active()) << 2
+            | u8::from(self.noise.length.active()) << 3
+            | u8::from(self.dmc.active()) << 4
+            | u8::from(self.frame_irq) << 6
+            | u8::from(self.dmc.irq) << 7;
+
+        self.frame_irq = false;
+        status
+    }
+
+    /// Whether the frame counter or the DMC is asserting an interrupt.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// The address the DMC memory reader is waiting to have fetched, if any.
+    pub fn dmc_read_address(&self) -> Option<u16> {
+        self.dmc.pending_read()
+    }
+
+    /// Supply the DMC with the byte at the address it requested.
+    pub fn dmc_fill(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+
+    /// Advance the APU by a single CPU cycle.
+    pub fn tick(&mut self) {
+        self.cycle += 1;
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        // The pulse channels are clocked once every APU cycle, which is every
+        // other CPU cycle.
+        if self.cycle.is_multiple_of(2) {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.clock_frame_counter();
+        self.clock_sample();
+    }
+
+    /// Mix the current output of every channel into a single sample, from 0.0
+    /// to 1.0.
+    pub fn output(&self) -> f32 {
+        let pulse = f32::from(self.pulse1.output() + self.pulse2.output());
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = f32::from(self.triangle.output()) / 8227.0
+            + f32::from(self.noise.output()) / 12241.0
+            + f32::from(self.dmc.output()) / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    /// Step the frame counter sequencer, clocking the envelopes, linear
+    /// counter, length counters, and sweep units at the appropriate points.
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+
+        match (self.frame_mode, self.frame_cycle) {
+            (_, 7457) | (_, 22371) => self.clock_quarter_frame(),
+            (_, 14913) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (FrameMode::FourStep, 29828) => self.raise_frame_irq(),
+            (FrameMode::FourStep, 29829) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.raise_frame_irq();
+            }
+            (FrameMode::FourStep, 29830) => {
+                self.raise_frame_irq();
+                self.frame_cycle = 0;
+            }
+            (FrameMode::FiveStep, 37281) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (FrameMode::FiveStep, 37282) => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_envelope();
+        self.pulse2.clock_envelope();
+        self.triangle.clock_linear();
+        self.noise.clock_envelope();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_length();
+        self.pulse2.clock_sweep();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    fn raise_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    /// Accumulate the mixer output, and average it into an output sample
+    /// whenever enough CPU cycles have passed for the configured sample rate.
+    fn clock_sample(&mut self) {
+        self.sample_sum += self.output();
+        self.sample_count += 1;
+        self.sample_clock += u64::from(self.sample_rate);
+
+        if self.sample_clock >= u64::from(CPU_FREQUENCY) {
+            self.sample_clock -= u64::from(CPU_FREQUENCY);
+            self.samples
+                .push(self.sample_sum / self.sample_count as f32);
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+}
+
+impl SaveState for Apu {
+    /// Samples that have been mixed but not yet taken aren't saved, since
+    /// they've already been produced.
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        state.bool(self.frame_mode == FrameMode::FiveStep);
+        state.u32(self.frame_cycle);
+        state.bool(self.frame_irq);
+        state.bool(self.irq_inhibit);
+        state.u64(self.cycle);
+        state.u32(self.sample_rate);
+        state.u64(self.sample_clock);
+        state.f32(self.sample_sum);
+        state.u32(self.sample_count);
+    }
+
+    /// Keeps the current sample rate, discarding the partially mixed sample if
+    /// the state was saved at a different rate.
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.frame_mode = if state.bool()? {
+            FrameMode::FiveStep
+        } else {
+            FrameMode::FourStep
+        };
+        self.frame_cycle = state.u32()?;
+        self.frame_irq = state.bool()?;
+        self.irq_inhibit = state.bool()?;
+        self.cycle = state.u64()?;
+        let sample_rate = state.u32()?;
+        self.sample_clock = state.u64()?;
+        self.sample_sum = state.f32()?;
+        self.sample_count = state.u32()?;
+
+        if sample_rate != self.sample_rate {
+            self.set_sample_rate(self.sample_rate);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(apu: &mut Apu, cycles: u32) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_status_length_counters() {
+        let mut apu = Apu::default();
+        apu.write(STATUS, 0x0f);
+        apu.write(0x4003, 0x08);
+        apu.write(0x4007, 0x08);
+        apu.write(0x400b, 0x08);
+        apu.write(0x400f, 0x08);
+
+        assert_eq!(apu.read_status() & 0x0f, 0x0f);
+
+        apu.write(STATUS, 0x05);
+        assert_eq!(apu.read_status() & 0x0f, 0x05);
+    }
+
+    #[test]
+    fn test_length_counter_ignored_while_disabled() {
+        let mut apu = Apu::default();
+        apu.write(0x4003, 0x08);
+
+        assert_eq!(apu.read_status() & 0x01, 0);
+    }
+
+    #[test]
+    fn test_length_counter_expires() {
+        let mut apu = Apu::default();
+        apu.write(STATUS, 0x01);
+        apu.write(0x4003, 0x18); // length index 3, a length of 2
+
+        run(&mut apu, 14913);
+        assert_eq!(apu.read_status() & 0x01, 0x01);
+
+        run(&mut apu, 29829 - 14913);
+        assert_eq!(apu.read_status() & 0x01, 0);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = Apu::default();
+
+        run(&mut apu, 29827);
+        assert!(!apu.irq());
+
+        run(&mut apu, 1);
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_frame_irq_inhibit() {
+        let mut apu = Apu::default();
+        apu.write(FRAME_COUNTER, 0x40);
+
+        run(&mut apu, 29830 * 2);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_five_step_mode() {
+        let mut apu = Apu::default();
+        apu.write(FRAME_COUNTER, 0x80);
+
+        run(&mut apu, 37282 * 2);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = Apu::new(48_000);
+        run(&mut apu, CPU_FREQUENCY / 10);
+
+        // 1/10th of a second, less the fraction of a sample that hasn't been
+        // completed yet.
+        assert_eq!(apu.take_samples().len(), 4799);
+        assert!(apu.samples().is_empty());
+    }
+
+    #[test]
+    fn test_pulse_output() {
+        let mut apu = Apu::default();
+        apu.write(STATUS, 0x01);
+        apu.write(0x4000, 0b1011_1111); // 50% duty, constant volume 15, halted
+        apu.write(0x4002, 0xfd); // ~440 Hz
+        apu.write(0x4003, 0x08);
+
+        run(&mut apu, CPU_FREQUENCY / 100);
+        let samples = apu.take_samples();
+
+        let max = samples.iter().cloned().fold(0.0, f32::max);
+        let min = samples.iter().cloned().fold(1.0, f32::min);
+        assert!((max - min - 95.88 / (8128.0 / 15.0 + 100.0)).abs() < 0.001);
+    }
+}
@@ -0,0 +1,141 @@
+use std::io;
+
+use super::{envelope::Envelope, length_counter::LengthCounter};
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles, indexed by the lower four bits of $400E.
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// Pseudo-random noise channel driven by a 15-bit linear feedback shift
+/// register.
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    shift_register: u16,
+    short_mode: bool,
+    timer: u16,
+    timer_period: u16,
+    pub length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    /// The shift register is loaded with 1 on power-up.
+    fn default() -> Self {
+        Self {
+            shift_register: 1,
+            short_mode: false,
+            timer: 0,
+            timer_period: PERIOD_TABLE[0],
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// Write to one of the channel's four registers, numbered 0 to 3.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                self.timer_period = PERIOD_TABLE[usize::from(value & 0x0f)];
+            }
+            3 => {
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+            }
+            _ => unreachable!("the noise channel has four registers"),
+        }
+    }
+
+    /// Clock the timer, shifting the feedback register when it reaches zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_length(&mut self) {
+        self.length.clock();
+    }
+
+    /// The current output level, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length.active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl SaveState for Noise {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.shift_register);
+        state.bool(self.short_mode);
+        state.u16(self.timer);
+        state.u16(self.timer_period);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.shift_register = state.u16()?;
+        self.short_mode = state.bool()?;
+        self.timer = state.u16()?;
+        self.timer_period = state.u16()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shift_register_sequence() {
+        let mut noise = Noise::default();
+
+        noise.clock_timer();
+        assert_eq!(noise.shift_register, 0b100_0000_0000_0000);
+
+        for _ in 0..4 {
+            noise.clock_timer();
+        }
+        assert_eq!(noise.shift_register, 0b010_0000_0000_0000);
+    }
+
+    #[test]
+    fn test_long_mode_period() {
+        let mut noise = Noise::default();
+        let mut seen = std::collections::HashSet::new();
+
+        for _ in 0..32767 {
+            seen.insert(noise.shift_register);
+            noise.timer = 0;
+            noise.clock_timer();
+        }
+
+        assert_eq!(seen.len(), 32767);
+        assert_eq!(noise.shift_register, 1);
+    }
+}
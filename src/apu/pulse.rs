@@ -0,0 +1,230 @@
+use std::io;
+
+use super::{envelope::Envelope, length_counter::LengthCounter};
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Waveform sequences for each of the four duty cycles.
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Which of the two pulse channels this is, which determines how the sweep
+/// unit negates its change amount.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PulseChannel {
+    /// Pulse 1 negates using ones' complement, subtracting an extra 1.
+    #[default]
+    One,
+    /// Pulse 2 negates using two's complement.
+    Two,
+}
+
+/// Square wave channel with a volume envelope and a frequency sweep unit.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pulse {
+    channel: PulseChannel,
+    duty: u8,
+    step: u8,
+    timer: u16,
+    timer_period: u16,
+    pub length: LengthCounter,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            ..Default::default()
+        }
+    }
+
+    /// Write to one of the channel's four registers, numbered 0 to 3.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(value & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+            _ => unreachable!("pulse channels have four registers"),
+        }
+    }
+
+    /// Clock the timer, advancing the duty sequence when it reaches zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_length(&mut self) {
+        self.length.clock();
+    }
+
+    /// Clock the sweep unit, updating the timer period when its divider
+    /// expires.
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.target_period();
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    /// The current output level, from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if self.muted()
+            || !self.length.active()
+            || DUTY_TABLE[usize::from(self.duty)][usize::from(self.step)] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    /// The period the sweep unit would set the timer to.
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+
+        if self.sweep_negate {
+            let borrow = u16::from(self.channel == PulseChannel::One);
+            self.timer_period.saturating_sub(change + borrow)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// Whether the channel is silenced by a period that is too low or a sweep
+    /// target that is too high.
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x07ff
+    }
+}
+
+impl SaveState for Pulse {
+    /// The channel number is fixed when the pulse is created, so it isn't
+    /// saved.
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.duty);
+        state.u8(self.step);
+        state.u16(self.timer);
+        state.u16(self.timer_period);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.bool(self.sweep_enabled);
+        state.u8(self.sweep_period);
+        state.bool(self.sweep_negate);
+        state.u8(self.sweep_shift);
+        state.bool(self.sweep_reload);
+        state.u8(self.sweep_divider);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.duty = state.u8()?;
+        self.step = state.u8()?;
+        self.timer = state.u16()?;
+        self.timer_period = state.u16()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.sweep_enabled = state.bool()?;
+        self.sweep_period = state.u8()?;
+        self.sweep_negate = state.bool()?;
+        self.sweep_shift = state.u8()?;
+        self.sweep_reload = state.bool()?;
+        self.sweep_divider = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn enabled_pulse(channel: PulseChannel) -> Pulse {
+        let mut pulse = Pulse::new(channel);
+        pulse.length.set_enabled(true);
+        pulse
+    }
+
+    #[test]
+    fn test_output_follows_duty() {
+        let mut pulse = enabled_pulse(PulseChannel::One);
+        pulse.write(0, 0b1001_1111); // 50% duty, constant volume 15
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01);
+
+        let mut outputs = vec![];
+        for _ in 0..8 {
+            outputs.push(pulse.output());
+            for _ in 0..=pulse.timer_period {
+                pulse.clock_timer();
+            }
+        }
+
+        assert_eq!(outputs.iter().filter(|&&o| o == 15).count(), 4);
+        assert_eq!(outputs.iter().filter(|&&o| o == 0).count(), 4);
+    }
+
+    #[test]
+    fn test_muted_below_minimum_period() {
+        let mut pulse = enabled_pulse(PulseChannel::One);
+        pulse.write(0, 0b1101_1111);
+        pulse.write(2, 0x07);
+        pulse.write(3, 0x00);
+
+        assert!(pulse.muted());
+    }
+
+    #[test]
+    fn test_sweep_negate() {
+        let mut pulse1 = enabled_pulse(PulseChannel::One);
+        let mut pulse2 = enabled_pulse(PulseChannel::Two);
+
+        for pulse in [&mut pulse1, &mut pulse2] {
+            pulse.write(1, 0b1000_1001); // enabled, period 0, negate, shift 1
+            pulse.write(2, 0x00);
+            pulse.write(3, 0x01);
+            pulse.clock_sweep();
+        }
+
+        assert_eq!(pulse1.timer_period, 0x0100 - 0x0080 - 1);
+        assert_eq!(pulse2.timer_period, 0x0100 - 0x0080);
+    }
+}
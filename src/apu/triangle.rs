@@ -0,0 +1,130 @@
+use std::io;
+
+use super::length_counter::LengthCounter;
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// The 32-step triangle waveform.
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// Triangle wave channel, gated by both a length counter and a linear counter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Triangle {
+    step: u8,
+    timer: u16,
+    timer_period: u16,
+    pub length: LengthCounter,
+    control: bool,
+    linear_counter: u8,
+    linear_reload_value: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    /// Write to one of the channel's four registers, numbered 0 to 3.
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0x7f;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(value & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.linear_reload = true;
+            }
+            _ => unreachable!("the triangle channel has four registers"),
+        }
+    }
+
+    /// Clock the timer, advancing the waveform when it reaches zero and both
+    /// counters are non-zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clock the linear counter, reloading it if the reload flag is set.
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        self.length.clock();
+    }
+
+    /// The current output level, from 0 to 15. Halting the sequencer holds the
+    /// last level rather than silencing the channel.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[usize::from(self.step)]
+    }
+}
+
+impl SaveState for Triangle {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.step);
+        state.u16(self.timer);
+        state.u16(self.timer_period);
+        self.length.save_state(state);
+        state.bool(self.control);
+        state.u8(self.linear_counter);
+        state.u8(self.linear_reload_value);
+        state.bool(self.linear_reload);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.step = state.u8()?;
+        self.timer = state.u16()?;
+        self.timer_period = state.u16()?;
+        self.length.load_state(state)?;
+        self.control = state.bool()?;
+        self.linear_counter = state.u8()?;
+        self.linear_reload_value = state.u8()?;
+        self.linear_reload = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_linear_counter_gates_sequencer() {
+        let mut triangle = Triangle::default();
+        triangle.length.set_enabled(true);
+        triangle.write(0, 0x00); // linear counter reload value of 0
+        triangle.write(3, 0x08);
+        triangle.clock_linear();
+
+        triangle.clock_timer();
+        assert_eq!(triangle.step, 0);
+
+        triangle.write(0, 0x7f);
+        triangle.write(3, 0x08);
+        triangle.clock_linear();
+
+        triangle.clock_timer();
+        assert_eq!(triangle.step, 1);
+    }
+}
@@ -0,0 +1,220 @@
+//! Battery-backed cartridge RAM, kept in a `.sav` file next to the ROM so that
+//! games saved on the cartridge persist between runs.
+//!
+//! The file holds the raw contents of the RAM, e.g. 8 KiB for the PRG-RAM at
+//! $6000-$7FFF, in the same format as other emulators' `.sav` files.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::cpu::{CPU, CYCLES_PER_FRAME};
+
+/// Frames between writes of a changed `.sav` file unless another interval is
+/// configured, which is about five seconds.
+pub const DEFAULT_FLUSH_INTERVAL: u64 = 300;
+
+/// The path of the `.sav` file for a ROM, e.g. `game.sav` for `game.nes`.
+pub fn sav_path<P: AsRef<Path>>(rom: P) -> PathBuf {
+    rom.as_ref().with_extension("sav")
+}
+
+/// A copy of the cartridge's battery-backed RAM, or None if there's no
+/// cartridge or it has no battery.
+pub fn export(cpu: &CPU) -> Option<Vec<u8>> {
+    cpu.mapper
+        .as_ref()
+        .and_then(|mapper| mapper.battery_ram())
+        .map(<[u8]>::to_vec)
+}
+
+/// Replace the contents of the cartridge's battery-backed RAM, failing if
+/// there's no cartridge, it has no battery, or the size doesn't match.
+pub fn import(cpu: &mut CPU, bytes: &[u8]) -> io::Result<()> {
+    match cpu.mapper.as_mut() {
+        Some(mapper) => mapper.load_battery_ram(bytes),
+        None => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no cartridge is loaded",
+        )),
+    }
+}
+
+/// Keeps a ROM's `.sav` file in sync with the cartridge's battery-backed RAM.
+#[derive(Debug)]
+pub struct Battery {
+    path: PathBuf,
+    interval: u64,
+    /// The contents of the file as last read or written.
+    saved: Vec<u8>,
+    /// The frame the RAM was last checked for changes on.
+    checked_frame: u64,
+}
+
+impl Battery {
+    /// Load the `.sav` file for a ROM into the cartridge's battery-backed RAM
+    /// if the file exists, and write any changes to it every `interval` frames
+    /// after. Returns None if the cartridge has no battery.
+    pub fn open<P: AsRef<Path>>(cpu: &mut CPU, rom: P, interval: u64) -> io::Result<Option<Self>> {
+        let Some(ram) = export(cpu) else {
+            return Ok(None);
+        };
+
+        let path = sav_path(rom);
+        let error = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+
+        let saved = match fs::read(&path) {
+            Ok(bytes) => {
+                import(cpu, &bytes).map_err(error)?;
+                bytes
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ram,
+            Err(e) => return Err(error(e)),
+        };
+
+        Ok(Some(Self {
+            path,
+            interval: interval.max(1),
+            saved,
+            checked_frame: cpu.cycles / CYCLES_PER_FRAME,
+        }))
+    }
+
+    /// The path of the `.sav` file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the RAM to the file if the interval has passed since it was last
+    /// checked and it has changed. Call this at least once a frame. Returns
+    /// whether the file was written.
+    pub fn update(&mut self, cpu: &CPU) -> io::Result<bool> {
+        let frame = cpu.cycles / CYCLES_PER_FRAME;
+
+        if frame < self.checked_frame + self.interval && frame >= self.checked_frame {
+            return Ok(false);
+        }
+
+        self.checked_frame = frame;
+        self.flush(cpu)
+    }
+
+    /// Write the RAM to the file now if it has changed since it was last read
+    /// or written. Returns whether the file was written.
+    pub fn flush(&mut self, cpu: &CPU) -> io::Result<bool> {
+        let Some(ram) = cpu.mapper.as_ref().and_then(|mapper| mapper.battery_ram()) else {
+            return Ok(false);
+        };
+
+        if ram == self.saved {
+            return Ok(false);
+        }
+
+        // Write to a temporary file first, so that the save isn't lost if the
+        // write is interrupted.
+        let temp = self.path.with_extension("sav.tmp");
+        fs::write(&temp, ram)
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e)))?;
+
+        self.saved = ram.to_vec();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::{Cartridge, Mirroring, PRG_RAM_SIZE};
+
+    /// A CPU with an NROM cartridge, with a battery if `battery` is set.
+    fn cpu(battery: bool) -> CPU {
+        let cartridge = Cartridge {
+            prg_rom: vec![0; 0x4000],
+            chr_rom: Vec::new(),
+            mapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery,
+        };
+
+        let mut cpu = CPU::new();
+        cpu.mapper = Some(cartridge.create_mapper().unwrap());
+        cpu
+    }
+
+    /// A path to a ROM in a new temporary directory for the test.
+    fn rom_path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nes799_test_{}", test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("game.nes")
+    }
+
+    #[test]
+    fn test_export_import() {
+        let mut cpu = cpu(true);
+        let mut ram = vec![0; PRG_RAM_SIZE];
+        ram[0x10] = 0x42;
+
+        import(&mut cpu, &ram).unwrap();
+        assert_eq!(cpu.peek(0x6010), 0x42);
+        assert_eq!(export(&cpu), Some(ram.clone()));
+
+        assert_eq!(export(&self::cpu(false)), None);
+        assert!(import(&mut self::cpu(false), &ram).is_err());
+        assert!(import(&mut CPU::new(), &ram).is_err());
+    }
+
+    #[test]
+    fn test_open_and_flush() {
+        let rom = rom_path("battery_flush");
+        assert_eq!(sav_path(&rom), rom.with_extension("sav"));
+        assert!(Battery::open(&mut cpu(false), &rom, 1).unwrap().is_none());
+
+        // Nothing is written until the RAM changes.
+        let mut first = cpu(true);
+        let mut battery = Battery::open(&mut first, &rom, 1).unwrap().unwrap();
+        assert!(!battery.flush(&first).unwrap());
+        assert!(!battery.path().exists());
+
+        first.mapper.as_mut().unwrap().write(0x7000, 0x99);
+        assert!(battery.flush(&first).unwrap());
+        assert!(!battery.flush(&first).unwrap());
+
+        // The next run starts with the saved RAM.
+        let mut second = cpu(true);
+        Battery::open(&mut second, &rom, 1).unwrap().unwrap();
+        assert_eq!(second.peek(0x7000), 0x99);
+
+        fs::write(battery.path(), [0; 16]).unwrap();
+        let err = Battery::open(&mut cpu(true), &rom, 1).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("battery save is 16 bytes, expected 8192"));
+
+        fs::remove_dir_all(rom.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_update() {
+        let rom = rom_path("battery_update");
+        let mut cpu = cpu(true);
+        let mut battery = Battery::open(&mut cpu, &rom, 10).unwrap().unwrap();
+
+        cpu.mapper.as_mut().unwrap().write(0x6000, 1);
+        cpu.cycles = 9 * CYCLES_PER_FRAME;
+        assert!(!battery.update(&cpu).unwrap());
+
+        cpu.cycles = 10 * CYCLES_PER_FRAME;
+        assert!(battery.update(&cpu).unwrap());
+        assert_eq!(fs::read(battery.path()).unwrap()[0], 1);
+
+        // A reset starts the interval over.
+        cpu.mapper.as_mut().unwrap().write(0x6000, 2);
+        cpu.cycles = 0;
+        assert!(battery.update(&cpu).unwrap());
+
+        fs::remove_dir_all(rom.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+
+use crate::cpu::{self, CPU, CYCLES_PER_FRAME};
+
+/// Address of the status byte, which holds [`RUNNING`] while the test runs,
+/// then the result code.
+pub const STATUS: u16 = 0x6000;
+/// Address of the signature marking the status and message as valid.
+pub const SIGNATURE_ADDR: u16 = 0x6001;
+pub const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+/// Address of the null-terminated message describing the result.
+pub const MESSAGE: u16 = 0x6004;
+
+/// Status while the test is still running.
+pub const RUNNING: u8 = 0x80;
+/// Status when the test needs the reset button to be pressed.
+pub const NEEDS_RESET: u8 = 0x81;
+/// Result code of a test that passed.
+pub const PASSED: u8 = 0x00;
+
+/// Frames to wait before pressing reset when asked to, which must be at least
+/// 100 ms.
+const RESET_DELAY_FRAMES: u64 = 10;
+
+/// How a test ROM finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The ROM reported a result code, which is [`PASSED`] if every test
+    /// passed.
+    Finished { code: u8, message: String },
+    /// The ROM hadn't reported a result after the maximum number of frames.
+    Timeout { message: String },
+    /// The CPU stopped on an error before the ROM reported a result.
+    Crashed { error: cpu::Error, message: String },
+}
+
+impl Outcome {
+    /// Whether the ROM reported that every test passed.
+    pub fn passed(&self) -> bool {
+        matches!(self, Self::Finished { code: PASSED, .. })
+    }
+
+    /// The message the ROM had written when it stopped.
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Finished { message, .. }
+            | Self::Timeout { message }
+            | Self::Crashed { message, .. } => message,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Finished { code: PASSED, .. } => write!(f, "passed")?,
+            Self::Finished { code, .. } => write!(f, "failed with code {}", code)?,
+            Self::Timeout { .. } => write!(f, "timed out")?,
+            Self::Crashed { error, .. } => write!(f, "crashed: {}", error)?,
+        }
+
+        match self.message().trim() {
+            "" => Ok(()),
+            message => write!(f, "\n{}", message),
+        }
+    }
+}
+
+/// Whether the signature has been written, so the status can be trusted.
+pub fn has_signature(cpu: &CPU) -> bool {
+    (0..3).all(|i| cpu.peek(SIGNATURE_ADDR + i) == SIGNATURE[usize::from(i)])
+}
+
+/// The current status, if the signature has been written.
+pub fn status(cpu: &CPU) -> Option<u8> {
+    has_signature(cpu).then(|| cpu.peek(STATUS))
+}
+
+/// The message written so far, up to its null terminator.
+pub fn message(cpu: &CPU) -> String {
+    let bytes: Vec<u8> = (MESSAGE..=u16::MAX)
+        .map(|addr| cpu.peek(addr))
+        .take_while(|&byte| byte != 0)
+        .collect();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Run a test ROM that's already loaded and reset, until it reports a result
+/// or the given number of frames pass. When the ROM asks for it, reset is
+/// pressed after a short delay.
+pub fn run(cpu: &mut CPU, max_frames: u64) -> Outcome {
+    let max_cycles = max_frames * CYCLES_PER_FRAME;
+    let mut elapsed = 0;
+    let mut reset_at = None;
+
+    loop {
+        // Reset clears the CPU's cycle counter, so count the cycles each
+        // instruction takes instead.
+        let start = cpu.cycles;
+        if let Err(error) = cpu.try_step() {
+            return Outcome::Crashed {
+                error,
+                message: message(cpu),
+            };
+        }
+        elapsed += cpu.cycles - start;
+
+        match status(cpu) {
+            Some(RUNNING) | None => {}
+            Some(NEEDS_RESET) => {
+                let reset =
+                    *reset_at.get_or_insert(elapsed + RESET_DELAY_FRAMES * CYCLES_PER_FRAME);
+
+                if elapsed >= reset {
+                    reset_at = None;
+                    cpu.reset();
+                }
+            }
+            Some(code) => {
+                return Outcome::Finished {
+                    code,
+                    message: message(cpu),
+                }
+            }
+        }
+
+        if elapsed >= max_cycles {
+            return Outcome::Timeout {
+                message: message(cpu),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Assemble a program that writes the signature and a message, then sets
+    /// the given status and loops forever.
+    fn report(code: u8, text: &str) -> Vec<u8> {
+        let mut program = vec![];
+
+        let mut store = |value: u8, addr: u16| {
+            let [lo, hi] = addr.to_le_bytes();
+            program.extend_from_slice(&[0xa9, value, 0x8d, lo, hi]); // LDA #value, STA addr
+        };
+
+        store(RUNNING, STATUS);
+        for (i, &byte) in SIGNATURE.iter().enumerate() {
+            store(byte, SIGNATURE_ADDR + i as u16);
+        }
+        for (i, byte) in text.bytes().chain([0]).enumerate() {
+            store(byte, MESSAGE + i as u16);
+        }
+        store(code, STATUS);
+
+        program
+    }
+
+    /// Append a jump to the instruction itself, at the given address.
+    fn trap(program: &mut Vec<u8>, origin: u16) {
+        let [lo, hi] = (origin + program.len() as u16).to_le_bytes();
+        program.extend_from_slice(&[0x4c, lo, hi]);
+    }
+
+    #[test]
+    fn test_passed() {
+        let mut program = report(PASSED, "Passed\n");
+        trap(&mut program, 0x8000);
+
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+
+        let outcome = run(&mut cpu, 1);
+        assert!(outcome.passed());
+        assert_eq!(outcome.message(), "Passed\n");
+        assert_eq!(outcome.to_string(), "passed\nPassed");
+    }
+
+    #[test]
+    fn test_failed() {
+        let mut program = report(3, "ADC #imm\nFailed #3\n");
+        trap(&mut program, 0x8000);
+
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+
+        let outcome = run(&mut cpu, 1);
+        assert!(!outcome.passed());
+        assert_eq!(
+            outcome,
+            Outcome::Finished {
+                code: 3,
+                message: "ADC #imm\nFailed #3\n".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut program = vec![];
+        trap(&mut program, 0x8000);
+
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+
+        assert_eq!(
+            run(&mut cpu, 2),
+            Outcome::Timeout {
+                message: String::new()
+            }
+        );
+        assert!(cpu.cycles >= 2 * CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn test_needs_reset() {
+        // On the first run, ask for reset. On the second, counted in $10,
+        // report that the test passed.
+        let mut program = vec![
+            0xe6, 0x10, // INC $10
+            0xa5, 0x10, // LDA $10
+            0xc9, 0x02, // CMP #$02
+            0xf0, 0x00, // BEQ passed, patched below
+        ];
+        program.extend(report(NEEDS_RESET, ""));
+        trap(&mut program, 0x8000);
+        program[7] = (program.len() - 8) as u8;
+        program.extend(report(PASSED, "Passed after reset\n"));
+        trap(&mut program, 0x8000);
+
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+
+        let outcome = run(&mut cpu, 20);
+        assert!(outcome.passed(), "{}", outcome);
+        assert_eq!(cpu.peek(0x10), 2);
+    }
+}
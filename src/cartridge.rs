@@ -0,0 +1,391 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    mapper::Mapper,
+    state::{StateReader, StateWriter},
+};
+
+/// Magic bytes at the start of every iNES file.
+pub const MAGIC: &[u8; 4] = b"NES\x1a";
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+/// Size of each unit of program ROM, as counted in the header.
+pub const PRG_BANK_SIZE: usize = 0x4000;
+/// Size of each unit of character ROM, as counted in the header.
+pub const CHR_BANK_SIZE: usize = 0x2000;
+/// Size of the PRG-RAM at $6000-$7FFF on cartridges with a battery.
+pub const PRG_RAM_SIZE: usize = 0x2000;
+
+const PRG_RAM_START: u16 = 0x6000;
+
+/// How the PPU's nametables are mirrored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+/// A cartridge parsed from an iNES ROM file.
+#[derive(Debug, Clone)]
+pub struct Cartridge {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    /// The iNES mapper number.
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+    /// Whether the cartridge has battery-backed PRG-RAM at $6000-$7FFF.
+    pub battery: bool,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Cartridge {
+    /// Load and parse the iNES file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parse an iNES file from its raw bytes.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err(invalid("not an iNES file"));
+        }
+
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+
+        let mirroring = match (flags6 & 0x08 != 0, flags6 & 0x01 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        let prg_start = HEADER_SIZE + if flags6 & 0x04 != 0 { TRAINER_SIZE } else { 0 };
+        let prg_end = prg_start + usize::from(bytes[4]) * PRG_BANK_SIZE;
+        let chr_end = prg_end + usize::from(bytes[5]) * CHR_BANK_SIZE;
+
+        if bytes.len() < chr_end {
+            return Err(invalid("iNES file is shorter than its header describes"));
+        }
+
+        Ok(Self {
+            prg_rom: bytes[prg_start..prg_end].to_vec(),
+            chr_rom: bytes[prg_end..chr_end].to_vec(),
+            mapper: (flags7 & 0xf0) | (flags6 >> 4),
+            mirroring,
+            battery: flags6 & 0x02 != 0,
+        })
+    }
+
+    /// Split the program ROM into its 16 KiB banks.
+    pub fn prg_banks(&self) -> std::slice::Chunks<'_, u8> {
+        self.prg_rom.chunks(PRG_BANK_SIZE)
+    }
+
+    /// The address that the given bank of program ROM is most likely to be
+    /// mapped at. A single bank is mirrored at $C000, and otherwise the last
+    /// bank is usually fixed at $C000 while the rest are switched in at $8000.
+    pub fn prg_bank_origin(&self, bank: usize) -> u16 {
+        if bank + 1 >= self.prg_banks().len() {
+            0xc000
+        } else {
+            0x8000
+        }
+    }
+
+    /// Create the mapper that the cartridge uses to map its program ROM into
+    /// the CPU's address space.
+    pub fn create_mapper(&self) -> io::Result<Box<dyn Mapper>> {
+        match self.mapper {
+            0 => Ok(Box::new(Nrom::new(self)?)),
+            n => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("mapper {} is not supported", n),
+            )),
+        }
+    }
+}
+
+/// Mapper 0, which maps either 16 KiB of program ROM mirrored at $8000 and
+/// $C000, or 32 KiB across all of $8000-$FFFF, plus 8 KiB of PRG-RAM at
+/// $6000-$7FFF if the cartridge has a battery.
+#[derive(Debug, Clone)]
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    /// Battery-backed PRG-RAM. Without a battery, $6000-$7FFF is left to plain
+    /// memory.
+    prg_ram: Option<Vec<u8>>,
+}
+
+impl Nrom {
+    pub fn new(cartridge: &Cartridge) -> io::Result<Self> {
+        match cartridge.prg_rom.len() {
+            PRG_BANK_SIZE | 0x8000 => Ok(Self {
+                prg_rom: cartridge.prg_rom.clone(),
+                prg_ram: cartridge.battery.then(|| vec![0; PRG_RAM_SIZE]),
+            }),
+            _ => Err(invalid("NROM program ROM must be 16 or 32 KiB")),
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_RAM_START..0x8000 => self
+                .prg_ram
+                .as_ref()
+                .map(|ram| ram[usize::from(addr - PRG_RAM_START)]),
+            0x8000.. => {
+                let offset = usize::from(addr - 0x8000) % self.prg_rom.len();
+                Some(self.prg_rom[offset])
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            PRG_RAM_START..0x8000 => match &mut self.prg_ram {
+                Some(ram) => {
+                    ram[usize::from(addr - PRG_RAM_START)] = value;
+                    true
+                }
+                None => false,
+            },
+            // Program ROM is read-only.
+            0x8000.. => true,
+            _ => false,
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        if let Some(ram) = &self.prg_ram {
+            state.bytes(ram);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        // Before version 2, PRG-RAM was saved as part of CPU memory, which the
+        // CPU copies over.
+        if let Some(ram) = self.prg_ram.as_mut().filter(|_| state.version() >= 2) {
+            ram.copy_from_slice(state.bytes(PRG_RAM_SIZE)?);
+        }
+
+        Ok(())
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.prg_ram.as_deref()
+    }
+
+    fn load_battery_ram(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(ram) = &mut self.prg_ram else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the cartridge has no battery-backed RAM",
+            ));
+        };
+
+        if bytes.len() != ram.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "battery save is {} bytes, expected {}",
+                    bytes.len(),
+                    ram.len()
+                ),
+            ));
+        }
+
+        ram.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::CPU;
+
+    /// Build an iNES file with the given flags and banks of program ROM, each
+    /// filled with its bank number, and ending in a reset vector of $C000.
+    fn build_ines(prg_banks: u8, flags6: u8, flags7: u8) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(MAGIC);
+        bytes[4] = prg_banks;
+        bytes[5] = 1;
+        bytes[6] = flags6;
+        bytes[7] = flags7;
+
+        if flags6 & 0x04 != 0 {
+            bytes.extend_from_slice(&[0xff; TRAINER_SIZE]);
+        }
+
+        for bank in 0..prg_banks {
+            let start = bytes.len();
+            bytes.resize(start + PRG_BANK_SIZE, bank);
+            bytes[start + PRG_BANK_SIZE - 4..start + PRG_BANK_SIZE - 2]
+                .copy_from_slice(&[0x00, 0xc0]);
+        }
+
+        bytes.resize(bytes.len() + CHR_BANK_SIZE, 0xcc);
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let cartridge = Cartridge::parse(&build_ines(2, 0x13, 0x40)).unwrap();
+
+        assert_eq!(cartridge.prg_rom.len(), 2 * PRG_BANK_SIZE);
+        assert_eq!(cartridge.chr_rom, vec![0xcc; CHR_BANK_SIZE]);
+        assert_eq!(cartridge.mapper, 0x41);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert!(cartridge.battery);
+    }
+
+    #[test]
+    fn test_parse_trainer() {
+        let cartridge = Cartridge::parse(&build_ines(1, 0x0c, 0x00)).unwrap();
+
+        assert_eq!(cartridge.mirroring, Mirroring::FourScreen);
+        assert_eq!(
+            cartridge.prg_rom,
+            build_ines(1, 0, 0)[16..16 + PRG_BANK_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Cartridge::parse(b"NES").is_err());
+        assert!(Cartridge::parse(&build_ines(2, 0, 0)[..PRG_BANK_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_prg_banks() {
+        let cartridge = Cartridge::parse(&build_ines(3, 0, 0)).unwrap();
+        let banks: Vec<_> = cartridge.prg_banks().collect();
+
+        assert_eq!(banks.len(), 3);
+        assert!(banks[2][..PRG_BANK_SIZE - 4].iter().all(|&b| b == 2));
+        assert_eq!(cartridge.prg_bank_origin(0), 0x8000);
+        assert_eq!(cartridge.prg_bank_origin(1), 0x8000);
+        assert_eq!(cartridge.prg_bank_origin(2), 0xc000);
+
+        let cartridge = Cartridge::parse(&build_ines(1, 0, 0)).unwrap();
+        assert_eq!(cartridge.prg_bank_origin(0), 0xc000);
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        let cartridge = Cartridge::parse(&build_ines(1, 0x10, 0x00)).unwrap();
+        let err = cartridge.create_mapper().unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_nrom_mirroring() {
+        let cartridge = Cartridge::parse(&build_ines(1, 0, 0)).unwrap();
+        let mut mapper = cartridge.create_mapper().unwrap();
+
+        assert_eq!(mapper.read(0x7fff), None);
+        assert_eq!(mapper.read(0x8000), mapper.read(0xc000));
+        assert!(mapper.write(0x8000, 0x42));
+        assert!(!mapper.write(0x6000, 0x42));
+    }
+
+    #[test]
+    fn test_nrom_battery_ram() {
+        let cartridge = Cartridge::parse(&build_ines(1, 0x02, 0)).unwrap();
+        let mut mapper = cartridge.create_mapper().unwrap();
+
+        assert!(mapper.write(0x6001, 0x42));
+        assert_eq!(mapper.read(0x6001), Some(0x42));
+        assert_eq!(mapper.read(0x5fff), None);
+        assert_eq!(mapper.battery_ram().unwrap()[1], 0x42);
+
+        let mut ram = vec![0; PRG_RAM_SIZE];
+        ram[PRG_RAM_SIZE - 1] = 0x99;
+        mapper.load_battery_ram(&ram).unwrap();
+        assert_eq!(mapper.read(0x7fff), Some(0x99));
+        assert_eq!(mapper.read(0x6001), Some(0));
+
+        let err = mapper.load_battery_ram(&ram[1..]).unwrap_err();
+        assert_eq!(err.to_string(), "battery save is 8191 bytes, expected 8192");
+
+        let cartridge = Cartridge::parse(&build_ines(1, 0, 0)).unwrap();
+        let mut mapper = cartridge.create_mapper().unwrap();
+        assert_eq!(mapper.battery_ram(), None);
+        assert!(mapper.load_battery_ram(&ram).is_err());
+    }
+
+    #[test]
+    fn test_reset_from_cartridge() {
+        let cartridge = Cartridge::parse(&build_ines(2, 0, 0)).unwrap();
+
+        let mut cpu = CPU::new();
+        cpu.mapper = Some(cartridge.create_mapper().unwrap());
+        cpu.reset();
+
+        assert_eq!(cpu.program_counter, 0xc000);
+        assert_eq!(cpu.peek(0x8000), 0);
+        assert_eq!(cpu.peek(0xc000), 1);
+    }
+
+    #[test]
+    fn test_battery_ram_save_state() {
+        let cpu = |flags6| {
+            let cartridge = Cartridge::parse(&build_ines(1, flags6, 0)).unwrap();
+            let mut cpu = CPU::new();
+            cpu.mapper = Some(cartridge.create_mapper().unwrap());
+            cpu
+        };
+
+        let mut battery = cpu(0x02);
+        battery.mapper.as_mut().unwrap().write(0x6000, 0x42);
+        let state = battery.save_state();
+        battery.mapper.as_mut().unwrap().write(0x6000, 0);
+        battery.load_state(&state).unwrap();
+        assert_eq!(battery.peek(0x6000), 0x42);
+
+        // Version 1 states kept PRG-RAM in CPU memory.
+        let mut without_battery = cpu(0);
+        without_battery.poke(0x7fff, 0x99);
+        let mut old = without_battery.save_state();
+        old[crate::state::MAGIC.len()..][..2].copy_from_slice(&1u16.to_le_bytes());
+
+        battery.load_state(&old).unwrap();
+        assert_eq!(battery.peek(0x6000), 0);
+        assert_eq!(battery.peek(0x7fff), 0x99);
+        assert_eq!(
+            battery.mapper.as_ref().unwrap().battery_ram().unwrap()[0x1fff],
+            0x99
+        );
+    }
+
+    #[test]
+    fn test_invalid_mapper_state_leaves_cartridge_unchanged() {
+        let cartridge = Cartridge::parse(&build_ines(1, 0x02, 0)).unwrap();
+        let mut cpu = CPU::new();
+        cpu.mapper = Some(cartridge.create_mapper().unwrap());
+
+        cpu.mapper.as_mut().unwrap().write(0x6000, 0x99);
+        let mut state = cpu.save_state();
+        cpu.mapper.as_mut().unwrap().write(0x6000, 0x42);
+
+        // Add a byte to the end of the mapper's state, which comes last.
+        let len_pos = state.len() - PRG_RAM_SIZE - 4;
+        let len = u32::from_le_bytes(state[len_pos..len_pos + 4].try_into().unwrap());
+        state[len_pos..len_pos + 4].copy_from_slice(&(len + 1).to_le_bytes());
+        state.push(0);
+
+        assert!(cpu.load_state(&state).is_err());
+        assert_eq!(cpu.peek(0x6000), 0x42);
+    }
+}
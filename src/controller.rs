@@ -0,0 +1,169 @@
+use std::io;
+
+use bitmask_enum::bitmask;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
+/// Address of the controller strobe (write) and controller 1 data (read)
+/// register.
+pub const JOYPAD1: u16 = 0x4016;
+/// Address of the controller 2 data register. Writes go to the APU frame
+/// counter instead.
+pub const JOYPAD2: u16 = 0x4017;
+
+/// Upper bits of a controller read, which aren't driven by the controller and
+/// so hold the high byte of the address left on the data bus.
+const OPEN_BUS: u8 = 0x40;
+
+/// Buttons on a standard controller, in the order they are reported.
+#[bitmask(u8)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Default for Button {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl Button {
+    /// Each button with its lowercase name, in the order they are reported.
+    pub const NAMES: [(Button, &'static str); 8] = [
+        (Self::A, "a"),
+        (Self::B, "b"),
+        (Self::Select, "select"),
+        (Self::Start, "start"),
+        (Self::Up, "up"),
+        (Self::Down, "down"),
+        (Self::Left, "left"),
+        (Self::Right, "right"),
+    ];
+}
+
+/// A standard controller, which reports its buttons one at a time through a
+/// shift register that is reloaded while the strobe is high.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Controller {
+    buttons: Button,
+    shift_register: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    /// The buttons currently held down.
+    pub fn buttons(&self) -> Button {
+        self.buttons
+    }
+
+    /// Replace the set of buttons held down.
+    pub fn set_buttons(&mut self, buttons: Button) {
+        self.buttons = buttons;
+    }
+
+    /// Hold down the given buttons.
+    pub fn press(&mut self, buttons: Button) {
+        self.buttons |= buttons;
+    }
+
+    /// Let go of the given buttons.
+    pub fn release(&mut self, buttons: Button) {
+        self.buttons &= buttons.not();
+    }
+
+    /// Set the strobe from bit 0 of a write to $4016, latching the buttons into
+    /// the shift register.
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 == 1;
+
+        if self.strobe {
+            self.shift_register = self.buttons.bits();
+        }
+    }
+
+    /// Read the next button from the shift register. Once all eight have been
+    /// read, a standard controller reports 1 for every subsequent read.
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.shift_register = self.buttons.bits();
+        }
+
+        let bit = self.shift_register & 1;
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+
+        OPEN_BUS | bit
+    }
+}
+
+impl SaveState for Controller {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.buttons.bits());
+        state.u8(self.shift_register);
+        state.bool(self.strobe);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.buttons = Button::from(state.u8()?);
+        self.shift_register = state.u8()?;
+        self.strobe = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(controller: &mut Controller) -> Vec<u8> {
+        (0..10).map(|_| controller.read() & 1).collect()
+    }
+
+    #[test]
+    fn test_serial_read() {
+        let mut controller = Controller::default();
+        controller.press(Button::A | Button::Start | Button::Right);
+        controller.write(1);
+        controller.write(0);
+
+        assert_eq!(
+            read_all(&mut controller),
+            vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_strobe_high_reports_a() {
+        let mut controller = Controller::default();
+        controller.write(1);
+
+        controller.press(Button::A);
+        assert_eq!(controller.read() & 1, 1);
+        assert_eq!(controller.read() & 1, 1);
+
+        controller.release(Button::A);
+        assert_eq!(controller.read() & 1, 0);
+    }
+
+    #[test]
+    fn test_latched_until_strobe() {
+        let mut controller = Controller::default();
+        controller.write(1);
+        controller.write(0);
+        controller.press(Button::A);
+
+        assert_eq!(controller.read() & 1, 0);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut controller = Controller::default();
+        assert_eq!(controller.read() & 0xe0, OPEN_BUS);
+    }
+}
@@ -0,0 +1,1003 @@
+use std::{collections::HashMap, error, fmt};
+
+use super::{
+    instructions::Instruction,
+    opcodes::{AddressingMode, OpCode, CPU_OPS_CODES},
+};
+
+/// A program assembled from source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// The address of the first byte.
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    /// The address of every label and the value of every constant, with local
+    /// labels named after the label they belong to, e.g. `loop@next`.
+    pub symbols: HashMap<String, u16>,
+}
+
+/// An error in a line of source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// The line the error is on, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+/// Assemble a program from source, starting at the given address unless the
+/// source begins with `.org`.
+///
+/// Each line may have a label, followed by an instruction or directive, and a
+/// comment after `;`. Labels end in `:`, and those starting with `@` are local
+/// to the label before them. Constants are defined with `name = expression`.
+/// The directives are `.org`, `.byte`, which also takes strings, and `.word`.
+///
+/// Operands use standard syntax, e.g. `#$nn`, `$nnnn,X`, or `($nn),Y`, and
+/// zero page addressing is used whenever the address is known to be in zero
+/// page by the time the instruction is reached. Expressions may contain
+/// decimal, `$` hex, `%` binary, and `'c'` character numbers, symbols, `*` for
+/// the current address, the `+ - * / & | ^` operators, parentheses, and the
+/// unary `-`, `~`, and `<`/`>` low and high byte operators.
+pub fn assemble(source: &str, origin: u16) -> Result<Program, Error> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| {
+            Line::parse(text).map_err(|message| Error {
+                line: i + 1,
+                message,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut assembler = Assembler {
+        origin,
+        ..Default::default()
+    };
+
+    assembler.pass(&lines, Pass::First)?;
+    assembler.pass(&lines, Pass::Second)?;
+
+    Ok(Program {
+        origin: assembler.origin,
+        bytes: assembler.bytes,
+        symbols: assembler.symbols,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    /// Define labels and choose an opcode for each instruction, allowing
+    /// symbols that haven't been defined yet.
+    First,
+    /// Emit bytes, now that every symbol is defined.
+    Second,
+}
+
+/// A line of source, split into its parts.
+#[derive(Debug)]
+struct Line<'a> {
+    label: Option<&'a str>,
+    statement: Option<Statement<'a>>,
+}
+
+#[derive(Debug)]
+enum Statement<'a> {
+    Constant(&'a str, &'a str),
+    Org(&'a str),
+    Byte(Vec<&'a str>),
+    Word(Vec<&'a str>),
+    Instruction(&'a str, Operand<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+/// The operand of an instruction, with its expressions still unevaluated.
+#[derive(Debug)]
+enum Operand<'a> {
+    Implied,
+    Accumulator,
+    Immediate(&'a str),
+    Direct(&'a str, Index),
+    Indirect(&'a str),
+    IndirectX(&'a str),
+    IndirectY(&'a str),
+}
+
+impl<'a> Line<'a> {
+    fn parse(text: &'a str) -> Result<Self, String> {
+        let mut text = strip_comment(text).trim();
+        let mut label = None;
+
+        if let Some((name, rest)) = text.split_once(':') {
+            if is_symbol(name) {
+                label = Some(name);
+                text = rest.trim();
+            }
+        }
+
+        if text.is_empty() {
+            return Ok(Self {
+                label,
+                statement: None,
+            });
+        }
+
+        if let Some((name, expr)) = text.split_once('=') {
+            let name = name.trim();
+            if is_symbol(name) && !name.starts_with('@') {
+                return Ok(Self {
+                    label,
+                    statement: Some(Statement::Constant(name, expr.trim())),
+                });
+            }
+        }
+
+        let (word, rest) = text
+            .split_once(char::is_whitespace)
+            .map_or((text, ""), |(word, rest)| (word, rest.trim()));
+
+        let statement = match word.to_ascii_lowercase().as_str() {
+            ".org" => Statement::Org(rest),
+            ".byte" => Statement::Byte(split_list(rest)?),
+            ".word" => Statement::Word(split_list(rest)?),
+            directive if directive.starts_with('.') => {
+                return Err(format!("unknown directive '{}'", word))
+            }
+            _ => Statement::Instruction(word, Operand::parse(rest)),
+        };
+
+        Ok(Self {
+            label,
+            statement: Some(statement),
+        })
+    }
+}
+
+impl<'a> Operand<'a> {
+    fn parse(text: &'a str) -> Self {
+        if text.is_empty() {
+            return Self::Implied;
+        }
+
+        if text.eq_ignore_ascii_case("a") {
+            return Self::Accumulator;
+        }
+
+        if let Some(expr) = text.strip_prefix('#') {
+            return Self::Immediate(expr.trim());
+        }
+
+        if let Some(inner) = enclosed(text) {
+            return match split_index(inner) {
+                (base, Index::X) => Self::IndirectX(base),
+                _ => Self::Indirect(inner.trim()),
+            };
+        }
+
+        let (base, index) = split_index(text);
+        match (index, enclosed(base)) {
+            (Index::Y, Some(inner)) => Self::IndirectY(inner.trim()),
+            _ => Self::Direct(base, index),
+        }
+    }
+}
+
+/// Remove a comment from the end of a line, ignoring `;` in quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &text[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// Whether the text is a valid label or constant name.
+fn is_symbol(text: &str) -> bool {
+    let name = text.strip_prefix('@').unwrap_or(text);
+
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split a comma-separated list, ignoring commas in quotes.
+fn split_list(text: &str) -> Result<Vec<&str>, String> {
+    let mut items = vec![];
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ',') => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+
+    if items.iter().any(|item| item.is_empty()) {
+        return Err("expected a list of values".to_string());
+    }
+
+    Ok(items)
+}
+
+/// The text inside a pair of parentheses that enclose all of it.
+fn enclosed(text: &str) -> Option<&str> {
+    let inner = text.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    Some(inner)
+}
+
+/// Split an `,X` or `,Y` index off the end of an operand.
+fn split_index(text: &str) -> (&str, Index) {
+    match text.rsplit_once(',') {
+        Some((base, reg)) if reg.trim().eq_ignore_ascii_case("x") => (base.trim(), Index::X),
+        Some((base, reg)) if reg.trim().eq_ignore_ascii_case("y") => (base.trim(), Index::Y),
+        _ => (text.trim(), Index::None),
+    }
+}
+
+/// Find the opcode for an instruction in an addressing mode, preferring the
+/// documented one when there are several.
+fn find_opcode(mnemonic: &str, mode: AddressingMode) -> Option<&'static OpCode> {
+    CPU_OPS_CODES
+        .iter()
+        .filter(|op| op.mode == mode && op.instruction.mnemonic().eq_ignore_ascii_case(mnemonic))
+        .min_by_key(|op| op.unofficial)
+}
+
+/// Why an expression couldn't be evaluated.
+enum EvalError {
+    /// The expression uses a symbol that isn't defined, which is only an error
+    /// on the second pass.
+    Undefined(String),
+    Invalid(String),
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    origin: u16,
+    bytes: Vec<u8>,
+    symbols: HashMap<String, u16>,
+    /// The opcode chosen for each line with an instruction on the first pass.
+    opcodes: HashMap<usize, &'static OpCode>,
+    pc: u32,
+    /// The label that local labels belong to.
+    scope: String,
+    /// Whether anything has been emitted, after which `.org` can only move
+    /// forward.
+    started: bool,
+}
+
+impl Assembler {
+    fn pass(&mut self, lines: &[Line], pass: Pass) -> Result<(), Error> {
+        self.pc = u32::from(self.origin);
+        self.scope.clear();
+        self.started = false;
+
+        for (i, line) in lines.iter().enumerate() {
+            self.line(i, line, pass).map_err(|message| Error {
+                line: i + 1,
+                message,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn line(&mut self, index: usize, line: &Line, pass: Pass) -> Result<(), String> {
+        if let Some(label) = line.label {
+            if !label.starts_with('@') {
+                self.scope = label.to_string();
+            }
+
+            let name = self.symbol_name(label);
+            let pc = self.address()?;
+
+            if pass == Pass::First && self.symbols.insert(name, pc).is_some() {
+                return Err(format!("'{}' is already defined", label));
+            }
+        }
+
+        let Some(statement) = &line.statement else {
+            return Ok(());
+        };
+
+        match statement {
+            Statement::Constant(name, expr) => {
+                if let Some(value) = self.eval(expr, pass)? {
+                    let value = word(value)?;
+                    let previous = self.symbols.insert(name.to_string(), value);
+
+                    if pass == Pass::First && previous.is_some() {
+                        return Err(format!("'{}' is already defined", name));
+                    }
+                }
+            }
+            Statement::Org(expr) => {
+                let addr = self
+                    .eval(expr, Pass::Second)
+                    .map_err(|e| format!(".org must be known on the first pass: {}", e))?
+                    .map_or(Ok(0), word)?;
+
+                if !self.started {
+                    self.origin = addr;
+                } else if u32::from(addr) < self.pc {
+                    return Err(format!(".org ${:04X} is behind the current address", addr));
+                }
+
+                self.pc = u32::from(addr);
+            }
+            Statement::Byte(items) => {
+                for item in items {
+                    if let Some(text) = string(item) {
+                        self.emit(text.as_bytes(), pass)?;
+                    } else {
+                        let value = self.eval(item, pass)?.map_or(Ok(0), byte)?;
+                        self.emit(&[value], pass)?;
+                    }
+                }
+            }
+            Statement::Word(items) => {
+                for item in items {
+                    let value = self.eval(item, pass)?.map_or(Ok(0), word)?;
+                    self.emit(&value.to_le_bytes(), pass)?;
+                }
+            }
+            Statement::Instruction(mnemonic, operand) => {
+                let opcode = match pass {
+                    Pass::First => {
+                        let opcode = self.choose_opcode(mnemonic, operand)?;
+                        self.opcodes.insert(index, opcode);
+                        opcode
+                    }
+                    Pass::Second => self.opcodes[&index],
+                };
+
+                let bytes = self.encode(opcode, operand, pass)?;
+                self.emit(&bytes, pass)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The full name of a symbol, with local labels named after their scope.
+    fn symbol_name(&self, name: &str) -> String {
+        if name.starts_with('@') {
+            format!("{}{}", self.scope, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// The current address, which must still be in the address space.
+    fn address(&self) -> Result<u16, String> {
+        u16::try_from(self.pc).map_err(|_| "program extends past $FFFF".to_string())
+    }
+
+    fn emit(&mut self, bytes: &[u8], pass: Pass) -> Result<(), String> {
+        self.address()?;
+
+        if pass == Pass::Second {
+            let start = (self.pc - u32::from(self.origin)) as usize;
+            let end = start + bytes.len();
+
+            if self.bytes.len() < end {
+                self.bytes.resize(end, 0);
+            }
+            self.bytes[start..end].copy_from_slice(bytes);
+        }
+
+        self.started = true;
+        self.pc += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Evaluate an expression, which is `None` on the first pass if it uses a
+    /// symbol that isn't defined yet.
+    fn eval(&self, expr: &str, pass: Pass) -> Result<Option<i32>, String> {
+        let mut parser = ExprParser {
+            text: expr.as_bytes(),
+            pos: 0,
+            assembler: self,
+        };
+
+        match parser.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(EvalError::Undefined(_)) if pass == Pass::First => Ok(None),
+            Err(EvalError::Undefined(name)) => Err(format!("'{}' is not defined", name)),
+            Err(EvalError::Invalid(message)) => Err(message),
+        }
+    }
+
+    /// Choose the opcode for an instruction, using zero page addressing if the
+    /// operand is already known to be in zero page.
+    fn choose_opcode(&self, mnemonic: &str, operand: &Operand) -> Result<&'static OpCode, String> {
+        if !CPU_OPS_CODES
+            .iter()
+            .any(|op| op.instruction.mnemonic().eq_ignore_ascii_case(mnemonic))
+        {
+            return Err(format!("unknown instruction '{}'", mnemonic));
+        }
+
+        let unsupported = || format!("{} does not support that addressing mode", mnemonic);
+
+        let (zero_page, absolute, expr) = match *operand {
+            Operand::Implied | Operand::Accumulator => {
+                let opcode = find_opcode(mnemonic, AddressingMode::NoneAddressing);
+                let accumulator = opcode.is_some_and(|op| {
+                    matches!(
+                        op.instruction,
+                        Instruction::Asl | Instruction::Lsr | Instruction::Rol | Instruction::Ror
+                    )
+                });
+
+                return match operand {
+                    Operand::Accumulator if !accumulator => Err(unsupported()),
+                    _ => opcode.ok_or_else(unsupported),
+                };
+            }
+            Operand::Immediate(_) => {
+                return find_opcode(mnemonic, AddressingMode::Immediate).ok_or_else(unsupported)
+            }
+            Operand::IndirectX(_) => {
+                return find_opcode(mnemonic, AddressingMode::IndirectX).ok_or_else(unsupported)
+            }
+            Operand::IndirectY(_) => {
+                return find_opcode(mnemonic, AddressingMode::IndirectY).ok_or_else(unsupported)
+            }
+            Operand::Indirect(expr) => match find_opcode(mnemonic, AddressingMode::Indirect) {
+                Some(opcode) => return Ok(opcode),
+                // Parentheses around an entire expression only group it.
+                None => (AddressingMode::ZeroPage, AddressingMode::Absolute, expr),
+            },
+            Operand::Direct(expr, Index::None) => {
+                if let Some(opcode) = find_opcode(mnemonic, AddressingMode::Relative) {
+                    return Ok(opcode);
+                }
+
+                (AddressingMode::ZeroPage, AddressingMode::Absolute, expr)
+            }
+            Operand::Direct(expr, Index::X) => {
+                (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX, expr)
+            }
+            Operand::Direct(expr, Index::Y) => {
+                (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY, expr)
+            }
+        };
+
+        let in_zero_page = self
+            .eval(expr, Pass::First)?
+            .is_some_and(|value| (0..=0xff).contains(&value));
+
+        match (
+            find_opcode(mnemonic, zero_page),
+            find_opcode(mnemonic, absolute),
+        ) {
+            (Some(opcode), _) if in_zero_page => Ok(opcode),
+            (_, Some(opcode)) => Ok(opcode),
+            (Some(opcode), None) => Ok(opcode),
+            (None, None) => Err(unsupported()),
+        }
+    }
+
+    /// Encode an instruction with its operand.
+    fn encode(&self, opcode: &OpCode, operand: &Operand, pass: Pass) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![opcode.code];
+
+        let expr = match *operand {
+            Operand::Implied | Operand::Accumulator => return Ok(bytes),
+            Operand::Immediate(expr)
+            | Operand::Direct(expr, _)
+            | Operand::Indirect(expr)
+            | Operand::IndirectX(expr)
+            | Operand::IndirectY(expr) => expr,
+        };
+
+        // Leave room for the operand on the first pass, when it may not be
+        // known yet.
+        let Some(value) = self.eval(expr, pass)? else {
+            bytes.resize(usize::from(opcode.len), 0);
+            return Ok(bytes);
+        };
+
+        match opcode.mode {
+            AddressingMode::Immediate => bytes.push(byte(value)?),
+            AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY => match u8::try_from(value) {
+                Ok(addr) => bytes.push(addr),
+                Err(_) => return Err(format!("${:04X} is not in zero page", value)),
+            },
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => bytes.extend(word(value)?.to_le_bytes()),
+            AddressingMode::Relative => {
+                let offset = value - (self.pc as i32 + 2);
+
+                match i8::try_from(offset) {
+                    Ok(offset) => bytes.push(offset as u8),
+                    Err(_) if pass == Pass::First => bytes.push(0),
+                    Err(_) => return Err(format!("branch to ${:04X} is out of range", value)),
+                }
+            }
+            AddressingMode::NoneAddressing => {}
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// The contents of a double-quoted string.
+fn string(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Convert a value to a byte, allowing negative values down to -128.
+fn byte(value: i32) -> Result<u8, String> {
+    match value {
+        -0x80..=0xff => Ok(value as u8),
+        _ => Err(format!("{} does not fit in a byte", value)),
+    }
+}
+
+/// Convert a value to a word, allowing negative values down to -32768.
+fn word(value: i32) -> Result<u16, String> {
+    match value {
+        -0x8000..=0xffff => Ok(value as u16),
+        _ => Err(format!("{} does not fit in a word", value)),
+    }
+}
+
+/// A recursive descent parser that evaluates an expression.
+struct ExprParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    assembler: &'a Assembler,
+}
+
+impl ExprParser<'_> {
+    fn parse(&mut self) -> Result<i32, EvalError> {
+        let value = self.binary(0)?;
+
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            None => Ok(value),
+            Some(&c) => Err(self.invalid(&format!("unexpected '{}'", char::from(c)))),
+        }
+    }
+
+    fn invalid(&self, message: &str) -> EvalError {
+        EvalError::Invalid(format!(
+            "{} in '{}'",
+            message,
+            String::from_utf8_lossy(self.text)
+        ))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    /// Parse binary operators with at least the given precedence, from `|`
+    /// binding loosest to `*` and `/` binding tightest.
+    fn binary(&mut self, precedence: u8) -> Result<i32, EvalError> {
+        const OPERATORS: [&[u8]; 5] = [b"|", b"^", b"&", b"+-", b"*/"];
+
+        let Some(&operators) = OPERATORS.get(usize::from(precedence)) else {
+            return self.unary();
+        };
+
+        let mut value = self.binary(precedence + 1)?;
+
+        while let Some(op) = self.peek().filter(|op| operators.contains(op)) {
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+
+            value = match op {
+                b'|' => value | rhs,
+                b'^' => value ^ rhs,
+                b'&' => value & rhs,
+                b'+' => value.wrapping_add(rhs),
+                b'-' => value.wrapping_sub(rhs),
+                b'*' => value.wrapping_mul(rhs),
+                _ => value
+                    .checked_div(rhs)
+                    .ok_or_else(|| self.invalid("division by zero"))?,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i32, EvalError> {
+        let op = self.peek();
+        if matches!(op, Some(b'<' | b'>' | b'-' | b'~')) {
+            self.pos += 1;
+        }
+
+        let value = match op {
+            Some(b'<') => self.unary()? & 0xff,
+            Some(b'>') => (self.unary()? >> 8) & 0xff,
+            Some(b'-') => self.unary()?.wrapping_neg(),
+            Some(b'~') => !self.unary()? & 0xffff,
+            _ => self.primary()?,
+        };
+
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<i32, EvalError> {
+        let Some(c) = self.peek() else {
+            return Err(self.invalid("expected a value"));
+        };
+
+        match c {
+            b'(' => {
+                self.pos += 1;
+                let value = self.binary(0)?;
+
+                if self.peek() != Some(b')') {
+                    return Err(self.invalid("expected ')'"));
+                }
+                self.pos += 1;
+
+                Ok(value)
+            }
+            b'*' => {
+                self.pos += 1;
+                Ok(self.assembler.pc as i32)
+            }
+            b'\'' => match self.text.get(self.pos..self.pos + 3) {
+                Some(&[b'\'', c, b'\'']) => {
+                    self.pos += 3;
+                    Ok(i32::from(c))
+                }
+                _ => Err(self.invalid("expected a character")),
+            },
+            b'$' => {
+                self.pos += 1;
+                self.number(16)
+            }
+            b'%' => {
+                self.pos += 1;
+                self.number(2)
+            }
+            b'0'..=b'9' => self.number(10),
+            b'@' | b'_' | b'a'..=b'z' | b'A'..=b'Z' => {
+                let start = self.pos;
+                self.pos += 1;
+                while self
+                    .text
+                    .get(self.pos)
+                    .is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_')
+                {
+                    self.pos += 1;
+                }
+
+                let name = String::from_utf8_lossy(&self.text[start..self.pos]);
+                let name = self.assembler.symbol_name(&name);
+
+                match self.assembler.symbols.get(&name) {
+                    Some(&value) => Ok(i32::from(value)),
+                    None => Err(EvalError::Undefined(name)),
+                }
+            }
+            c => Err(self.invalid(&format!("unexpected '{}'", char::from(c)))),
+        }
+    }
+
+    fn number(&mut self, radix: u32) -> Result<i32, EvalError> {
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_alphanumeric)
+        {
+            self.pos += 1;
+        }
+
+        let digits = String::from_utf8_lossy(&self.text[start..self.pos]);
+        u16::from_str_radix(&digits, radix)
+            .map(i32::from)
+            .map_err(|_| self.invalid("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source, 0x8000).unwrap().bytes
+    }
+
+    fn bytes_of(source: &str) -> Result<Vec<u8>, Error> {
+        assemble(source, 0x8000).map(|program| program.bytes)
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source, 0x8000).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        assert_eq!(
+            bytes(
+                "
+                lda #$01
+                lda $10
+                lda $10,x
+                ldx $10,Y
+                lda $0200
+                lda $0200,X
+                lda $0200,Y
+                jmp ($FFFC)
+                lda ($10,X)
+                lda ($10),Y
+                asl a
+                lsr
+                inx
+                "
+            ),
+            vec![
+                0xa9, 0x01, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x00, 0x02, 0xbd, 0x00, 0x02,
+                0xb9, 0x00, 0x02, 0x6c, 0xfc, 0xff, 0xa1, 0x10, 0xb1, 0x10, 0x0a, 0x4a, 0xe8,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ldy_indexed() {
+        assert_eq!(
+            bytes("ldy $10,X\nldy $0200,X"),
+            vec![0xb4, 0x10, 0xbc, 0x00, 0x02]
+        );
+    }
+
+    /// Opcodes of each documented instruction with an operand, in the
+    /// columns of a reference card: immediate, zero page, zero page,X, zero
+    /// page,Y, absolute, absolute,X, absolute,Y, (indirect,X), and
+    /// (indirect),Y. Written out by hand so that it doesn't share mistakes with
+    /// the opcode table.
+    const REFERENCE_CARD: &str = "
+        ADC 69 65 75 -- 6D 7D 79 61 71
+        AND 29 25 35 -- 2D 3D 39 21 31
+        ASL -- 06 16 -- 0E 1E -- -- --
+        BIT -- 24 -- -- 2C -- -- -- --
+        CMP C9 C5 D5 -- CD DD D9 C1 D1
+        CPX E0 E4 -- -- EC -- -- -- --
+        CPY C0 C4 -- -- CC -- -- -- --
+        DEC -- C6 D6 -- CE DE -- -- --
+        EOR 49 45 55 -- 4D 5D 59 41 51
+        INC -- E6 F6 -- EE FE -- -- --
+        JMP -- -- -- -- 4C -- -- -- --
+        JSR -- -- -- -- 20 -- -- -- --
+        LDA A9 A5 B5 -- AD BD B9 A1 B1
+        LDX A2 A6 -- B6 AE -- BE -- --
+        LDY A0 A4 B4 -- AC BC -- -- --
+        LSR -- 46 56 -- 4E 5E -- -- --
+        ORA 09 05 15 -- 0D 1D 19 01 11
+        ROL -- 26 36 -- 2E 3E -- -- --
+        ROR -- 66 76 -- 6E 7E -- -- --
+        SBC E9 E5 F5 -- ED FD F9 E1 F1
+        STA -- 85 95 -- 8D 9D 99 81 91
+        STX -- 86 -- 96 8E -- -- -- --
+        STY -- 84 94 -- 8C -- -- -- --
+    ";
+
+    #[test]
+    fn test_reference_card() {
+        use crate::cpu::disassembler::DecodedInstruction;
+
+        const OPERANDS: [&str; 9] = [
+            "#$34", "$34", "$34,X", "$34,Y", "$1234", "$1234,X", "$1234,Y", "($34,X)", "($34),Y",
+        ];
+
+        for row in REFERENCE_CARD.lines().filter(|row| !row.trim().is_empty()) {
+            let mut columns = row.split_whitespace();
+            let mnemonic = columns.next().unwrap();
+
+            for (code, operand) in columns.zip(OPERANDS) {
+                let Ok(code) = u8::from_str_radix(code, 16) else {
+                    continue;
+                };
+                let source = format!("{} {}", mnemonic, operand);
+                let bytes = &[code, 0x34, 0x12][..if operand.contains("$12") { 3 } else { 2 }];
+
+                assert_eq!(bytes_of(&source), Ok(bytes.to_vec()), "{}", source);
+                assert_eq!(
+                    DecodedInstruction::decode(bytes, 0x8000).to_string(),
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        use crate::cpu::disassembler::DecodedInstruction;
+
+        for op in CPU_OPS_CODES.iter().filter(|op| !op.unofficial) {
+            let bytes = &[op.code, 0x34, 0x12][..usize::from(op.len)];
+            let source = DecodedInstruction::decode(bytes, 0x8000).to_string();
+
+            assert_eq!(
+                bytes_of(&source),
+                Ok(bytes.to_vec()),
+                "${:02x} disassembled as '{}'",
+                op.code,
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        let program = assemble(
+            "
+            start:  ldx #3      ; count down
+            @loop:  dex
+                    bne @loop
+                    jsr sub
+                    jmp start
+            sub:    ldy #0
+            @loop:  iny
+                    bne @loop
+                    rts
+            ",
+            0x0600,
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.bytes,
+            vec![
+                0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x20, 0x0b, 0x06, 0x4c, 0x00, 0x06, 0xa0, 0x00, 0xc8,
+                0xd0, 0xfd, 0x60,
+            ]
+        );
+        assert_eq!(program.symbols["start@loop"], 0x0602);
+        assert_eq!(program.symbols["sub@loop"], 0x060d);
+    }
+
+    #[test]
+    fn test_forward_references() {
+        // A forward reference can't be known to be in zero page on the first
+        // pass, so it's assembled as absolute, while a constant defined
+        // earlier is assembled as zero page.
+        assert_eq!(
+            bytes(
+                "
+                ptr = $10
+                lda ptr
+                lda later
+                beq done
+                done: brk
+                later = $20
+                "
+            ),
+            vec![0xa5, 0x10, 0xad, 0x20, 0x00, 0xf0, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let program = assemble(
+            "
+            .org $c000
+            table: .byte 1, $ff, -1, 'A', \"hi;\"
+            .word table, $1234
+            .org $c010
+            .byte >table, <table
+            ",
+            0x8000,
+        )
+        .unwrap();
+
+        assert_eq!(program.origin, 0xc000);
+        assert_eq!(
+            program.bytes,
+            vec![
+                0x01, 0xff, 0xff, 0x41, 0x68, 0x69, 0x3b, 0x00, 0xc0, 0x34, 0x12, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0xc0, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(
+            bytes(
+                "
+                base = $1200
+                lda #<(base + $34)
+                ldx #>base + 1
+                ldy #2 + 3 * 4
+                lda #%1010 | $f0 & $30
+                lda base + 2 * 8,x
+                jmp *
+                "
+            ),
+            vec![
+                0xa9, 0x34, 0xa2, 0x13, 0xa0, 0x0e, 0xa9, 0x3a, 0xbd, 0x10, 0x12, 0x4c, 0x0b, 0x80,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefers_official_opcodes() {
+        assert_eq!(
+            bytes("nop\nsbc #1\nlax $10"),
+            vec![0xea, 0xe9, 0x01, 0xa7, 0x10]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("lda #1\nfoo"), "line 2: unknown instruction 'foo'");
+        assert_eq!(error("jmp missing"), "line 1: 'missing' is not defined");
+        assert_eq!(error("x: nop\nx: nop"), "line 2: 'x' is already defined");
+        assert_eq!(
+            error("inx a"),
+            "line 1: inx does not support that addressing mode"
+        );
+        assert_eq!(error("lda ($1234),y"), "line 1: $1234 is not in zero page");
+        assert_eq!(error("lda #256"), "line 1: 256 does not fit in a byte");
+        assert_eq!(
+            error(".org $0200\nnop\n.org $0100"),
+            "line 3: .org $0100 is behind the current address"
+        );
+        assert_eq!(
+            error("start: .byte 0\n.org start + 200\nbne start"),
+            "line 3: branch to $8000 is out of range"
+        );
+        assert_eq!(error(".fill 4"), "line 1: unknown directive '.fill'");
+    }
+}
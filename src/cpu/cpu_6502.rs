@@ -1,7 +1,8 @@
 /// A trait for implementing every instruction supported by the 6502 CPU.
 pub trait Cpu6502 {
     /// Add the accumulator, the value at the given address, and the carry bit,
-    /// and store the result in the accumulator.
+    /// and store the result in the accumulator. In decimal mode on the 6502,
+    /// the operands and result are binary-coded decimal.
     ///
     /// Processor status bits affected:
     ///
@@ -31,13 +32,13 @@ pub trait Cpu6502 {
     fn asl(&mut self, addr: Option<u16>);
 
     /// Branch to the given address if the carry bit is not set.
-    fn bcc(&mut self, displacement: u16);
+    fn bcc(&mut self, addr: u16);
 
     /// Branch to the given address if the carry bit is set.
-    fn bcs(&mut self, displacement: u16);
+    fn bcs(&mut self, addr: u16);
 
     /// Branch to the given address if the zero bit is set.
-    fn beq(&mut self, displacement: u16);
+    fn beq(&mut self, addr: u16);
 
     /// Perform a bit test on the value at the given address.
     ///
@@ -49,29 +50,31 @@ pub trait Cpu6502 {
     fn bit(&mut self, addr: u16);
 
     /// Branch to the given address if the negative bit is set.
-    fn bmi(&mut self, displacement: u16);
+    fn bmi(&mut self, addr: u16);
 
     /// Branch to the given address if the zero bit is not set.
-    fn bne(&mut self, displacement: u16);
+    fn bne(&mut self, addr: u16);
 
     /// Branch to the given address if the negative bit is not set.
-    fn bpl(&mut self, displacement: u16);
+    fn bpl(&mut self, addr: u16);
 
-    /// Force an interrupt, pushing the the program counter and processor status
-    /// onto the stack, setting the program counter to the value at the
-    /// designated interrupt address, and set the break bits.
+    /// Force an interrupt, pushing the address after the padding byte that
+    /// follows the instruction and the processor status onto the stack,
+    /// setting the program counter to the value at the designated interrupt
+    /// address, and set the break bits.
     ///
     /// Processor status bits affected:
     ///
+    /// * I - set to 1.
     /// * B - set to 1.
     /// * B2 - set to 1.
     fn brk(&mut self);
 
     /// Branch to the given address if the overflow bit is not set.
-    fn bvc(&mut self, displacement: u16);
+    fn bvc(&mut self, addr: u16);
 
     /// Branch to the given address if the overflow bit is set.
-    fn bvs(&mut self, displacement: u16);
+    fn bvs(&mut self, addr: u16);
 
     /// Clear the carry bit.
     ///
@@ -128,6 +131,16 @@ pub trait Cpu6502 {
     /// * N - set if Y register <= value at address.
     fn cpy(&mut self, addr: u16);
 
+    /// Decrement the value at the given address, then compare the accumulator
+    /// with the result. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - set if accumulator >= the decremented value.
+    /// * Z - set if accumulator == the decremented value.
+    /// * N - set to bit 7 of the accumulator minus the decremented value.
+    fn dcp(&mut self, addr: u16);
+
     /// Decrement the value at the given address.
     ///
     /// Processor status bits affected:
@@ -188,13 +201,33 @@ pub trait Cpu6502 {
     /// * N - set to bit 7 of the result.
     fn iny(&mut self);
 
+    /// Increment the value at the given address, then subtract the result from
+    /// the accumulator. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - clear if the subtraction borrowed.
+    /// * Z - set if the result is 0.
+    /// * V - set if bit 7 of the result is incorrect.
+    /// * N - set to bit 7 of the result.
+    fn isb(&mut self, addr: u16);
+
     /// Set the program counter to the specified address.
     fn jmp(&mut self, addr: u16);
 
-    /// Push the address of the next sequential instruction onto the stack, and
+    /// Push the address of the last byte of the instruction onto the stack, and
     /// set the program counter to the given address.
     fn jsr(&mut self, addr: u16);
 
+    /// Load the value at the given address into both the accumulator and the X
+    /// register. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * Z - set if the value is 0.
+    /// * N - set to bit 7 of the value.
+    fn lax(&mut self, addr: u16);
+
     /// Set the accumulator to the value at the given address.
     ///
     /// Processor status bits affected:
@@ -270,6 +303,16 @@ pub trait Cpu6502 {
     /// * N - set from stack.
     fn plp(&mut self);
 
+    /// Rotate the value at the given address one place to the left, then
+    /// perform a bitwise and between the accumulator and the result. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - set to bit 7 of the initial value.
+    /// * Z - set if the accumulator is 0.
+    /// * N - set to bit 7 of the accumulator.
+    fn rla(&mut self, addr: u16);
+
     /// Rotate the bits in the accumulator or at the given address one place to
     /// the left through the carry bit.
     ///
@@ -288,6 +331,17 @@ pub trait Cpu6502 {
     /// * N - set if the bit 7 of the new value is set.
     fn ror(&mut self, addr: Option<u16>);
 
+    /// Rotate the value at the given address one place to the right, then add
+    /// the result and the carry bit to the accumulator. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - set if the addition overflows past bit 7.
+    /// * Z - set if the result is 0.
+    /// * V - set if bit 7 of the result is incorrect.
+    /// * N - set to bit 7 of the result.
+    fn rra(&mut self, addr: u16);
+
     /// Return from an interrupt by pulling the processor flags and program
     /// counter from the stack.
     ///
@@ -303,11 +357,17 @@ pub trait Cpu6502 {
     /// * N - set from stack.
     fn rti(&mut self);
 
-    /// Return from a subroutine by setting the program counter to the last
-    /// value on the stack.
+    /// Return from a subroutine by setting the program counter to the value
+    /// after the last address on the stack.
     fn rts(&mut self);
 
-    /// Subtract the value at the given address from the accumulator.
+    /// Store the bitwise and of the accumulator and the X register at the given
+    /// address. Unofficial.
+    fn sax(&mut self, addr: u16);
+
+    /// Subtract the value at the given address from the accumulator. In
+    /// decimal mode on the 6502, the operands and result are binary-coded
+    /// decimal, but the flags are set as in binary mode.
     ///
     /// Processor status bits affected:
     ///
@@ -338,6 +398,27 @@ pub trait Cpu6502 {
     /// * I - set to 1.
     fn sei(&mut self);
 
+    /// Shift the value at the given address one place to the left, then perform
+    /// a bitwise or between the accumulator and the result. Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - set to bit 7 of the initial value.
+    /// * Z - set if the accumulator is 0.
+    /// * N - set to bit 7 of the accumulator.
+    fn slo(&mut self, addr: u16);
+
+    /// Shift the value at the given address one place to the right, then
+    /// perform an exclusive or between the accumulator and the result.
+    /// Unofficial.
+    ///
+    /// Processor status bits affected:
+    ///
+    /// * C - set to bit 0 of the initial value.
+    /// * Z - set if the accumulator is 0.
+    /// * N - set to bit 7 of the accumulator.
+    fn sre(&mut self, addr: u16);
+
     /// Store the accumulator at the given address.
     fn sta(&mut self, addr: u16);
 
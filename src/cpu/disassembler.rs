@@ -0,0 +1,237 @@
+use std::fmt;
+
+use super::{
+    instructions::Instruction,
+    opcodes::{self, AddressingMode, OpCode},
+};
+
+/// Mnemonic used for bytes that aren't part of a recognized instruction.
+pub const BYTE_DIRECTIVE: &str = ".byte";
+
+/// An instruction decoded from a sequence of bytes, or a single byte that
+/// couldn't be decoded as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// The address of the first byte.
+    pub addr: u16,
+    /// Every byte of the instruction, starting with the opcode.
+    pub bytes: Vec<u8>,
+    /// The uppercase mnemonic, e.g. `LDA`, or [`BYTE_DIRECTIVE`].
+    pub mnemonic: String,
+    /// The operand in standard syntax, e.g. `($10),Y`, or empty if there
+    /// isn't one.
+    pub operand: String,
+    /// The address a branch or jump goes to, if it's known without running
+    /// the program.
+    pub target: Option<u16>,
+    /// Whether the opcode is outside of the documented instruction set.
+    pub unofficial: bool,
+}
+
+impl DecodedInstruction {
+    /// Decode the instruction at the start of the given bytes, which are at
+    /// the given address. Unrecognized opcodes and instructions cut off by the
+    /// end of the bytes are decoded as a single `.byte`.
+    pub fn decode(bytes: &[u8], addr: u16) -> Self {
+        let opcode = bytes
+            .first()
+            .and_then(|&code| opcodes::decode(code))
+            .filter(|opcode| usize::from(opcode.len) <= bytes.len());
+
+        match opcode {
+            Some(opcode) => {
+                let bytes = &bytes[..usize::from(opcode.len)];
+                let (operand, target) = format_operand(opcode, bytes, addr);
+
+                Self {
+                    addr,
+                    bytes: bytes.to_vec(),
+                    mnemonic: opcode.instruction.mnemonic().to_string(),
+                    operand,
+                    target,
+                    unofficial: opcode.unofficial,
+                }
+            }
+            None => {
+                let byte = bytes.first().copied().unwrap_or_default();
+
+                Self {
+                    addr,
+                    bytes: vec![byte],
+                    mnemonic: BYTE_DIRECTIVE.to_string(),
+                    operand: format!("${:02X}", byte),
+                    target: None,
+                    unofficial: false,
+                }
+            }
+        }
+    }
+
+    /// The address just past the last byte of the instruction.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Format the instruction as a line of a listing, with its address and
+    /// bytes, e.g. `8000  A9 01     LDA #$01`.
+    pub fn listing(&self) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{:04X}  {:8}  {}", self.addr, bytes.join(" "), self)
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    /// Format the instruction in assembly syntax, e.g. `LDA #$01`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operand.is_empty() {
+            f.write_str(&self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, self.operand)
+        }
+    }
+}
+
+/// Disassemble every instruction in the given bytes, which start at the given
+/// address.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<DecodedInstruction> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let instruction =
+            DecodedInstruction::decode(&bytes[offset..], origin.wrapping_add(offset as u16));
+
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Format the operand of an instruction in standard syntax, along with the
+/// address it branches or jumps to, if it's fixed.
+fn format_operand(opcode: &OpCode, bytes: &[u8], addr: u16) -> (String, Option<u16>) {
+    let byte = bytes.get(1).copied().unwrap_or_default();
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or_default()]);
+
+    match opcode.mode {
+        AddressingMode::Immediate => (format!("#${:02X}", byte), None),
+        AddressingMode::ZeroPage => (format!("${:02X}", byte), None),
+        AddressingMode::ZeroPageX => (format!("${:02X},X", byte), None),
+        AddressingMode::ZeroPageY => (format!("${:02X},Y", byte), None),
+        AddressingMode::Absolute => {
+            let target = match opcode.instruction {
+                Instruction::Jmp | Instruction::Jsr => Some(word),
+                _ => None,
+            };
+
+            (format!("${:04X}", word), target)
+        }
+        AddressingMode::AbsoluteX => (format!("${:04X},X", word), None),
+        AddressingMode::AbsoluteY => (format!("${:04X},Y", word), None),
+        AddressingMode::Indirect => (format!("(${:04X})", word), None),
+        AddressingMode::IndirectX => (format!("(${:02X},X)", byte), None),
+        AddressingMode::IndirectY => (format!("(${:02X}),Y", byte), None),
+        AddressingMode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            (format!("${:04X}", target), Some(target))
+        }
+        AddressingMode::NoneAddressing => match opcode.instruction {
+            Instruction::Asl | Instruction::Lsr | Instruction::Rol | Instruction::Ror => {
+                ("A".to_string(), None)
+            }
+            _ => (String::new(), None),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Disassemble the given bytes at $8000, formatted in assembly syntax.
+    fn asm(bytes: &[u8]) -> Vec<String> {
+        disassemble(bytes, 0x8000)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        assert_eq!(
+            asm(&[
+                0xa9, 0x01, // immediate
+                0xa5, 0x10, // zero page
+                0xb5, 0x10, // zero page,X
+                0xb6, 0x10, // zero page,Y
+                0xad, 0x00, 0x02, // absolute
+                0xbd, 0x00, 0x02, // absolute,X
+                0xb9, 0x00, 0x02, // absolute,Y
+                0x6c, 0xfc, 0xff, // indirect
+                0xa1, 0x10, // (indirect,X)
+                0xb1, 0x10, // (indirect),Y
+                0x0a, // accumulator
+                0xe8, // implied
+            ]),
+            vec![
+                "LDA #$01",
+                "LDA $10",
+                "LDA $10,X",
+                "LDX $10,Y",
+                "LDA $0200",
+                "LDA $0200,X",
+                "LDA $0200,Y",
+                "JMP ($FFFC)",
+                "LDA ($10,X)",
+                "LDA ($10),Y",
+                "ASL A",
+                "INX",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ldy_indexed() {
+        let instructions = disassemble(&[0xb4, 0x10, 0xbc, 0x00, 0x02], 0x8000);
+
+        assert_eq!(
+            instructions.iter().map(|i| i.listing()).collect::<Vec<_>>(),
+            vec!["8000  B4 10     LDY $10,X", "8002  BC 00 02  LDY $0200,X"]
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let instructions = disassemble(&[0xd0, 0xfe, 0x10, 0x02, 0x20, 0x34, 0x12], 0x8000);
+
+        assert_eq!(instructions[0].to_string(), "BNE $8000");
+        assert_eq!(instructions[0].target, Some(0x8000));
+        assert_eq!(instructions[1].to_string(), "BPL $8006");
+        assert_eq!(instructions[2].target, Some(0x1234));
+        assert_eq!(instructions[2].next_addr(), 0x8007);
+    }
+
+    #[test]
+    fn test_unknown_and_truncated() {
+        let instructions = disassemble(&[0x02, 0xea, 0xad, 0x00], 0x8000);
+
+        assert_eq!(
+            instructions.iter().map(|i| i.listing()).collect::<Vec<_>>(),
+            vec![
+                "8000  02        .byte $02",
+                "8001  EA        NOP",
+                "8002  AD        .byte $AD",
+                "8003  00        BRK",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unofficial() {
+        let instruction = DecodedInstruction::decode(&[0xa7, 0x10], 0x0600);
+
+        assert_eq!(instruction.to_string(), "LAX $10");
+        assert!(instruction.unofficial);
+    }
+}
@@ -1,7 +1,7 @@
-use super::cpu_6502::Cpu6502;
+use super::{cpu_6502::Cpu6502, CPU};
 
 /// Values representing each discrete instruction supported by the 6502 CPU.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Instruction {
     /// ADd with Carry
     Adc,
@@ -43,6 +43,8 @@ pub enum Instruction {
     Cpx,
     /// ComPare Y register
     Cpy,
+    /// DeCrement memory and comPare (unofficial)
+    Dcp,
     /// DECrecment memory
     Dec,
     /// DEcrement X register
@@ -57,10 +59,14 @@ pub enum Instruction {
     Inx,
     /// INcrement Y register
     Iny,
+    /// Increment memory and SuBtract with carry (unofficial)
+    Isb,
     /// JuMP
     Jmp,
     /// Jump to SubRoutine
     Jsr,
+    /// LoaD Accumulator and X register (unofficial)
+    Lax,
     /// LoaD Accumulator
     Lda,
     /// LoaD X register
@@ -81,14 +87,20 @@ pub enum Instruction {
     Pla,
     /// PuLl Processor status
     Plp,
+    /// Rotate Left and AND (unofficial)
+    Rla,
     /// ROtate Left
     Rol,
     /// ROtate Right
     Ror,
+    /// Rotate Right and Add with carry (unofficial)
+    Rra,
     /// ReTurn from Interrupt
     Rti,
     /// ReTurn from Subroutine
     Rts,
+    /// Store Accumulator AND X register (unofficial)
+    Sax,
     /// SuBtract with Carry
     Sbc,
     /// SEt Carry flag
@@ -97,6 +109,10 @@ pub enum Instruction {
     Sed,
     /// SEt Interrupt disable flag
     Sei,
+    /// Shift Left and OR (unofficial)
+    Slo,
+    /// Shift Right and EOR (unofficial)
+    Sre,
     /// STore Accumulator
     Sta,
     /// STore X register
@@ -117,85 +133,158 @@ pub enum Instruction {
     Tya,
 }
 
-pub trait Instructions {
-    fn call(&mut self, instruction: &Instruction, addr: Option<u16>);
-    fn with_operand<CB>(&mut self, callback: CB, addr: Option<u16>)
-    where
-        CB: Fn(&mut Self, u16);
+impl Instruction {
+    /// The three-letter assembly mnemonic for the instruction, e.g. `LDA`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Adc => "ADC",
+            Self::And => "AND",
+            Self::Asl => "ASL",
+            Self::Bcc => "BCC",
+            Self::Bcs => "BCS",
+            Self::Beq => "BEQ",
+            Self::Bit => "BIT",
+            Self::Bmi => "BMI",
+            Self::Bne => "BNE",
+            Self::Bpl => "BPL",
+            Self::Brk => "BRK",
+            Self::Bvc => "BVC",
+            Self::Bvs => "BVS",
+            Self::Clc => "CLC",
+            Self::Cld => "CLD",
+            Self::Cli => "CLI",
+            Self::Clv => "CLV",
+            Self::Cmp => "CMP",
+            Self::Cpx => "CPX",
+            Self::Cpy => "CPY",
+            Self::Dcp => "DCP",
+            Self::Dec => "DEC",
+            Self::Dex => "DEX",
+            Self::Dey => "DEY",
+            Self::Eor => "EOR",
+            Self::Inc => "INC",
+            Self::Inx => "INX",
+            Self::Iny => "INY",
+            Self::Isb => "ISB",
+            Self::Jmp => "JMP",
+            Self::Jsr => "JSR",
+            Self::Lax => "LAX",
+            Self::Lda => "LDA",
+            Self::Ldx => "LDX",
+            Self::Ldy => "LDY",
+            Self::Lsr => "LSR",
+            Self::Nop => "NOP",
+            Self::Ora => "ORA",
+            Self::Pha => "PHA",
+            Self::Php => "PHP",
+            Self::Pla => "PLA",
+            Self::Plp => "PLP",
+            Self::Rla => "RLA",
+            Self::Rol => "ROL",
+            Self::Ror => "ROR",
+            Self::Rra => "RRA",
+            Self::Rti => "RTI",
+            Self::Rts => "RTS",
+            Self::Sax => "SAX",
+            Self::Sbc => "SBC",
+            Self::Sec => "SEC",
+            Self::Sed => "SED",
+            Self::Sei => "SEI",
+            Self::Slo => "SLO",
+            Self::Sre => "SRE",
+            Self::Sta => "STA",
+            Self::Stx => "STX",
+            Self::Sty => "STY",
+            Self::Tax => "TAX",
+            Self::Tay => "TAY",
+            Self::Tsx => "TSX",
+            Self::Txa => "TXA",
+            Self::Txs => "TXS",
+            Self::Tya => "TYA",
+        }
+    }
 }
 
-impl<T> Instructions for T
-where
-    T: Cpu6502,
-{
-    /// Call the corresponding function for the given instruction.
-    fn call(&mut self, instruction: &Instruction, addr: Option<u16>) {
-        match instruction {
-            Instruction::Adc => self.with_operand(Self::adc, addr),
-            Instruction::And => self.with_operand(Self::and, addr),
-            Instruction::Asl => self.asl(addr), // handles None case to operate on accumulator
-            Instruction::Bcc => self.with_operand(Self::bcc, addr),
-            Instruction::Bcs => self.with_operand(Self::bcs, addr),
-            Instruction::Beq => self.with_operand(Self::beq, addr),
-            Instruction::Bit => self.with_operand(Self::bit, addr),
-            Instruction::Bmi => self.with_operand(Self::bmi, addr),
-            Instruction::Bne => self.with_operand(Self::bne, addr),
-            Instruction::Bpl => self.with_operand(Self::bpl, addr),
-            Instruction::Brk => self.brk(),
-            Instruction::Bvc => self.with_operand(Self::bvc, addr),
-            Instruction::Bvs => self.with_operand(Self::bvs, addr),
-            Instruction::Clc => self.clc(),
-            Instruction::Cld => self.cld(),
-            Instruction::Cli => self.cli(),
-            Instruction::Clv => self.clv(),
-            Instruction::Cmp => self.with_operand(Self::cmp, addr),
-            Instruction::Cpx => self.with_operand(Self::cpx, addr),
-            Instruction::Cpy => self.with_operand(Self::cpy, addr),
-            Instruction::Dec => self.with_operand(Self::dec, addr),
-            Instruction::Dex => self.dex(),
-            Instruction::Dey => self.dey(),
-            Instruction::Eor => self.with_operand(Self::eor, addr),
-            Instruction::Inc => self.with_operand(Self::inc, addr),
-            Instruction::Inx => self.inx(),
-            Instruction::Iny => self.iny(),
-            Instruction::Jmp => self.with_operand(Self::jmp, addr),
-            Instruction::Jsr => self.with_operand(Self::jsr, addr),
-            Instruction::Lda => self.with_operand(Self::lda, addr),
-            Instruction::Ldx => self.with_operand(Self::ldx, addr),
-            Instruction::Ldy => self.with_operand(Self::ldy, addr),
-            Instruction::Lsr => self.lsr(addr), // handles None case to operate on accumulator
-            Instruction::Nop => self.nop(),
-            Instruction::Ora => self.with_operand(Self::ora, addr),
-            Instruction::Pha => self.pha(),
-            Instruction::Php => self.php(),
-            Instruction::Pla => self.pla(),
-            Instruction::Plp => self.plp(),
-            Instruction::Rol => self.rol(addr), // handles None case to operate on accumulator
-            Instruction::Ror => self.ror(addr), // handles None case to operate on accumulator
-            Instruction::Rti => self.rti(),
-            Instruction::Rts => self.rts(),
-            Instruction::Sbc => self.with_operand(Self::sbc, addr),
-            Instruction::Sec => self.sec(),
-            Instruction::Sed => self.sed(),
-            Instruction::Sei => self.sei(),
-            Instruction::Sta => self.with_operand(Self::sta, addr),
-            Instruction::Stx => self.with_operand(Self::stx, addr),
-            Instruction::Sty => self.with_operand(Self::sty, addr),
-            Instruction::Tax => self.tax(),
-            Instruction::Tay => self.tay(),
-            Instruction::Tsx => self.tsx(),
-            Instruction::Txa => self.txa(),
-            Instruction::Txs => self.txs(),
-            Instruction::Tya => self.tya(),
+/// Executes an instruction on the CPU, given its operand address if it has one.
+pub type Handler = fn(&mut CPU, Option<u16>);
+
+impl Instruction {
+    /// The function that executes the instruction, which is stored with each
+    /// opcode so that executing one doesn't need to match on the instruction.
+    pub const fn handler(self) -> Handler {
+        /// Call an instruction that requires an operand, panicking if the
+        /// operand is missing.
+        macro_rules! with_operand {
+            ($f:ident) => {
+                |cpu, addr| cpu.$f(addr.expect("Required operand is missing"))
+            };
         }
-    }
 
-    /// Call the given callback that requires an operand and panic if the operand
-    /// is missing.
-    fn with_operand<CB>(&mut self, callback: CB, addr: Option<u16>)
-    where
-        CB: Fn(&mut Self, u16),
-    {
-        callback(self, addr.expect("Required operand is missing"))
+        match self {
+            Self::Adc => with_operand!(adc),
+            Self::And => with_operand!(and),
+            Self::Asl => |cpu, addr| cpu.asl(addr), // handles None case to operate on accumulator
+            Self::Bcc => with_operand!(bcc),
+            Self::Bcs => with_operand!(bcs),
+            Self::Beq => with_operand!(beq),
+            Self::Bit => with_operand!(bit),
+            Self::Bmi => with_operand!(bmi),
+            Self::Bne => with_operand!(bne),
+            Self::Bpl => with_operand!(bpl),
+            Self::Brk => |cpu, _| cpu.brk(),
+            Self::Bvc => with_operand!(bvc),
+            Self::Bvs => with_operand!(bvs),
+            Self::Clc => |cpu, _| cpu.clc(),
+            Self::Cld => |cpu, _| cpu.cld(),
+            Self::Cli => |cpu, _| cpu.cli(),
+            Self::Clv => |cpu, _| cpu.clv(),
+            Self::Cmp => with_operand!(cmp),
+            Self::Cpx => with_operand!(cpx),
+            Self::Cpy => with_operand!(cpy),
+            Self::Dcp => with_operand!(dcp),
+            Self::Dec => with_operand!(dec),
+            Self::Dex => |cpu, _| cpu.dex(),
+            Self::Dey => |cpu, _| cpu.dey(),
+            Self::Eor => with_operand!(eor),
+            Self::Inc => with_operand!(inc),
+            Self::Inx => |cpu, _| cpu.inx(),
+            Self::Iny => |cpu, _| cpu.iny(),
+            Self::Isb => with_operand!(isb),
+            Self::Jmp => with_operand!(jmp),
+            Self::Jsr => with_operand!(jsr),
+            Self::Lax => with_operand!(lax),
+            Self::Lda => with_operand!(lda),
+            Self::Ldx => with_operand!(ldx),
+            Self::Ldy => with_operand!(ldy),
+            Self::Lsr => |cpu, addr| cpu.lsr(addr), // handles None case to operate on accumulator
+            Self::Nop => |cpu, _| cpu.nop(),
+            Self::Ora => with_operand!(ora),
+            Self::Pha => |cpu, _| cpu.pha(),
+            Self::Php => |cpu, _| cpu.php(),
+            Self::Pla => |cpu, _| cpu.pla(),
+            Self::Plp => |cpu, _| cpu.plp(),
+            Self::Rla => with_operand!(rla),
+            Self::Rol => |cpu, addr| cpu.rol(addr), // handles None case to operate on accumulator
+            Self::Ror => |cpu, addr| cpu.ror(addr), // handles None case to operate on accumulator
+            Self::Rra => with_operand!(rra),
+            Self::Rti => |cpu, _| cpu.rti(),
+            Self::Rts => |cpu, _| cpu.rts(),
+            Self::Sax => with_operand!(sax),
+            Self::Sbc => with_operand!(sbc),
+            Self::Sec => |cpu, _| cpu.sec(),
+            Self::Sed => |cpu, _| cpu.sed(),
+            Self::Sei => |cpu, _| cpu.sei(),
+            Self::Slo => with_operand!(slo),
+            Self::Sre => with_operand!(sre),
+            Self::Sta => with_operand!(sta),
+            Self::Stx => with_operand!(stx),
+            Self::Sty => with_operand!(sty),
+            Self::Tax => |cpu, _| cpu.tax(),
+            Self::Tay => |cpu, _| cpu.tay(),
+            Self::Tsx => |cpu, _| cpu.tsx(),
+            Self::Txa => |cpu, _| cpu.txa(),
+            Self::Txs => |cpu, _| cpu.txs(),
+            Self::Tya => |cpu, _| cpu.tya(),
+        }
     }
 }
@@ -1,6 +1,13 @@
+use std::io;
+
 use super::mode::Mode;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub const MEMORY_SIZE: usize = 0x10000;
+/// Size of a page of memory, the unit that changes are tracked in.
+pub const PAGE_SIZE: usize = 0x100;
+/// Number of pages in memory.
+pub const PAGES: usize = MEMORY_SIZE / PAGE_SIZE;
 
 pub const STACK: u16 = 0x0100;
 pub const STACK_RESET: u8 = 0xfd;
@@ -17,6 +24,14 @@ pub trait MemoryValue {
 
     /// Write a value to the appropriate number of bytes in memory.
     fn write_to_memory(memory: &mut Memory, addr: usize, value: Self);
+
+    /// Assemble a value from individual bytes returned by the given function,
+    /// starting at the given address.
+    fn read_bytes<F: FnMut(u16) -> u8>(addr: u16, read: F) -> Self;
+
+    /// Pass each individual byte of a value to the given function, starting at
+    /// the given address.
+    fn write_bytes<F: FnMut(u16, u8)>(addr: u16, value: Self, write: F);
 }
 
 impl MemoryValue for u8 {
@@ -24,12 +39,23 @@ impl MemoryValue for u8 {
 
     /// Read a single byte from memory as a u8.
     fn read_from_memory(memory: &Memory, addr: usize) -> Self {
-        memory.0[addr]
+        memory.bytes[addr]
     }
 
     /// Write a u8 value to a single byte of memory.
     fn write_to_memory(memory: &mut Memory, addr: usize, value: Self) {
-        memory.0[addr] = value;
+        memory.bytes[addr] = value;
+        memory.dirty.insert(addr);
+    }
+
+    /// Read a single byte.
+    fn read_bytes<F: FnMut(u16) -> u8>(addr: u16, mut read: F) -> Self {
+        read(addr)
+    }
+
+    /// Write a single byte.
+    fn write_bytes<F: FnMut(u16, u8)>(addr: u16, value: Self, mut write: F) {
+        write(addr, value)
     }
 }
 
@@ -39,7 +65,7 @@ impl MemoryValue for u16 {
     /// Read two bytes from memory as a little-endian u16.
     fn read_from_memory(memory: &Memory, addr: usize) -> Self {
         u16::from_le_bytes(
-            memory.0[addr..addr + 2]
+            memory.bytes[addr..addr + 2]
                 .try_into()
                 .expect("invalid memory range"),
         )
@@ -47,20 +73,77 @@ impl MemoryValue for u16 {
 
     /// Write a u16 value in little-endian form to two bytes of memory.
     fn write_to_memory(memory: &mut Memory, addr: usize, value: Self) {
-        let (_, right) = memory.0.split_at_mut(addr);
+        let (_, right) = memory.bytes.split_at_mut(addr);
         let (mid, _) = right.split_at_mut(2);
 
         mid.copy_from_slice(&value.to_le_bytes());
+        memory.dirty.insert(addr);
+        memory.dirty.insert(addr + 1);
+    }
+
+    /// Read two consecutive bytes as a little-endian u16.
+    fn read_bytes<F: FnMut(u16) -> u8>(addr: u16, mut read: F) -> Self {
+        let lo = read(addr);
+        let hi = read(addr.wrapping_add(1));
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// Write a u16 in little-endian form to two consecutive bytes.
+    fn write_bytes<F: FnMut(u16, u8)>(addr: u16, value: Self, mut write: F) {
+        let [lo, hi] = value.to_le_bytes();
+
+        write(addr, lo);
+        write(addr.wrapping_add(1), hi);
     }
 }
 
-/// A 64 KiB memory register.
+/// A set of pages of memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pages([u64; PAGES / 64]);
+
+impl Pages {
+    /// Every page.
+    pub const ALL: Self = Self([u64::MAX; PAGES / 64]);
+
+    /// Add the page containing the given address.
+    fn insert(&mut self, addr: usize) {
+        let page = addr / PAGE_SIZE;
+        self.0[page / 64] |= 1 << (page % 64);
+    }
+
+    pub fn contains(&self, page: usize) -> bool {
+        self.0[page / 64] & (1 << (page % 64)) != 0
+    }
+
+    /// The pages in the set, in order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..PAGES).filter(|&page| self.contains(page))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|bits| bits.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&bits| bits == 0)
+    }
+}
+
+/// A 64 KiB memory register, which keeps track of the pages written since they
+/// were last taken with [`Memory::take_dirty`].
 #[derive(Debug, Clone, Copy)]
-pub struct Memory([u8; MEMORY_SIZE]);
+pub struct Memory {
+    bytes: [u8; MEMORY_SIZE],
+    dirty: Pages,
+}
 
 impl Default for Memory {
     fn default() -> Self {
-        Memory([0; MEMORY_SIZE])
+        Memory {
+            bytes: [0; MEMORY_SIZE],
+            dirty: Pages::ALL,
+        }
     }
 }
 
@@ -69,10 +152,21 @@ impl Memory {
     pub fn load(&mut self, program: Vec<u8>, mode: Mode) {
         let program_rom = mode.program_rom();
 
-        self.0[program_rom..(program_rom + program.len())].copy_from_slice(&program[..]);
+        self.load_at(&program, program_rom as u16);
         self.write(RESET, program_rom as u16);
     }
 
+    /// Copy a program into memory starting at the given address.
+    pub fn load_at(&mut self, program: &[u8], addr: u16) {
+        let addr = usize::from(addr);
+        let end = addr + program.len();
+
+        self.bytes[addr..end].copy_from_slice(program);
+        for page in addr / PAGE_SIZE..end.div_ceil(PAGE_SIZE) {
+            self.dirty.insert(page * PAGE_SIZE);
+        }
+    }
+
     /// Read a u8 or u16 from memory.
     pub fn read<T: MemoryValue>(&self, addr: u16) -> T {
         T::read_from_memory(self, addr as usize)
@@ -82,6 +176,40 @@ impl Memory {
     pub fn write<T: MemoryValue>(&mut self, addr: u16, value: T) {
         T::write_to_memory(self, addr as usize, value)
     }
+
+    /// The contents of a page.
+    pub fn page(&self, page: usize) -> &[u8] {
+        &self.bytes[page * PAGE_SIZE..(page + 1) * PAGE_SIZE]
+    }
+
+    /// The entire contents of memory.
+    pub fn bytes(&self) -> &[u8; MEMORY_SIZE] {
+        &self.bytes
+    }
+
+    /// Replace the entire contents of memory.
+    pub fn set_bytes(&mut self, bytes: &[u8; MEMORY_SIZE]) {
+        self.bytes = *bytes;
+        self.dirty = Pages::ALL;
+    }
+
+    /// The pages written since the last call, or since the memory was created
+    /// or loaded, which counts as writing every page.
+    pub fn take_dirty(&mut self) -> Pages {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+impl SaveState for Memory {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.bytes);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.bytes.copy_from_slice(state.bytes(MEMORY_SIZE)?);
+        self.dirty = Pages::ALL;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -91,7 +219,7 @@ mod test {
     #[test]
     fn test_read_u8() {
         let mut memory = Memory::default();
-        memory.0[0xbeef] = 0x42;
+        memory.bytes[0xbeef] = 0x42;
 
         assert_eq!(memory.read::<u8>(0xbeef), 0x42);
     }
@@ -101,8 +229,8 @@ mod test {
         let mut memory = Memory::default();
         let addr = 0xbeef;
 
-        memory.0[addr] = 0x42;
-        memory.0[addr + 1] = 0x43;
+        memory.bytes[addr] = 0x42;
+        memory.bytes[addr + 1] = 0x43;
 
         assert_eq!(memory.read::<u16>(addr as u16), 0x4342);
     }
@@ -112,7 +240,7 @@ mod test {
         let mut memory = Memory::default();
         memory.write(0xbeef, 0x42_u8);
 
-        assert_eq!(memory.0[0xbeef], 0x42);
+        assert_eq!(memory.bytes[0xbeef], 0x42);
     }
 
     #[test]
@@ -122,7 +250,22 @@ mod test {
 
         memory.write(addr, 0x4342_u16);
 
-        assert_eq!(memory.0[addr as usize], 0x42);
-        assert_eq!(memory.0[addr as usize + 1], 0x43);
+        assert_eq!(memory.bytes[addr as usize], 0x42);
+        assert_eq!(memory.bytes[addr as usize + 1], 0x43);
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        let mut memory = Memory::default();
+        assert_eq!(memory.take_dirty(), Pages::ALL);
+        assert!(memory.take_dirty().is_empty());
+
+        memory.write(0x12ff, 0x4342_u16);
+        memory.load_at(&[0; 0x101], 0x8080);
+
+        let pages = memory.take_dirty();
+        assert_eq!(pages.iter().collect::<Vec<_>>(), [0x12, 0x13, 0x80, 0x81]);
+        assert_eq!(pages.len(), 4);
+        assert!(memory.take_dirty().is_empty());
     }
 }
@@ -1,24 +1,58 @@
-use std::{fmt::LowerHex, ops::Shr};
+use std::{error, fmt, io};
+
+use crate::{
+    apu::{self, Apu},
+    controller::{self, Controller},
+    mapper::Mapper,
+    state::{SaveState, StateReader, StateWriter},
+};
 
 use self::{
     cpu_6502::Cpu6502,
-    instructions::Instructions,
-    memory::{Memory, MemoryValue},
+    memory::{Memory, MemoryValue, Pages, MEMORY_SIZE},
     mode::Mode,
     opcodes::AddressingMode,
     status::Status,
+    trace::Tracer,
+    watch::Watchpoints,
 };
 
+pub mod assembler;
 mod cpu_6502;
+pub mod disassembler;
 mod instructions;
-mod memory;
+pub mod memory;
 pub mod mode;
 mod opcodes;
-mod status;
+pub mod status;
+pub mod trace;
+pub mod watch;
 
 #[cfg(test)]
 mod test;
 
+/// Number of CPU cycles in a single NTSC frame, rounded up from 29780.5.
+pub const CYCLES_PER_FRAME: u64 = 29_781;
+
+/// An error that stops the CPU from executing a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The opcode fetched from the given address isn't recognized.
+    UnknownOpcode { opcode: u8, addr: u16 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { opcode, addr } => {
+                write!(f, "Opcode {:x} is not recognized at ${:04x}", opcode, addr)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
 /// One-byte stack pointer.
 #[derive(Debug, Clone, Copy)]
 pub struct StackPointer(u8);
@@ -43,6 +77,12 @@ impl From<StackPointer> for u16 {
     }
 }
 
+impl From<u8> for StackPointer {
+    fn from(s: u8) -> Self {
+        Self(s)
+    }
+}
+
 impl StackPointer {
     pub fn wrapping_add(self, rhs: u8) -> Self {
         Self(self.0.wrapping_add(rhs))
@@ -64,6 +104,15 @@ pub struct CPU {
     pub status: Status,
     memory: Memory,
     pub mode: Mode,
+    /// Number of cycles executed since the last reset.
+    pub cycles: u64,
+    pub apu: Apu,
+    pub controllers: [Controller; 2],
+    pub mapper: Option<Box<dyn Mapper>>,
+    /// Receives instruction and fetch events, if set.
+    pub tracer: Option<Box<dyn Tracer>>,
+    /// Checks every access to the bus against its watchpoints, if set.
+    pub watchpoints: Option<Watchpoints>,
 }
 
 impl CPU {
@@ -84,30 +133,211 @@ impl CPU {
         self.memory.load(program, self.mode);
     }
 
+    /// Copy a program into memory starting at the given address, without
+    /// changing the reset vector.
+    pub fn load_at(&mut self, program: &[u8], addr: u16) {
+        self.memory.load_at(program, addr);
+    }
+
     /// Set the program counter to the value at the designated reset address in
-    /// memory, and reset all flags and internal registers to their default
-    /// values, while preserving the contents of memory.
+    /// memory or the cartridge, and reset all flags and internal registers to
+    /// their default values, while preserving the contents of memory.
     pub fn reset(&mut self) {
         *self = Self {
-            program_counter: self.memory.read(memory::RESET),
+            program_counter: u16::read_bytes(memory::RESET, |addr| self.peek(addr)),
             memory: self.memory,
+            mode: self.mode,
+            apu: Apu::new(self.apu.sample_rate()),
+            controllers: self.controllers,
+            mapper: self.mapper.take(),
+            tracer: self.tracer.take(),
+            watchpoints: self.watchpoints.take(),
             ..Default::default()
         }
     }
 
-    /// Read and execute each instruction in the program.
-    pub fn run(&mut self) {
-        let opcodes = &(*opcodes::OPCODES_MAP);
+    /// Save the entire state of the machine in the versioned format described
+    /// in [`crate::state`]. The tracer and watchpoints aren't included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.save_state_into(&mut bytes);
+        bytes
+    }
 
-        loop {
-            let code: u8 = self.read_program_counter();
+    /// Save the entire state of the machine into a buffer, replacing its
+    /// contents but reusing its allocation, for saving states frequently.
+    pub fn save_state_into(&self, bytes: &mut Vec<u8>) {
+        self.save_state_parts(bytes, true);
+    }
+
+    /// Save the state of the machine other than its memory, for keeping a copy
+    /// of memory up to date with [`CPU::take_dirty_pages`] instead of copying
+    /// all of it each time. Restore it with [`CPU::load_state_with_memory`].
+    pub fn save_state_without_memory_into(&self, bytes: &mut Vec<u8>) {
+        self.save_state_parts(bytes, false);
+    }
+
+    fn save_state_parts(&self, bytes: &mut Vec<u8>, with_memory: bool) {
+        let mut state = StateWriter::with_header_in(std::mem::take(bytes));
+
+        state.u8(self.accumulator);
+        state.u8(self.index_x);
+        state.u8(self.index_y);
+        state.u16(self.program_counter);
+        state.u8(self.stack_pointer.into());
+        state.u8(self.status.bits());
+        state.u8(Mode::ALL
+            .iter()
+            .position(|&mode| mode == self.mode)
+            .unwrap() as u8);
+        state.u64(self.cycles);
+        if with_memory {
+            self.memory.save_state(&mut state);
+        }
+        self.apu.save_state(&mut state);
+        for controller in &self.controllers {
+            controller.save_state(&mut state);
+        }
+
+        let mut mapper = StateWriter::new();
+        if let Some(m) = &self.mapper {
+            m.save_state(&mut mapper);
+        }
+        state.sized_bytes(&mapper.into_bytes());
+
+        *bytes = state.into_bytes();
+    }
+
+    /// Restore the state of the machine from [`CPU::save_state`], keeping the
+    /// current cartridge, tracer, watchpoints, and sample rate. The CPU and its
+    /// cartridge are left unchanged if the state is invalid or from an
+    /// unsupported version.
+    pub fn load_state(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.load_state_parts(bytes, None)
+    }
+
+    /// Restore the state of the machine from
+    /// [`CPU::save_state_without_memory_into`] and the contents of memory.
+    pub fn load_state_with_memory(
+        &mut self,
+        bytes: &[u8],
+        memory: &[u8; MEMORY_SIZE],
+    ) -> io::Result<()> {
+        self.load_state_parts(bytes, Some(memory))
+    }
+
+    fn load_state_parts(
+        &mut self,
+        bytes: &[u8],
+        memory: Option<&[u8; MEMORY_SIZE]>,
+    ) -> io::Result<()> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut state = StateReader::with_header(bytes)?;
+        let mut loaded = Self {
+            apu: Apu::new(self.apu.sample_rate()),
+            ..Default::default()
+        };
 
-            let opcode = opcodes
-                .get(&code)
-                .unwrap_or_else(|| panic!("Opcode {:x} is not recognized", code));
+        loaded.accumulator = state.u8()?;
+        loaded.index_x = state.u8()?;
+        loaded.index_y = state.u8()?;
+        loaded.program_counter = state.u16()?;
+        loaded.stack_pointer = state.u8()?.into();
+        loaded.status = Status::from(state.u8()?);
+        loaded.mode = *Mode::ALL
+            .get(usize::from(state.u8()?))
+            .ok_or_else(|| invalid("unknown mode in save state"))?;
+        loaded.cycles = state.u64()?;
+        match memory {
+            Some(memory) => loaded.memory.set_bytes(memory),
+            None => loaded.memory.load_state(&mut state)?,
+        }
+        loaded.apu.load_state(&mut state)?;
+        for controller in &mut loaded.controllers {
+            controller.load_state(&mut state)?;
+        }
 
-            let addr = self.get_operand_address(&opcode.mode);
-            self.call(&opcode.instruction, addr);
+        let mapper = state.sized_bytes()?;
+        state.finish()?;
+
+        match &mut self.mapper {
+            Some(m) => {
+                // The mapper can only be restored in place, so keep its current
+                // state to put back if the new one turns out to be invalid.
+                let mut backup = StateWriter::new();
+                m.save_state(&mut backup);
+
+                let result = (|| {
+                    let mut mapper = StateReader::with_version(mapper, state.version());
+                    m.load_state(&mut mapper)?;
+                    mapper.finish()?;
+
+                    // Before version 2, battery-backed PRG-RAM was saved as
+                    // part of CPU memory.
+                    if state.version() < 2 && m.battery_ram().is_some() {
+                        let ram: Vec<u8> = (0x6000..0x8000)
+                            .map(|addr| loaded.memory.read(addr))
+                            .collect();
+                        m.load_battery_ram(&ram)?;
+                    }
+
+                    Ok(())
+                })();
+
+                if let Err(e) = result {
+                    m.load_state(&mut StateReader::new(&backup.into_bytes()))
+                        .expect("a mapper should load the state it just saved");
+                    return Err(e);
+                }
+            }
+            None if !mapper.is_empty() => {
+                return Err(invalid(
+                    "save state includes cartridge state, but no cartridge is loaded",
+                ));
+            }
+            None => (),
+        }
+
+        *self = Self {
+            mapper: self.mapper.take(),
+            tracer: self.tracer.take(),
+            watchpoints: self.watchpoints.take(),
+            ..loaded
+        };
+
+        Ok(())
+    }
+
+    /// The CPU's memory, not including the cartridge or memory-mapped
+    /// registers.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// The pages of memory written since the last call, or since memory was
+    /// last loaded, which counts as writing every page.
+    pub fn take_dirty_pages(&mut self) -> Pages {
+        self.memory.take_dirty()
+    }
+
+    /// Read a single byte from memory or the cartridge.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.mapper
+            .as_ref()
+            .and_then(|mapper| mapper.read(addr))
+            .unwrap_or_else(|| self.memory.read(addr))
+    }
+
+    /// Write a single byte directly to memory, bypassing the cartridge and any
+    /// memory-mapped registers.
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.memory.write(addr, value);
+    }
+
+    /// Read and execute each instruction in the program.
+    pub fn run(&mut self) {
+        loop {
+            self.step();
 
             // Break if the program counter is empty.
             if self.program_counter == 0 {
@@ -116,15 +346,225 @@ impl CPU {
         }
     }
 
-    /// Retrieve an operand address based on the given addressing mode.
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> Option<u16> {
-        match mode {
+    /// Run the program for the given number of frames' worth of cycles. Returns
+    /// false if the program halted before then.
+    pub fn run_frames(&mut self, frames: u64) -> bool {
+        let end = self.cycles + frames * CYCLES_PER_FRAME;
+
+        while self.cycles < end {
+            self.step();
+
+            if self.program_counter == 0 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Call the subroutine at the given address with a return address of $0000,
+    /// and run until it returns or the given number of cycles have passed.
+    /// Returns false if the subroutine didn't return in time.
+    pub fn run_subroutine(&mut self, addr: u16, max_cycles: u64) -> bool {
+        let end = self.cycles + max_cycles;
+
+        // RTS increments the address it pulls from the stack.
+        self.stack_push(0xffff_u16);
+        self.program_counter = addr;
+
+        while self.program_counter != 0 {
+            if self.cycles >= end {
+                return false;
+            }
+
+            self.step();
+        }
+
+        true
+    }
+
+    /// Let the given number of cycles pass without executing any instructions.
+    pub fn idle(&mut self, cycles: u64) {
+        self.tick(cycles);
+    }
+
+    /// Read and execute a single instruction, advance the APU by the number of
+    /// cycles it took, and service any pending interrupt. Panics if the opcode
+    /// isn't recognized.
+    pub fn step(&mut self) {
+        if let Err(e) = self.try_step() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Read and execute a single instruction like [`CPU::step`], returning an
+    /// error if the opcode isn't recognized. The program counter is left
+    /// pointing at the offending opcode.
+    pub fn try_step(&mut self) -> Result<(), Error> {
+        if self.tracer.is_some() {
+            self.trace_instruction();
+        }
+
+        let addr = self.program_counter;
+        if self.watchpoints.is_some() {
+            self.watch_instruction(addr);
+        }
+
+        let code: u8 = self.read_program_counter();
+
+        let opcode = match opcodes::decode(code) {
+            Some(opcode) => opcode,
+            None => {
+                self.program_counter = addr;
+                return Err(Error::UnknownOpcode { opcode: code, addr });
+            }
+        };
+
+        let (addr, page_crossed) = self.get_operand_address(&opcode.mode);
+        (opcode.handler)(self, addr);
+        self.tick(opcode.cycles.into());
+
+        if page_crossed && opcode.page_cross_penalty() {
+            self.tick(1);
+        }
+
+        if self.apu.irq() && !self.status.contains(Status::InterruptDisable) {
+            self.interrupt(memory::INTERRUPT);
+        }
+
+        Ok(())
+    }
+
+    /// Advance the cycle counter and, on the 2A03, the APU by the given number
+    /// of cycles. The CPU is stalled while the DMC memory reader fetches a
+    /// sample byte.
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+
+        if let Mode::Nes2A03 = self.mode {
+            for _ in 0..cycles {
+                self.apu.tick();
+
+                if let Some(addr) = self.apu.dmc_read_address() {
+                    let value = self.read(addr);
+                    self.apu.dmc_fill(value);
+                    self.tick(4);
+                }
+            }
+        }
+    }
+
+    /// Push the program counter and processor status onto the stack, and jump
+    /// to the address stored at the given interrupt vector.
+    fn interrupt(&mut self, vector: u16) {
+        self.stack_push(self.program_counter);
+        self.stack_push((self.status & Status::Break.not() | Status::Break2).bits());
+        self.status.set(Status::InterruptDisable, true);
+
+        self.program_counter = self.read(vector);
+        self.tick(7);
+    }
+
+    /// Read a u8 or u16 from the bus, which is routed to memory or to a
+    /// memory-mapped register.
+    fn read<T: MemoryValue>(&mut self, addr: u16) -> T {
+        T::read_bytes(addr, |addr| self.read_u8(addr))
+    }
+
+    /// Write a u8 or u16 to the bus, which is routed to memory or to a
+    /// memory-mapped register.
+    fn write<T: MemoryValue>(&mut self, addr: u16, value: T) {
+        T::write_bytes(addr, value, |addr, value| self.write_u8(addr, value))
+    }
+
+    /// Read a single byte from the bus.
+    fn read_u8(&mut self, addr: u16) -> u8 {
+        let value = match (self.mode, addr) {
+            (Mode::Nes2A03, apu::STATUS) => self.apu.read_status(),
+            (Mode::Nes2A03, controller::JOYPAD1) => self.controllers[0].read(),
+            (Mode::Nes2A03, controller::JOYPAD2) => self.controllers[1].read(),
+            _ => self.peek(addr),
+        };
+
+        if self.watchpoints.is_some() {
+            self.watch_read(addr, value);
+        }
+
+        value
+    }
+
+    /// Write a single byte to the bus.
+    fn write_u8(&mut self, addr: u16, value: u8) {
+        if self.watchpoints.is_some() {
+            self.watch_write(addr, value);
+        }
+
+        match (self.mode, addr) {
+            (Mode::Nes2A03, apu::REGISTERS..=apu::REGISTERS_END)
+            | (Mode::Nes2A03, apu::STATUS)
+            | (Mode::Nes2A03, apu::FRAME_COUNTER) => self.apu.write(addr, value),
+            (Mode::Nes2A03, controller::JOYPAD1) => {
+                for controller in &mut self.controllers {
+                    controller.write(value);
+                }
+            }
+            _ => {
+                let mapped = match self.mapper.as_mut() {
+                    Some(mapper) => mapper.write(addr, value),
+                    None => false,
+                };
+
+                if !mapped {
+                    self.memory.write(addr, value);
+                }
+            }
+        }
+    }
+
+    /// Tell the watchpoints which instruction is about to make accesses. Like
+    /// the other watch hooks, this is kept out of line so that the bus is as
+    /// fast without watchpoints as it was before they existed.
+    #[cold]
+    #[inline(never)]
+    fn watch_instruction(&mut self, addr: u16) {
+        if let Some(watchpoints) = self.watchpoints.as_mut() {
+            watchpoints.pc = addr;
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn watch_read(&mut self, addr: u16, value: u8) {
+        if let Some(watchpoints) = self.watchpoints.as_mut() {
+            watchpoints.read(addr, value);
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn watch_write(&mut self, addr: u16, value: u8) {
+        let old = self.peek(addr);
+        if let Some(watchpoints) = self.watchpoints.as_mut() {
+            watchpoints.write(addr, old, value);
+        }
+    }
+
+    /// Retrieve an operand address based on the given addressing mode, and
+    /// whether indexing it crossed a page boundary.
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> (Option<u16>, bool) {
+        let addr = match mode {
             // Return the program counter, and increment it manually since we'll
             // be reading it directly.
             AddressingMode::Immediate => {
-                let pc = Some(self.program_counter);
+                let pc = self.program_counter;
                 self.program_counter += 1;
-                pc
+
+                if self.tracer.is_some() {
+                    let value = self.peek(pc);
+                    self.trace_fetch(pc, value);
+                }
+
+                Some(pc)
             }
 
             AddressingMode::ZeroPage => Some(self.read_program_counter::<u8>().into()),
@@ -140,28 +580,32 @@ impl CPU {
             ),
 
             AddressingMode::Absolute => Some(self.read_program_counter::<u16>()),
-            AddressingMode::AbsoluteX => Some(
-                self.read_program_counter::<u16>()
-                    .wrapping_add(self.index_x.into()),
-            ),
-            AddressingMode::AbsoluteY => Some(
-                self.read_program_counter::<u16>()
-                    .wrapping_add(self.index_y.into()),
-            ),
+            AddressingMode::AbsoluteX => {
+                let base = self.read_program_counter();
+                return index_address(base, self.index_x);
+            }
+            AddressingMode::AbsoluteY => {
+                let base = self.read_program_counter();
+                return index_address(base, self.index_y);
+            }
 
             AddressingMode::Indirect => {
-                let addr: u16 = self.read_program_counter();
-                // TODO: fail if addr as u8 == 0xff
-                Some(self.memory.read(addr))
+                // The high byte of the target is read without carrying into the
+                // high byte of the pointer, so a pointer at $xxFF wraps around
+                // to $xx00.
+                let ptr: u16 = self.read_program_counter();
+                let lo = self.read_u8(ptr);
+                let hi = self.read_u8((ptr & 0xff00) | (ptr.wrapping_add(1) & 0x00ff));
+                Some(u16::from_le_bytes([lo, hi]))
             }
             AddressingMode::IndirectX => {
                 let ptr: u8 = self.read_program_counter();
-                Some(self.memory.read(ptr.wrapping_add(self.index_x).into()))
+                Some(self.read_zero_page_pointer(ptr.wrapping_add(self.index_x)))
             }
             AddressingMode::IndirectY => {
                 let ptr: u8 = self.read_program_counter();
-                let addr: u16 = self.memory.read(ptr.into());
-                Some(addr.wrapping_add(self.index_y.into()))
+                let base = self.read_zero_page_pointer(ptr);
+                return index_address(base, self.index_y);
             }
 
             AddressingMode::Relative => {
@@ -170,23 +614,52 @@ impl CPU {
             }
 
             AddressingMode::NoneAddressing => None,
-        }
+        };
+
+        (addr, false)
+    }
+
+    /// Read a pointer from the zero page, wrapping around to $00 if it starts
+    /// at $FF.
+    fn read_zero_page_pointer(&mut self, ptr: u8) -> u16 {
+        let lo = self.read_u8(ptr.into());
+        let hi = self.read_u8(ptr.wrapping_add(1).into());
+        u16::from_le_bytes([lo, hi])
     }
 
     /// Read the value at the address of the program counter, and increment the
     /// counter by the number of bytes in the returned value.
-    fn read_program_counter<T: MemoryValue + LowerHex>(&mut self) -> T {
-        let val: T = self.memory.read(self.program_counter);
-        println!("{:x}: {:x}", self.program_counter, val);
+    fn read_program_counter<T: MemoryValue>(&mut self) -> T {
+        let val = T::read_bytes(self.program_counter, |addr| {
+            let value = self.read_u8(addr);
+            self.trace_fetch(addr, value);
+            value
+        });
+
         self.program_counter += T::BITS / 8;
-        println!("{:x}", self.program_counter);
         val
     }
 
+    /// Pass a byte fetched through the program counter to the tracer.
+    fn trace_fetch(&mut self, addr: u16, value: u8) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.fetch(addr, value);
+        }
+    }
+
+    /// Pass the CPU's state to the tracer before executing an instruction.
+    fn trace_instruction(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.instruction(self);
+            self.tracer = Some(tracer);
+        }
+    }
+
     /// Add the given value to the accumulator.
     fn add_to_accumulator(&mut self, value: u8) {
-        let sum =
-            u16::from(self.accumulator + value) + u16::from(self.status.contains(Status::Carry));
+        let sum = u16::from(self.accumulator)
+            + u16::from(value)
+            + u16::from(self.status.contains(Status::Carry));
 
         self.status.set_carry(sum);
 
@@ -197,18 +670,82 @@ impl CPU {
         self.set_accumulator(result);
     }
 
-    /// If the condition is met, add the relative displacement to the program
-    /// counter to branch to a new location.
-    fn branch(&mut self, displacement: u16, condition: bool) {
+    /// Add the given value and the carry bit to the accumulator, in decimal if
+    /// decimal mode is enabled.
+    fn add_with_carry(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.add_decimal_to_accumulator(value);
+        } else {
+            self.add_to_accumulator(value);
+        }
+    }
+
+    /// Subtract the given value and the inverse of the carry bit from the
+    /// accumulator, in decimal if decimal mode is enabled.
+    fn subtract_with_carry(&mut self, value: u8) {
+        let accumulator = self.accumulator;
+        let carry = self.status.contains(Status::Carry);
+
+        self.add_to_accumulator((value as i8).wrapping_neg().wrapping_sub(1) as u8);
+
+        if self.decimal_mode() {
+            self.accumulator = subtract_decimal(accumulator, value, carry);
+        }
+    }
+
+    /// Whether ADC and SBC operate in binary-coded decimal. The 2A03 lacks
+    /// decimal mode, so the decimal flag has no effect on it.
+    fn decimal_mode(&self) -> bool {
+        self.mode == Mode::Mos6502 && self.status.contains(Status::Decimal)
+    }
+
+    /// Add the given value to the accumulator in binary-coded decimal, setting
+    /// the flags as the NMOS 6502 does, including for invalid BCD operands.
+    /// The zero flag is set from the binary sum, and the negative and overflow
+    /// flags from the sum before the high digit is adjusted.
+    fn add_decimal_to_accumulator(&mut self, value: u8) {
+        let carry = u8::from(self.status.contains(Status::Carry));
+        let (a, b) = (i16::from(self.accumulator), i16::from(value));
+
+        let mut low = (a & 0x0f) + (b & 0x0f) + i16::from(carry);
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+
+        let signed = i16::from((a & 0xf0) as u8 as i8) + i16::from((b & 0xf0) as u8 as i8) + low;
+        let mut sum = (a & 0xf0) + (b & 0xf0) + low;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+
+        self.status
+            .set_zero(self.accumulator.wrapping_add(value).wrapping_add(carry));
+        self.status.set_negative(signed as u8);
+        self.status.set_overflow(!(-128..=127).contains(&signed));
+        self.status.set(Status::Carry, sum >= 0x100);
+
+        self.accumulator = sum as u8;
+    }
+
+    /// If the condition is met, branch to the given address, which has already
+    /// had the relative displacement applied. Taking the branch costs an extra
+    /// cycle, and another if it lands on a different page.
+    fn branch(&mut self, addr: u16, condition: bool) {
         if condition {
-            self.program_counter += displacement;
+            let cycles = if addr & 0xff00 != self.program_counter & 0xff00 {
+                2
+            } else {
+                1
+            };
+
+            self.program_counter = addr;
+            self.tick(cycles);
         }
     }
 
-    /// Compare the given value to the value at the given address, and set the
-    /// carry, zero, and negative flags accordingly.
-    fn compare(&mut self, value: u8, addr: u16) {
-        let rhs: u8 = self.memory.read(addr);
+    /// Compare two values, and set the carry, zero, and negative flags
+    /// accordingly.
+    fn compare(&mut self, value: u8, rhs: u8) {
         let result = value.wrapping_sub(rhs);
 
         self.status.set(Status::Carry, value >= rhs);
@@ -216,6 +753,79 @@ impl CPU {
         self.status.set_negative(result);
     }
 
+    /// Replace the accumulator, or the value at the given address, with the
+    /// result of the given operation on it, and return the result.
+    fn modify<F: FnOnce(&mut Self, u8) -> u8>(&mut self, addr: Option<u16>, operation: F) -> u8 {
+        let value = match addr {
+            Some(addr) => self.read(addr),
+            None => self.accumulator,
+        };
+
+        let result = operation(self, value);
+
+        match addr {
+            Some(addr) => self.write(addr, result),
+            None => self.accumulator = result,
+        };
+
+        result
+    }
+
+    /// Shift a value one place to the left, setting the carry bit to its bit 7.
+    fn shift_left(&mut self, value: u8) -> u8 {
+        let result = value << 1;
+
+        self.status.set(Status::Carry, value >> 7 == 1);
+        self.set_status_negative_zero(result);
+
+        result
+    }
+
+    /// Shift a value one place to the right, setting the carry bit to its bit
+    /// 0.
+    fn shift_right(&mut self, value: u8) -> u8 {
+        let result = value >> 1;
+
+        self.status.set(Status::Carry, value % 2 == 1);
+        self.set_status_negative_zero(result);
+
+        result
+    }
+
+    /// Rotate a value and the carry bit one place to the left.
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let result = (value << 1) | self.status.and(Status::Carry).bits();
+
+        self.status.set(Status::Carry, value >> 7 == 1);
+        self.set_status_negative_zero(result);
+
+        result
+    }
+
+    /// Rotate a value and the carry bit one place to the right.
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let result = (value >> 1) | (self.status.and(Status::Carry).bits() << 7);
+
+        self.status.set(Status::Carry, value & 1 == 1);
+        self.set_status_negative_zero(result);
+
+        result
+    }
+
+    /// Add one to a value.
+    fn increment(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.set_status_negative_zero(result);
+        result
+    }
+
+    /// Subtract one from a value.
+    fn decrement(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.set_status_negative_zero(result);
+        result
+    }
+
     /// Set the accumulator to the given value and update the negative and zero
     /// status bits.
     fn set_accumulator(&mut self, value: u8) {
@@ -243,104 +853,95 @@ impl CPU {
         self.status.set_zero(value);
     }
 
-    /// Pop a value off of the stack and advance the stack pointer.
+    /// Pop a value off of the stack and advance the stack pointer, wrapping
+    /// around within the stack page.
     fn stack_pop<T: MemoryValue>(&mut self) -> T {
-        let sp = self.stack_pointer.wrapping_add(1);
-
-        self.stack_pointer = if T::BITS / 8 == 2 {
-            sp.wrapping_add(1)
-        } else {
-            sp
-        };
-
-        self.memory.read(sp.into())
+        T::read_bytes(0, |_| {
+            self.stack_pointer = self.stack_pointer.wrapping_add(1);
+            self.read_u8(self.stack_pointer.into())
+        })
     }
 
-    /// Push a value onto the stack and retreat the stack pointer.
-    fn stack_push<T: MemoryValue + Shr<u8>>(&mut self, value: T) {
-        if T::BITS / 8 == 2 {
-            self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-        }
+    /// Push a value onto the stack and retreat the stack pointer, wrapping
+    /// around within the stack page.
+    fn stack_push<T: MemoryValue>(&mut self, value: T) {
+        let size = (T::BITS / 8) as u8;
+        let start = self.stack_pointer.wrapping_sub(size - 1);
+
+        T::write_bytes(0, value, |offset, byte| {
+            self.write_u8(start.wrapping_add(offset as u8).into(), byte);
+        });
 
-        self.memory.write(self.stack_pointer.into(), value);
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(size);
     }
 }
 
 impl Cpu6502 for CPU {
     fn adc(&mut self, addr: u16) {
-        self.add_to_accumulator(self.memory.read(addr));
+        let value = self.read(addr);
+        self.add_with_carry(value);
     }
 
     fn and(&mut self, addr: u16) {
-        self.set_accumulator(self.accumulator & self.memory.read::<u8>(addr));
+        let value: u8 = self.read(addr);
+        self.set_accumulator(self.accumulator & value);
     }
 
     fn asl(&mut self, addr: Option<u16>) {
-        let value = match addr {
-            Some(addr) => self.memory.read(addr),
-            None => self.accumulator,
-        };
-
-        let result = value << 1;
-
-        self.status.set(Status::Carry, value >> 7 == 1);
-        self.set_status_negative_zero(result);
-
-        match addr {
-            Some(addr) => self.memory.write(addr, result),
-            None => self.accumulator = result,
-        };
+        self.modify(addr, Self::shift_left);
     }
 
-    fn bcc(&mut self, displacement: u16) {
-        self.branch(displacement, !self.status.contains(Status::Carry));
+    fn bcc(&mut self, addr: u16) {
+        self.branch(addr, !self.status.contains(Status::Carry));
     }
 
-    fn bcs(&mut self, displacement: u16) {
-        self.branch(displacement, self.status.contains(Status::Carry));
+    fn bcs(&mut self, addr: u16) {
+        self.branch(addr, self.status.contains(Status::Carry));
     }
 
-    fn beq(&mut self, displacement: u16) {
-        self.branch(displacement, self.status.contains(Status::Zero));
+    fn beq(&mut self, addr: u16) {
+        self.branch(addr, self.status.contains(Status::Zero));
     }
 
     fn bit(&mut self, addr: u16) {
-        let value: u8 = self.memory.read(addr);
+        let value: u8 = self.read(addr);
 
         self.status.set_zero(self.accumulator & value);
         self.status.set_overflow(value & 0b0100_0000 != 0);
         self.status.set_negative(value);
     }
 
-    fn bmi(&mut self, displacement: u16) {
-        self.branch(displacement, self.status.contains(Status::Negative));
+    fn bmi(&mut self, addr: u16) {
+        self.branch(addr, self.status.contains(Status::Negative));
     }
 
-    fn bne(&mut self, displacement: u16) {
-        self.branch(displacement, !self.status.contains(Status::Zero));
+    fn bne(&mut self, addr: u16) {
+        self.branch(addr, !self.status.contains(Status::Zero));
     }
 
-    fn bpl(&mut self, displacement: u16) {
-        self.branch(displacement, !self.status.contains(Status::Negative));
+    fn bpl(&mut self, addr: u16) {
+        self.branch(addr, !self.status.contains(Status::Negative));
     }
 
     fn brk(&mut self) {
-        self.stack_push(self.program_counter);
+        // BRK is followed by a padding byte, which the return address skips.
+        self.stack_push(self.program_counter.wrapping_add(1));
         self.php();
 
-        self.program_counter = self.memory.read(memory::INTERRUPT);
+        self.program_counter = self.read(memory::INTERRUPT);
+
+        self.status.set(Status::InterruptDisable, true);
 
         self.status.set(Status::Break, true);
         self.status.set(Status::Break2, true);
     }
 
-    fn bvc(&mut self, displacement: u16) {
-        self.branch(displacement, !self.status.contains(Status::Overflow));
+    fn bvc(&mut self, addr: u16) {
+        self.branch(addr, !self.status.contains(Status::Overflow));
     }
 
-    fn bvs(&mut self, displacement: u16) {
-        self.branch(displacement, self.status.contains(Status::Overflow));
+    fn bvs(&mut self, addr: u16) {
+        self.branch(addr, self.status.contains(Status::Overflow));
     }
 
     fn clc(&mut self) {
@@ -360,24 +961,27 @@ impl Cpu6502 for CPU {
     }
 
     fn cmp(&mut self, addr: u16) {
-        self.compare(self.accumulator, addr);
+        let value = self.read(addr);
+        self.compare(self.accumulator, value);
     }
 
     fn cpx(&mut self, addr: u16) {
-        self.compare(self.index_x, addr);
+        let value = self.read(addr);
+        self.compare(self.index_x, value);
     }
 
     fn cpy(&mut self, addr: u16) {
-        self.compare(self.index_y, addr);
+        let value = self.read(addr);
+        self.compare(self.index_y, value);
     }
 
     fn dec(&mut self, addr: u16) {
-        let value: u8 = self.memory.read(addr);
-        let result = value.wrapping_sub(1);
-
-        self.memory.write(addr, result);
+        self.modify(Some(addr), Self::decrement);
+    }
 
-        self.set_status_negative_zero(result);
+    fn dcp(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::decrement);
+        self.compare(self.accumulator, value);
     }
 
     fn dex(&mut self) {
@@ -389,16 +993,12 @@ impl Cpu6502 for CPU {
     }
 
     fn eor(&mut self, addr: u16) {
-        self.set_accumulator(self.accumulator ^ self.memory.read::<u8>(addr));
+        let value: u8 = self.read(addr);
+        self.set_accumulator(self.accumulator ^ value);
     }
 
     fn inc(&mut self, addr: u16) {
-        let value: u8 = self.memory.read(addr);
-        let result = value.wrapping_add(1);
-
-        self.memory.write(addr, result);
-
-        self.set_status_negative_zero(result);
+        self.modify(Some(addr), Self::increment);
     }
 
     fn inx(&mut self) {
@@ -409,46 +1009,48 @@ impl Cpu6502 for CPU {
         self.set_index_y(self.index_y.wrapping_add(1));
     }
 
+    fn isb(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::increment);
+        self.subtract_with_carry(value);
+    }
+
     fn jmp(&mut self, addr: u16) {
         self.program_counter = addr;
     }
 
     fn jsr(&mut self, addr: u16) {
-        self.stack_push(self.program_counter + 1);
+        self.stack_push(self.program_counter.wrapping_sub(1));
         self.program_counter = addr;
     }
 
+    fn lax(&mut self, addr: u16) {
+        let value = self.read(addr);
+        self.set_accumulator(value);
+        self.index_x = value;
+    }
+
     fn lda(&mut self, addr: u16) {
-        self.set_accumulator(self.memory.read(addr));
+        let value = self.read(addr);
+        self.set_accumulator(value);
     }
 
     fn ldx(&mut self, addr: u16) {
-        self.set_index_x(self.memory.read(addr));
+        let value = self.read(addr);
+        self.set_index_x(value);
     }
 
     fn ldy(&mut self, addr: u16) {
-        self.set_index_y(self.memory.read(addr));
+        let value = self.read(addr);
+        self.set_index_y(value);
     }
 
     fn lsr(&mut self, addr: Option<u16>) {
-        let initial = match addr {
-            Some(addr) => self.memory.read(addr),
-            None => self.accumulator,
-        };
-
-        let result = initial >> 1;
-
-        self.status.set(Status::Carry, initial % 2 == 1);
-        self.set_status_negative_zero(result);
-
-        match addr {
-            Some(addr) => self.memory.write(addr, result),
-            None => self.accumulator = result,
-        }
+        self.modify(addr, Self::shift_right);
     }
 
     fn ora(&mut self, addr: u16) {
-        self.set_accumulator(self.accumulator | self.memory.read::<u8>(addr));
+        let value: u8 = self.read(addr);
+        self.set_accumulator(self.accumulator | value);
     }
 
     fn pha(&mut self) {
@@ -456,7 +1058,7 @@ impl Cpu6502 for CPU {
     }
 
     fn php(&mut self) {
-        self.stack_push(self.status.bits());
+        self.stack_push((self.status | Status::Break | Status::Break2).bits());
     }
 
     fn pla(&mut self) {
@@ -468,55 +1070,40 @@ impl Cpu6502 for CPU {
         self.status = Status::from(self.stack_pop::<u8>());
     }
 
-    fn rol(&mut self, addr: Option<u16>) {
-        let initial = match addr {
-            Some(addr) => self.memory.read(addr),
-            None => self.accumulator,
-        };
-
-        let result = (initial << 1) | self.status.and(Status::Carry).bits();
-
-        self.status.set(Status::Carry, initial >> 7 == 1);
-        self.set_status_negative_zero(result);
+    fn rla(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::rotate_left);
+        self.set_accumulator(self.accumulator & value);
+    }
 
-        match addr {
-            Some(addr) => self.memory.write(addr, result),
-            None => self.accumulator = result,
-        };
+    fn rol(&mut self, addr: Option<u16>) {
+        self.modify(addr, Self::rotate_left);
     }
 
     fn ror(&mut self, addr: Option<u16>) {
-        let initial = match addr {
-            Some(addr) => self.memory.read(addr),
-            None => self.accumulator,
-        };
-
-        let result = (initial >> 1) | (self.status.and(Status::Carry).bits() << 7);
-
-        self.status.set(Status::Carry, initial & 1 == 1);
-        self.set_status_negative_zero(result);
+        self.modify(addr, Self::rotate_right);
+    }
 
-        match addr {
-            Some(addr) => self.memory.write(addr, result),
-            None => self.accumulator = result,
-        };
+    fn rra(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::rotate_right);
+        self.add_with_carry(value);
     }
 
     fn rti(&mut self) {
         self.plp();
-        self.rts();
+        self.program_counter = self.stack_pop();
     }
 
     fn rts(&mut self) {
-        self.program_counter = self.stack_pop();
+        self.program_counter = self.stack_pop::<u16>().wrapping_add(1);
     }
 
     fn sbc(&mut self, addr: u16) {
-        self.add_to_accumulator(
-            (self.memory.read::<u8>(addr) as i8)
-                .wrapping_neg()
-                .wrapping_sub(1) as u8,
-        );
+        let value = self.read(addr);
+        self.subtract_with_carry(value);
+    }
+
+    fn sax(&mut self, addr: u16) {
+        self.write(addr, self.accumulator & self.index_x);
     }
 
     fn sec(&mut self) {
@@ -531,16 +1118,26 @@ impl Cpu6502 for CPU {
         self.status.set(Status::InterruptDisable, true);
     }
 
+    fn slo(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::shift_left);
+        self.set_accumulator(self.accumulator | value);
+    }
+
+    fn sre(&mut self, addr: u16) {
+        let value = self.modify(Some(addr), Self::shift_right);
+        self.set_accumulator(self.accumulator ^ value);
+    }
+
     fn sta(&mut self, addr: u16) {
-        self.memory.write(addr, self.accumulator);
+        self.write(addr, self.accumulator);
     }
 
     fn stx(&mut self, addr: u16) {
-        self.memory.write(addr, self.index_x);
+        self.write(addr, self.index_x);
     }
 
     fn sty(&mut self, addr: u16) {
-        self.memory.write(addr, self.index_y);
+        self.write(addr, self.index_y);
     }
 
     fn tax(&mut self) {
@@ -567,3 +1164,29 @@ impl Cpu6502 for CPU {
         self.set_accumulator(self.index_y);
     }
 }
+
+/// Add an index register to a base address, returning the result and whether
+/// it's on a different page from the base.
+fn index_address(base: u16, index: u8) -> (Option<u16>, bool) {
+    let addr = base.wrapping_add(index.into());
+    (Some(addr), addr & 0xff00 != base & 0xff00)
+}
+
+/// Subtract a value and the inverse of the carry bit from another in
+/// binary-coded decimal, as the NMOS 6502 does, including for invalid BCD
+/// operands.
+fn subtract_decimal(a: u8, b: u8, carry: bool) -> u8 {
+    let (a, b) = (i16::from(a), i16::from(b));
+
+    let mut low = (a & 0x0f) - (b & 0x0f) + i16::from(carry) - 1;
+    if low < 0 {
+        low = ((low - 0x06) & 0x0f) - 0x10;
+    }
+
+    let mut difference = (a & 0xf0) - (b & 0xf0) + low;
+    if difference < 0 {
+        difference -= 0x60;
+    }
+
+    difference as u8
+}
@@ -1,20 +1,72 @@
-#[derive(Debug, Clone, Copy)]
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Mos6502,
+    #[default]
     Nes2A03,
 }
 
-impl Default for Mode {
-    fn default() -> Self {
-        Self::Nes2A03
-    }
-}
-
 impl Mode {
+    /// Every supported mode.
+    pub const ALL: [Mode; 2] = [Self::Mos6502, Self::Nes2A03];
+
     pub fn program_rom(&self) -> usize {
         match self {
             Self::Mos6502 => 0x0600,
             Self::Nes2A03 => 0x8000,
         }
     }
+
+    /// Whether the mode has an APU, which only the 2A03 does.
+    pub fn has_apu(&self) -> bool {
+        *self == Self::Nes2A03
+    }
+
+    /// The lowercase name of the mode, as accepted by [`Mode::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mos6502 => "mos6502",
+            Self::Nes2A03 => "nes2a03",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(Mode::name).collect();
+                format!("unknown mode '{}', expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("mos6502".parse(), Ok(Mode::Mos6502));
+        assert_eq!("NES2A03".parse(), Ok(Mode::Nes2A03));
+        assert_eq!(
+            "z80".parse::<Mode>(),
+            Err("unknown mode 'z80', expected one of mos6502, nes2a03".to_string())
+        );
+
+        for mode in Mode::ALL {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+    }
 }
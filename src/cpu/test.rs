@@ -1,5 +1,12 @@
 use super::*;
 
+/// Assemble a test program at the start of program ROM.
+fn asm(source: &str) -> Vec<u8> {
+    assembler::assemble(source, Mode::default().program_rom() as u16)
+        .unwrap()
+        .bytes
+}
+
 #[test]
 fn test_0x00_brk() {
     let mut cpu = CPU::new();
@@ -12,8 +19,11 @@ fn test_0x00_brk() {
     cpu.run();
 
     assert_eq!(cpu.stack_pop::<u8>(), status);
-    assert_eq!(cpu.stack_pop::<u16>(), pc + 1);
+    // BRK is a two-byte instruction whose second byte is padding, so RTI
+    // returns past it.
+    assert_eq!(cpu.stack_pop::<u16>(), pc + 2);
 
+    assert!(cpu.status.contains(Status::InterruptDisable));
     assert!(cpu.status.contains(Status::Break));
     assert!(cpu.status.contains(Status::Break2));
 }
@@ -26,6 +36,46 @@ fn test_0xa0_ldy_immediate() {
     assert_eq!(cpu.index_y, 0x05);
 }
 
+#[test]
+fn test_0xb4_ldy_zero_page_x() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xb4, 0x10, 0x00]);
+    cpu.reset();
+
+    cpu.index_x = 0x01;
+    cpu.memory.write(0x10, 0x99_u8);
+    cpu.memory.write(0x11, 0x42_u8);
+    let start = cpu.cycles;
+    cpu.step();
+
+    assert_eq!(cpu.index_y, 0x42);
+    assert_eq!(cpu.cycles - start, 4);
+}
+
+#[test]
+fn test_0xbc_ldy_absolute_x() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xbc, 0x00, 0x02, // LDY $0200,X, 4 cycles
+        0xbc, 0xff, 0x02, // LDY $02FF,X, 5 cycles
+    ]);
+    cpu.reset();
+
+    cpu.index_x = 0x01;
+    cpu.memory.write(0x0201, 0x42_u8);
+    cpu.memory.write(0x0300, 0x43_u8);
+
+    let start = cpu.cycles;
+    cpu.step();
+    assert_eq!(cpu.index_y, 0x42);
+    assert_eq!(cpu.cycles - start, 4);
+
+    let start = cpu.cycles;
+    cpu.step();
+    assert_eq!(cpu.index_y, 0x43);
+    assert_eq!(cpu.cycles - start, 5);
+}
+
 #[test]
 fn test_0xa1_lda_indirect_x() {
     let mut cpu = CPU::new();
@@ -177,8 +227,12 @@ fn test_0xd8_cld() {
 #[test]
 fn test_0x58_cli() {
     let mut cpu = CPU::new();
+    cpu.load(vec![0x58, 0x00]);
+    cpu.reset();
     cpu.status |= Status::InterruptDisable;
-    cpu.load_and_run(vec![0x58, 0x00]);
+
+    // Step over CLI only, since the BRK after it sets the flag again.
+    cpu.step();
 
     assert!(!cpu.status.contains(Status::InterruptDisable));
 }
@@ -452,8 +506,9 @@ fn test_0x40_rti() {
 
     cpu.run();
 
+    // The BRK returned to pushes the address after its padding byte.
     assert_eq!(cpu.stack_pop::<u8>(), status);
-    assert_eq!(cpu.stack_pop::<u16>(), pc + 1);
+    assert_eq!(cpu.stack_pop::<u16>(), pc + 2);
     // assert_eq!(
     //     cpu.memory
     //         .read::<u16>(cpu.stack_pointer.wrapping_add(1).into()),
@@ -461,3 +516,613 @@ fn test_0x40_rti() {
     // );
     // assert_eq!(cpu.status.bits(), status);
 }
+
+#[test]
+fn test_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![
+        0xa9, 0x42, // LDA #$42, 2 cycles
+        0x8d, 0x00, 0x02, // STA $0200, 4 cycles
+        0x00, // BRK, 7 cycles
+    ]);
+
+    assert_eq!(cpu.cycles, 13);
+}
+
+#[test]
+fn test_apu_registers() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm("
+        lda #$01    ; enable pulse 1
+        sta $4015
+        lda #$08    ; load the length counter
+        sta $4003
+        lda $4015
+        brk
+    "));
+
+    assert_eq!(cpu.accumulator, 0x01);
+    assert_eq!(cpu.memory.read::<u8>(0x4003), 0x00);
+}
+
+#[test]
+fn test_apu_registers_mos6502() {
+    let mut cpu = CPU::new();
+    cpu.mode = Mode::Mos6502;
+    cpu.load_and_run(vec![
+        0xa9, 0x08, // LDA #$08
+        0x8d, 0x03, 0x40, // STA $4003
+        0x00,
+    ]);
+
+    assert_eq!(cpu.memory.read::<u8>(0x4003), 0x08);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(memory::INTERRUPT, 0x9000_u16);
+    cpu.memory.write(0x9000, 0x00_u8);
+
+    cpu.load(asm("
+                cli
+        @loop:  jmp @loop
+    "));
+    cpu.reset();
+
+    while cpu.program_counter != 0x9000 {
+        cpu.step();
+    }
+
+    assert!(cpu.cycles >= 29828);
+    assert!(cpu.status.contains(Status::InterruptDisable));
+    assert_eq!(cpu.stack_pop::<u8>() & Status::Break.bits(), 0);
+    assert_eq!(cpu.stack_pop::<u16>(), 0x8001);
+}
+
+#[test]
+fn test_dmc_stalls_cpu() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0xc000, 0xaa_u8);
+    cpu.load(asm("
+        lda #$00    ; sample at $C000, one byte long
+        sta $4012
+        sta $4013
+        lda #$10    ; enable the DMC
+        sta $4015
+        nop
+    "));
+    cpu.reset();
+
+    for _ in 0..6 {
+        cpu.step();
+    }
+
+    assert_eq!(cpu.cycles, 2 + 4 + 4 + 2 + 4 + 4 + 2);
+    assert_eq!(cpu.apu.read_status() & 0x10, 0);
+}
+
+#[test]
+fn test_run_frames() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x4c, 0x00, 0x80]); // JMP $8000
+    cpu.reset();
+
+    assert!(cpu.run_frames(2));
+    assert!(cpu.cycles >= 2 * CYCLES_PER_FRAME);
+    assert!(cpu.cycles < 2 * CYCLES_PER_FRAME + 3);
+}
+
+#[test]
+fn test_run_frames_halt() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xea, 0x00]);
+    cpu.reset();
+
+    assert!(!cpu.run_frames(1));
+    assert_eq!(cpu.cycles, 9);
+}
+
+#[test]
+fn test_0x20_jsr_0x60_rts() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm("
+                jsr sub
+                lda #$42
+                brk
+        sub:    ldx #$01
+                rts
+    "));
+
+    assert_eq!(cpu.index_x, 0x01);
+    assert_eq!(cpu.accumulator, 0x42);
+}
+
+#[test]
+fn test_0x20_jsr_return_address() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x20, 0x00, 0x90]);
+    cpu.reset();
+    cpu.step();
+
+    assert_eq!(cpu.program_counter, 0x9000);
+    assert_eq!(cpu.stack_pop::<u16>(), 0x8002);
+}
+
+#[test]
+fn test_0xd0_bne() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm("
+                ldx #$03
+        @loop:  iny
+                dex
+                bne @loop
+                brk
+    "));
+
+    assert_eq!(cpu.index_y, 0x03);
+}
+
+#[test]
+fn test_0xf0_beq_forward() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm("
+                lda #$00
+                beq @done
+                lda #$42
+        @done:  brk
+    "));
+
+    assert_eq!(cpu.accumulator, 0x00);
+}
+
+#[test]
+fn test_0x69_adc_carry() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![0xa9, 0xff, 0x69, 0x02, 0x00]);
+
+    assert_eq!(cpu.accumulator, 0x01);
+    assert!(cpu.status.contains(Status::Carry));
+    assert!(!cpu.status.contains(Status::Overflow));
+}
+
+#[test]
+fn test_0x69_adc_overflow() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![0xa9, 0x7f, 0x69, 0x01, 0x00]);
+
+    assert_eq!(cpu.accumulator, 0x80);
+    assert!(!cpu.status.contains(Status::Carry));
+    assert!(cpu.status.contains(Status::Overflow));
+}
+
+#[test]
+fn test_run_subroutine() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xe8, // INX
+        0x60, // RTS
+    ]);
+    cpu.reset();
+
+    assert!(cpu.run_subroutine(0x8000, 100));
+    assert_eq!(cpu.index_x, 1);
+    assert_eq!(u8::from(cpu.stack_pointer), memory::STACK_RESET);
+}
+
+#[test]
+fn test_run_subroutine_timeout() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x4c, 0x00, 0x80]); // JMP $8000
+    cpu.reset();
+
+    assert!(!cpu.run_subroutine(0x8000, 100));
+}
+
+#[test]
+fn test_controller_read() {
+    use crate::controller::Button;
+
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xa9, 0x01, // LDA #$01
+        0x8d, 0x16, 0x40, // STA $4016, strobe high
+        0xa9, 0x00, // LDA #$00
+        0x8d, 0x16, 0x40, // STA $4016, strobe low
+        0xa2, 0x08, // LDX #$08
+        0xad, 0x16, 0x40, // loop: LDA $4016
+        0x4a, // LSR A
+        0x26, 0x00, // ROL $00
+        0xad, 0x17, 0x40, // LDA $4017
+        0x4a, // LSR A
+        0x26, 0x01, // ROL $01
+        0xca, // DEX
+        0xd0, 0xf0, // BNE loop
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.controllers[0].press(Button::A | Button::Up);
+    cpu.controllers[1].press(Button::B | Button::Right);
+    cpu.run();
+
+    // Buttons are read starting from A, so they're rotated in reverse order.
+    assert_eq!(cpu.memory.read::<u8>(0x00), 0b1000_1000);
+    assert_eq!(cpu.memory.read::<u8>(0x01), 0b0100_0001);
+}
+
+#[test]
+fn test_try_step_unknown_opcode() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xe8, 0x02]); // INX, then an unrecognized opcode
+    cpu.reset();
+
+    assert_eq!(cpu.try_step(), Ok(()));
+    assert_eq!(
+        cpu.try_step(),
+        Err(Error::UnknownOpcode {
+            opcode: 0x02,
+            addr: 0x8001
+        })
+    );
+    assert_eq!(cpu.program_counter, 0x8001);
+}
+
+#[test]
+#[should_panic(expected = "Opcode 2 is not recognized at $8000")]
+fn test_step_unknown_opcode() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x02]);
+    cpu.reset();
+    cpu.step();
+}
+
+#[test]
+fn test_load_at() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x00]);
+    cpu.load_at(&[0xe8, 0x00], 0x1234);
+    cpu.reset();
+
+    assert_eq!(cpu.program_counter, 0x8000);
+    assert_eq!(cpu.peek(0x1234), 0xe8);
+
+    cpu.program_counter = 0x1234;
+    cpu.run();
+    assert_eq!(cpu.index_x, 1);
+}
+
+#[test]
+fn test_0x6c_jmp_indirect_page_wrap() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0x02ff, 0x00_u8);
+    cpu.memory.write(0x0200, 0x03_u8); // read instead of $0300
+    cpu.memory.write(0x0300, 0x04_u8);
+    cpu.load(vec![0x6c, 0xff, 0x02]);
+    cpu.reset();
+    cpu.step();
+
+    assert_eq!(cpu.program_counter, 0x0300);
+}
+
+#[test]
+fn test_indirect_zero_page_wrap() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0xff, 0x34_u8);
+    cpu.memory.write(0x00, 0x12_u8);
+    cpu.memory.write(0x1234, 0x42_u8);
+    cpu.memory.write(0x1235, 0x43_u8);
+    cpu.load(vec![
+        0xa2, 0x7f, // LDX #$7F
+        0xa1, 0x80, // LDA ($80,X), pointer at $FF
+        0xaa, // TAX
+        0xa0, 0x01, // LDY #$01
+        0xb1, 0xff, // LDA ($FF),Y
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.index_x, 0x42);
+    assert_eq!(cpu.accumulator, 0x43);
+}
+
+#[test]
+fn test_0x08_php_sets_break() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x08, 0x00]);
+    cpu.reset();
+    cpu.status = Status::from(0x00);
+    cpu.step();
+
+    assert_eq!(cpu.stack_pop::<u8>(), 0x30);
+}
+
+#[test]
+fn test_stack_wrap() {
+    let mut cpu = CPU::new();
+    cpu.stack_pointer = StackPointer(0x00);
+    cpu.stack_push(0x1234_u16);
+
+    assert_eq!(u8::from(cpu.stack_pointer), 0xfe);
+    assert_eq!(cpu.memory.read::<u8>(0x0100), 0x12);
+    assert_eq!(cpu.memory.read::<u8>(0x01ff), 0x34);
+    assert_eq!(cpu.stack_pop::<u16>(), 0x1234);
+    assert_eq!(u8::from(cpu.stack_pointer), 0x00);
+}
+
+#[test]
+fn test_page_cross_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xa2, 0x01, // LDX #$01, 2 cycles
+        0xbd, 0x00, 0x02, // LDA $0200,X, 4 cycles
+        0xbd, 0xff, 0x02, // LDA $02FF,X, 5 cycles
+        0x9d, 0xff, 0x02, // STA $02FF,X, 5 cycles
+    ]);
+    cpu.reset();
+
+    for _ in 0..4 {
+        cpu.step();
+    }
+
+    assert_eq!(cpu.cycles, 16);
+}
+
+#[test]
+fn test_branch_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0x18; 0x100]);
+    cpu.load_at(&[0x90, 0x00], 0x8000); // BCC +0, taken
+    cpu.load_at(&[0xb0, 0x00], 0x8002); // BCS +0, not taken
+    cpu.load_at(&[0x90, 0x7f], 0x8004); // BCC to $8085, taken
+    cpu.load_at(&[0x90, 0x7f], 0x8085); // BCC to $8106, taken across a page
+    cpu.reset();
+
+    let mut cycles = vec![];
+    for _ in 0..4 {
+        let start = cpu.cycles;
+        cpu.step();
+        cycles.push(cpu.cycles - start);
+    }
+
+    assert_eq!(cycles, vec![3, 2, 3, 4]);
+    assert_eq!(cpu.program_counter, 0x8106);
+}
+
+#[test]
+fn test_unofficial_lax_sax() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0x10, 0x8f_u8);
+    cpu.load(vec![
+        0xa7, 0x10, // LAX $10
+        0xa9, 0xf0, // LDA #$F0
+        0x87, 0x11, // SAX $11
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.index_x, 0x8f);
+    assert_eq!(cpu.memory.read::<u8>(0x11), 0x80);
+}
+
+#[test]
+fn test_unofficial_read_modify_write() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0x10, 0x41_u8);
+    cpu.memory.write(0x11, 0x80_u8);
+    cpu.memory.write(0x12, 0x03_u8);
+    cpu.memory.write(0x13, 0x01_u8);
+    cpu.load(vec![
+        0xa9, 0x40, // LDA #$40
+        0xc7, 0x10, // DCP $10: $10 = $40, A == $40
+        0x07, 0x11, // SLO $11: $11 = $00, carry set, A = $40
+        0x47, 0x12, // SRE $12: $12 = $01, carry set, A = $41
+        0x67, 0x13, // RRA $13: $13 = $80, A = $41 + $80 + 1
+        0x00,
+    ]);
+    cpu.reset();
+
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.memory.read::<u8>(0x10), 0x40);
+    assert!(cpu.status.contains(Status::Zero | Status::Carry));
+
+    cpu.step();
+    assert_eq!(cpu.memory.read::<u8>(0x11), 0x00);
+    assert_eq!(cpu.accumulator, 0x40);
+
+    cpu.step();
+    assert_eq!(cpu.memory.read::<u8>(0x12), 0x01);
+    assert_eq!(cpu.accumulator, 0x41);
+
+    cpu.run();
+    assert_eq!(cpu.memory.read::<u8>(0x13), 0x80);
+    assert_eq!(cpu.accumulator, 0xc2);
+}
+
+#[test]
+fn test_unofficial_isb_rla() {
+    let mut cpu = CPU::new();
+    cpu.memory.write(0x10, 0x0f_u8);
+    cpu.memory.write(0x11, 0x81_u8);
+    cpu.load(vec![
+        0x38, // SEC
+        0xa9, 0x20, // LDA #$20
+        0xe7, 0x10, // ISB $10: $10 = $10, A = $10
+        0x27, 0x11, // RLA $11: $11 = $03, A = $00
+        0x00,
+    ]);
+    cpu.reset();
+
+    for _ in 0..3 {
+        cpu.step();
+    }
+    assert_eq!(cpu.memory.read::<u8>(0x10), 0x10);
+    assert_eq!(cpu.accumulator, 0x10);
+
+    cpu.run();
+    assert_eq!(cpu.memory.read::<u8>(0x11), 0x03);
+    assert_eq!(cpu.accumulator, 0x00);
+    assert!(cpu.status.contains(Status::Carry | Status::Zero));
+}
+
+#[test]
+fn test_unofficial_nop_operands() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0x1a, // NOP
+        0x80, 0xff, // NOP #$FF
+        0x44, 0x10, // NOP $10
+        0x0c, 0x00, 0x02, // NOP $0200
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.accumulator, 0);
+    assert_eq!(cpu.cycles, 2 + 2 + 3 + 4 + 7);
+}
+
+#[test]
+fn test_adc_decimal() {
+    let mut cpu = CPU::new();
+    cpu.mode = Mode::Mos6502;
+    cpu.load(vec![
+        0xf8, // SED
+        0x38, // SEC
+        0xa9, 0x58, // LDA #$58
+        0x69, 0x46, // ADC #$46
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.accumulator, 0x05);
+    assert!(cpu.status.contains(Status::Carry));
+}
+
+#[test]
+fn test_sbc_decimal() {
+    let mut cpu = CPU::new();
+    cpu.mode = Mode::Mos6502;
+    cpu.load(vec![
+        0xf8, // SED
+        0x18, // CLC
+        0xa9, 0x40, // LDA #$40
+        0xe9, 0x13, // SBC #$13
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.accumulator, 0x26);
+    assert!(cpu.status.contains(Status::Carry));
+}
+
+#[test]
+fn test_decimal_ignored_on_2a03() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xf8, // SED
+        0xa9, 0x09, // LDA #$09
+        0x69, 0x01, // ADC #$01
+        0x00,
+    ]);
+    cpu.reset();
+    cpu.run();
+
+    assert_eq!(cpu.accumulator, 0x0a);
+}
+
+#[test]
+fn test_opcode_table() {
+    for (code, opcode) in opcodes::OPCODES.iter().enumerate() {
+        if let Some(opcode) = opcode {
+            assert_eq!(usize::from(opcode.code), code);
+        }
+    }
+
+    assert_eq!(
+        opcodes::OPCODES.iter().flatten().count(),
+        opcodes::CPU_OPS_CODES.len()
+    );
+    assert!(opcodes::decode(0x02).is_none());
+}
+
+#[test]
+fn test_mnemonics() {
+    for opcode in opcodes::CPU_OPS_CODES.iter() {
+        assert_eq!(
+            opcode.instruction.mnemonic(),
+            format!("{:?}", opcode.instruction).to_uppercase()
+        );
+    }
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let mut cpu = CPU::new();
+    cpu.load(asm("
+                lda #$0f    ; enable every channel but the DMC
+                sta $4015
+                lda #$bf    ; pulse 1 at constant full volume
+                sta $4000
+                lda #$fd
+                sta $4002
+                lda #$08
+                sta $4003
+        @loop:  inc $10
+                ldx $10
+                txa
+                sta $0200,y
+                iny
+                jmp @loop
+    "));
+    cpu.reset();
+    cpu.controllers[0].press(controller::Button::Start);
+    cpu.run_frames(2);
+    let state = cpu.save_state();
+
+    cpu.apu.take_samples();
+    cpu.run_frames(3);
+    let expected = (cpu.save_state(), cpu.apu.take_samples());
+
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.save_state(), state);
+    assert!(cpu.apu.samples().is_empty());
+
+    cpu.run_frames(3);
+    assert_eq!((cpu.save_state(), cpu.apu.take_samples()), expected);
+    assert_eq!(cpu.controllers[0].buttons(), controller::Button::Start);
+}
+
+#[test]
+fn test_load_invalid_state() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm("
+        lda #$42
+        brk
+    "));
+    let state = cpu.save_state();
+
+    let mut other = CPU::new();
+    other.mode = Mode::Mos6502;
+    other.accumulator = 0x01;
+
+    let truncated = other.load_state(&state[..state.len() - 1]).unwrap_err();
+    assert_eq!(truncated.to_string(), "save state is truncated");
+
+    let mut unknown_mode = state.clone();
+    // The mode follows the six byte header and seven bytes of registers.
+    unknown_mode[13] = 0xff;
+    assert!(other.load_state(&unknown_mode).is_err());
+
+    // A failed load leaves the CPU as it was.
+    assert_eq!(other.accumulator, 0x01);
+    assert_eq!(other.mode, Mode::Mos6502);
+
+    other.load_state(&state).unwrap();
+    assert_eq!(other.accumulator, 0x42);
+    assert_eq!(other.mode, Mode::Nes2A03);
+}
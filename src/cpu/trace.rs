@@ -0,0 +1,464 @@
+use std::{
+    fmt::{self, Debug, Write as _},
+    io::Write,
+    str::FromStr,
+};
+
+use super::{
+    instructions::Instruction,
+    mode::Mode,
+    opcodes::{self, AddressingMode, OpCode},
+    status::Status,
+    CPU,
+};
+
+/// Number of PPU dots that pass in each CPU cycle on NTSC.
+const DOTS_PER_CYCLE: u64 = 3;
+const DOTS_PER_SCANLINE: u64 = 341;
+const SCANLINES_PER_FRAME: u64 = 262;
+
+/// Memory-mapped PPU and APU registers on the 2A03, which nestest.log shows as
+/// reading $FF rather than triggering their side effects.
+const IO_REGISTERS: std::ops::RangeInclusive<u16> = 0x2000..=0x401f;
+
+/// Receives events from the CPU as it executes a program. Register one by
+/// setting [`CPU::tracer`]; when none is registered, nothing is traced.
+pub trait Tracer: Debug {
+    /// Called before each instruction is executed, with the CPU in the state
+    /// the instruction will execute in.
+    fn instruction(&mut self, _cpu: &CPU) {}
+
+    /// Called for each byte of an instruction fetched through the program
+    /// counter.
+    fn fetch(&mut self, _addr: u16, _value: u8) {}
+}
+
+/// Formats a [`TraceWriter`] can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction, with its bytes, mnemonic, and the registers
+    /// before it executes.
+    Instruction,
+    /// One line per instruction in the format of nestest.log, with the operand
+    /// resolved against the registers and memory.
+    Nestest,
+    /// One line per byte fetched through the program counter.
+    Fetch,
+}
+
+impl TraceFormat {
+    /// Every supported format.
+    pub const ALL: [TraceFormat; 3] = [Self::Instruction, Self::Nestest, Self::Fetch];
+
+    /// The lowercase name of the format, as accepted by
+    /// [`TraceFormat::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Instruction => "instruction",
+            Self::Nestest => "nestest",
+            Self::Fetch => "fetch",
+        }
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(TraceFormat::name).collect();
+                format!(
+                    "unknown trace format '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Format the instruction at the program counter and the registers before it
+/// executes, e.g. `8000  A9 01     LDA   A:00 X:00 Y:00 P:34 SP:FD CYC:7`.
+pub fn format_instruction(cpu: &CPU) -> String {
+    let pc = cpu.program_counter;
+    let code = cpu.peek(pc);
+
+    let (len, mnemonic) = match opcodes::decode(code) {
+        Some(opcode) => (opcode.len, opcode.instruction.mnemonic()),
+        None => (1, "???"),
+    };
+
+    let mut bytes = String::new();
+    for i in 0..u16::from(len) {
+        let _ = write!(bytes, "{:02X} ", cpu.peek(pc.wrapping_add(i)));
+    }
+
+    format!(
+        "{:04X}  {:9} {:5} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+        pc,
+        bytes,
+        mnemonic,
+        cpu.accumulator,
+        cpu.index_x,
+        cpu.index_y,
+        cpu.status.bits(),
+        u8::from(cpu.stack_pointer),
+        cpu.cycles
+    )
+}
+
+/// Format the instruction at the program counter like a line of nestest.log,
+/// e.g. `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24
+/// SP:FD PPU:  0, 21 CYC:7`. The PPU position is derived from the number of
+/// cycles executed. Unofficial opcodes are marked with a `*`.
+pub fn format_nestest(cpu: &CPU) -> String {
+    let pc = cpu.program_counter;
+    let opcode = opcodes::decode(cpu.peek(pc));
+
+    let bytes: Vec<String> = (0..opcode.map_or(1, |op| u16::from(op.len)))
+        .map(|i| format!("{:02X}", cpu.peek(pc.wrapping_add(i))))
+        .collect();
+
+    let asm = match opcode {
+        Some(opcode) => {
+            let marker = if opcode.unofficial { '*' } else { ' ' };
+
+            match format_operand(cpu, opcode).as_str() {
+                "" => format!("{}{}", marker, opcode.instruction.mnemonic()),
+                operand => format!("{}{} {}", marker, opcode.instruction.mnemonic(), operand),
+            }
+        }
+        None => " ???".to_string(),
+    };
+
+    let dots = cpu.cycles * DOTS_PER_CYCLE;
+
+    format!(
+        "{:04X}  {:8} {:33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        pc,
+        bytes.join(" "),
+        asm,
+        cpu.accumulator,
+        cpu.index_x,
+        cpu.index_y,
+        // The break bits only exist in copies of the status pushed onto the
+        // stack.
+        (cpu.status & Status::Break.not() | Status::Break2).bits(),
+        u8::from(cpu.stack_pointer),
+        dots / DOTS_PER_SCANLINE % SCANLINES_PER_FRAME,
+        dots % DOTS_PER_SCANLINE,
+        cpu.cycles
+    )
+}
+
+/// Format the operand of the instruction at the program counter, along with
+/// the address it resolves to and the value there, as nestest.log does.
+fn format_operand(cpu: &CPU, opcode: &OpCode) -> String {
+    let pc = cpu.program_counter;
+    let byte = cpu.peek(pc.wrapping_add(1));
+    let word = u16::from_le_bytes([byte, cpu.peek(pc.wrapping_add(2))]);
+
+    match opcode.mode {
+        AddressingMode::Immediate => format!("#${:02X}", byte),
+
+        AddressingMode::ZeroPage => format!("${:02X} = {:02X}", byte, cpu.peek(byte.into())),
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let (register, index) = match opcode.mode {
+                AddressingMode::ZeroPageX => ('X', cpu.index_x),
+                _ => ('Y', cpu.index_y),
+            };
+            let addr = byte.wrapping_add(index);
+
+            format!(
+                "${:02X},{} @ {:02X} = {:02X}",
+                byte,
+                register,
+                addr,
+                cpu.peek(addr.into())
+            )
+        }
+
+        AddressingMode::Absolute => match opcode.instruction {
+            Instruction::Jmp | Instruction::Jsr => format!("${:04X}", word),
+            _ => format!("${:04X} = {:02X}", word, peek_operand(cpu, word)),
+        },
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let (register, index) = match opcode.mode {
+                AddressingMode::AbsoluteX => ('X', cpu.index_x),
+                _ => ('Y', cpu.index_y),
+            };
+            let addr = word.wrapping_add(index.into());
+
+            format!(
+                "${:04X},{} @ {:04X} = {:02X}",
+                word,
+                register,
+                addr,
+                peek_operand(cpu, addr)
+            )
+        }
+
+        AddressingMode::Indirect => {
+            // The high byte of the target is read without carrying into the
+            // high byte of the pointer.
+            let hi = (word & 0xff00) | (word.wrapping_add(1) & 0x00ff);
+            let target = u16::from_le_bytes([cpu.peek(word), cpu.peek(hi)]);
+
+            format!("(${:04X}) = {:04X}", word, target)
+        }
+        AddressingMode::IndirectX => {
+            let ptr = byte.wrapping_add(cpu.index_x);
+            let addr = zero_page_word(cpu, ptr);
+
+            format!(
+                "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                byte,
+                ptr,
+                addr,
+                peek_operand(cpu, addr)
+            )
+        }
+        AddressingMode::IndirectY => {
+            let base = zero_page_word(cpu, byte);
+            let addr = base.wrapping_add(cpu.index_y.into());
+
+            format!(
+                "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                byte,
+                base,
+                addr,
+                peek_operand(cpu, addr)
+            )
+        }
+
+        AddressingMode::Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${:04X}", target)
+        }
+
+        AddressingMode::NoneAddressing => match opcode.instruction {
+            Instruction::Asl | Instruction::Lsr | Instruction::Rol | Instruction::Ror => {
+                "A".to_string()
+            }
+            _ => String::new(),
+        },
+    }
+}
+
+/// Read the value at an operand's address without side effects, showing I/O
+/// registers as $FF.
+fn peek_operand(cpu: &CPU, addr: u16) -> u8 {
+    match cpu.mode {
+        Mode::Nes2A03 if IO_REGISTERS.contains(&addr) => 0xff,
+        _ => cpu.peek(addr),
+    }
+}
+
+/// Read a little-endian pointer from the zero page, wrapping around within it.
+fn zero_page_word(cpu: &CPU, ptr: u8) -> u16 {
+    u16::from_le_bytes([cpu.peek(ptr.into()), cpu.peek(ptr.wrapping_add(1).into())])
+}
+
+/// Format a single byte fetched through the program counter, e.g. `8000: A9`.
+pub fn format_fetch(addr: u16, value: u8) -> String {
+    format!("{:04X}: {:02X}", addr, value)
+}
+
+/// A tracer that writes a line for each event in the given format. Errors
+/// writing the trace are ignored, so that they don't interrupt the program.
+#[derive(Debug)]
+pub struct TraceWriter<W: Write + Debug> {
+    writer: W,
+    format: TraceFormat,
+}
+
+impl<W: Write + Debug> TraceWriter<W> {
+    pub fn new(writer: W, format: TraceFormat) -> Self {
+        Self { writer, format }
+    }
+}
+
+impl<W: Write + Debug> Tracer for TraceWriter<W> {
+    fn instruction(&mut self, cpu: &CPU) {
+        let line = match self.format {
+            TraceFormat::Instruction => format_instruction(cpu),
+            TraceFormat::Nestest => format_nestest(cpu),
+            TraceFormat::Fetch => return,
+        };
+
+        let _ = writeln!(self.writer, "{}", line);
+    }
+
+    fn fetch(&mut self, addr: u16, value: u8) {
+        if self.format == TraceFormat::Fetch {
+            let _ = writeln!(self.writer, "{}", format_fetch(addr, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// Records every event as a line of text.
+    #[derive(Debug, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Tracer for Recorder {
+        fn instruction(&mut self, cpu: &CPU) {
+            self.0.borrow_mut().push(format_instruction(cpu));
+        }
+
+        fn fetch(&mut self, addr: u16, value: u8) {
+            self.0.borrow_mut().push(format_fetch(addr, value));
+        }
+    }
+
+    #[test]
+    fn test_tracer() {
+        let lines = Rc::new(RefCell::new(vec![]));
+
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x42, 0x8d, 0x00, 0x02, 0x00]);
+        cpu.tracer = Some(Box::new(Recorder(lines.clone())));
+        cpu.reset();
+        cpu.run();
+
+        assert_eq!(
+            *lines.borrow(),
+            vec![
+                "8000  A9 42     LDA   A:00 X:00 Y:00 P:34 SP:FD CYC:0",
+                "8000: A9",
+                "8001: 42",
+                "8002  8D 00 02  STA   A:42 X:00 Y:00 P:34 SP:FD CYC:2",
+                "8002: 8D",
+                "8003: 00",
+                "8004: 02",
+                "8005  00        BRK   A:42 X:00 Y:00 P:34 SP:FD CYC:6",
+                "8005: 00",
+            ]
+        );
+    }
+
+    /// Load an instruction at the given address and point the program counter
+    /// at it.
+    fn cpu_at(pc: u16, bytes: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_at(bytes, pc);
+        cpu.program_counter = pc;
+        cpu
+    }
+
+    /// The disassembly column of a nestest line, including the unofficial
+    /// marker.
+    fn nestest_asm(cpu: &CPU) -> String {
+        format_nestest(cpu)[15..48].trim().to_string()
+    }
+
+    #[test]
+    fn test_format_nestest() {
+        let mut cpu = cpu_at(0xc000, &[0x4c, 0xf5, 0xc5]);
+        cpu.cycles = 7;
+
+        assert_eq!(
+            format_nestest(&cpu),
+            "C000  4C F5 C5  JMP $C5F5                       \
+             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+
+        cpu.cycles = 114;
+        assert!(format_nestest(&cpu).ends_with("PPU:  1,  1 CYC:114"));
+    }
+
+    #[test]
+    fn test_format_nestest_operands() {
+        let mut cpu = cpu_at(0x8000, &[0xa9, 0x01]);
+        assert_eq!(nestest_asm(&cpu), "LDA #$01");
+
+        cpu = cpu_at(0x8000, &[0x4a]);
+        assert_eq!(nestest_asm(&cpu), "LSR A");
+
+        cpu = cpu_at(0x8000, &[0xe8]);
+        assert_eq!(nestest_asm(&cpu), "INX");
+
+        cpu = cpu_at(0x8000, &[0x85, 0x10]);
+        cpu.poke(0x10, 0x3f);
+        assert_eq!(nestest_asm(&cpu), "STA $10 = 3F");
+
+        cpu = cpu_at(0x8000, &[0xb6, 0xff]);
+        cpu.index_y = 2;
+        cpu.poke(0x01, 0x3f);
+        assert_eq!(nestest_asm(&cpu), "LDX $FF,Y @ 01 = 3F");
+
+        cpu = cpu_at(0x8000, &[0xad, 0x00, 0x02]);
+        cpu.poke(0x0200, 0x3f);
+        assert_eq!(nestest_asm(&cpu), "LDA $0200 = 3F");
+
+        cpu = cpu_at(0x8000, &[0xbd, 0x00, 0x02]);
+        cpu.index_x = 5;
+        cpu.poke(0x0205, 0x3f);
+        assert_eq!(nestest_asm(&cpu), "LDA $0200,X @ 0205 = 3F");
+
+        cpu = cpu_at(0x8000, &[0x20, 0x34, 0x12]);
+        assert_eq!(nestest_asm(&cpu), "JSR $1234");
+
+        cpu = cpu_at(0x8000, &[0x6c, 0xff, 0x02]);
+        cpu.poke(0x02ff, 0x00);
+        cpu.poke(0x0200, 0x03);
+        cpu.poke(0x0300, 0x04);
+        assert_eq!(nestest_asm(&cpu), "JMP ($02FF) = 0300");
+
+        cpu = cpu_at(0x8000, &[0xa1, 0x80]);
+        cpu.index_x = 2;
+        cpu.poke(0x82, 0x00);
+        cpu.poke(0x83, 0x03);
+        cpu.poke(0x0300, 0x5a);
+        assert_eq!(nestest_asm(&cpu), "LDA ($80,X) @ 82 = 0300 = 5A");
+
+        cpu = cpu_at(0x8000, &[0xb1, 0xff]);
+        cpu.index_y = 2;
+        cpu.poke(0xff, 0x00);
+        cpu.poke(0x00, 0x04);
+        cpu.poke(0x0402, 0x89);
+        assert_eq!(nestest_asm(&cpu), "LDA ($FF),Y = 0400 @ 0402 = 89");
+
+        cpu = cpu_at(0x8010, &[0xd0, 0xfe]);
+        assert_eq!(nestest_asm(&cpu), "BNE $8010");
+    }
+
+    #[test]
+    fn test_format_nestest_unofficial() {
+        let mut cpu = cpu_at(0x8000, &[0x04, 0xa9]);
+        assert_eq!(nestest_asm(&cpu), "*NOP $A9 = 00");
+        assert!(format_nestest(&cpu).starts_with("8000  04 A9    *NOP $A9 = 00    "));
+
+        cpu = cpu_at(0x8000, &[0xeb, 0x40]);
+        assert_eq!(nestest_asm(&cpu), "*SBC #$40");
+    }
+
+    #[test]
+    fn test_format_nestest_io_registers() {
+        let mut cpu = cpu_at(0x8000, &[0x8d, 0x15, 0x40]);
+        assert_eq!(nestest_asm(&cpu), "STA $4015 = FF");
+
+        cpu.mode = Mode::Mos6502;
+        assert_eq!(nestest_asm(&cpu), "STA $4015 = 00");
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("fetch".parse(), Ok(TraceFormat::Fetch));
+        assert_eq!("Instruction".parse(), Ok(TraceFormat::Instruction));
+        assert!("cycle".parse::<TraceFormat>().is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::{fmt, ops::RangeInclusive, str::FromStr};
+
+/// Which accesses to an address trigger a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// Every read.
+    Read,
+    /// Every write, whether or not it changes the value.
+    Write,
+    /// Writes that store a different value from the one already there.
+    Change,
+}
+
+impl WatchKind {
+    /// Every kind of watchpoint.
+    pub const ALL: [WatchKind; 3] = [Self::Read, Self::Write, Self::Change];
+
+    /// The lowercase name of the kind, as accepted by [`WatchKind::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Change => "change",
+        }
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for WatchKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(WatchKind::name).collect();
+                format!(
+                    "unknown watch kind '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A range of addresses to watch for a kind of access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub kind: WatchKind,
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ${:04x}", self.kind, self.range.start())?;
+
+        if self.range.end() != self.range.start() {
+            write!(f, "-${:04x}", self.range.end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An access that triggered a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub kind: WatchKind,
+    pub addr: u16,
+    /// The value before the access, which for reads is the value read.
+    pub old: u8,
+    /// The value after the access.
+    pub new: u8,
+    /// The address of the instruction that made the access.
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            WatchKind::Read => write!(f, "read of ${:02x} from ${:04x}", self.new, self.addr)?,
+            WatchKind::Write | WatchKind::Change => write!(
+                f,
+                "write to ${:04x}: ${:02x} -> ${:02x}",
+                self.addr, self.old, self.new
+            )?,
+        }
+
+        write!(f, " by the instruction at ${:04x}", self.pc)
+    }
+}
+
+/// Watchpoints on the CPU's bus, and the accesses that have triggered them.
+/// Register them by setting [`CPU::watchpoints`](super::CPU::watchpoints);
+/// when it isn't set, accesses aren't checked at all.
+#[derive(Debug, Default, Clone)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    hits: Vec<WatchHit>,
+    /// The address of the instruction being executed.
+    pub(super) pc: u16,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove every watchpoint whose range contains the given address,
+    /// returning whether there were any.
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| !watchpoint.range.contains(&addr));
+
+        self.watchpoints.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// Take the accesses that have triggered a watchpoint since this was last
+    /// called, in the order they happened.
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Record a read from the bus.
+    pub(super) fn read(&mut self, addr: u16, value: u8) {
+        self.check(addr, value, value, |kind| kind == WatchKind::Read);
+    }
+
+    /// Record a write to the bus, replacing the given old value.
+    pub(super) fn write(&mut self, addr: u16, old: u8, new: u8) {
+        self.check(addr, old, new, |kind| match kind {
+            WatchKind::Read => false,
+            WatchKind::Write => true,
+            WatchKind::Change => old != new,
+        });
+    }
+
+    fn check(&mut self, addr: u16, old: u8, new: u8, triggers: impl Fn(WatchKind) -> bool) {
+        for watchpoint in &self.watchpoints {
+            if watchpoint.range.contains(&addr) && triggers(watchpoint.kind) {
+                self.hits.push(WatchHit {
+                    kind: watchpoint.kind,
+                    addr,
+                    old,
+                    new,
+                    pc: self.pc,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::{assembler::assemble, CPU};
+
+    /// Run a program assembled at $8000 with the given watchpoints, returning
+    /// the accesses that triggered them.
+    fn run(source: &str, watchpoints: &[Watchpoint]) -> Vec<WatchHit> {
+        let mut cpu = CPU::new();
+        cpu.load(assemble(source, 0x8000).unwrap().bytes);
+        cpu.reset();
+
+        let mut watch = Watchpoints::new();
+        for watchpoint in watchpoints {
+            watch.add(watchpoint.clone());
+        }
+        cpu.watchpoints = Some(watch);
+
+        cpu.run();
+        cpu.watchpoints.as_mut().unwrap().take_hits()
+    }
+
+    fn watchpoint(range: RangeInclusive<u16>, kind: WatchKind) -> Watchpoint {
+        Watchpoint { range, kind }
+    }
+
+    #[test]
+    fn test_write_and_change() {
+        let hits = run(
+            "
+            lda #1
+            sta $10
+            sta $10
+            inc $10
+            ",
+            &[
+                watchpoint(0x10..=0x10, WatchKind::Write),
+                watchpoint(0x10..=0x10, WatchKind::Change),
+            ],
+        );
+
+        let hit = |kind, old, new, pc| WatchHit {
+            kind,
+            addr: 0x10,
+            old,
+            new,
+            pc,
+        };
+
+        assert_eq!(
+            hits,
+            vec![
+                hit(WatchKind::Write, 0, 1, 0x8002),
+                hit(WatchKind::Change, 0, 1, 0x8002),
+                hit(WatchKind::Write, 1, 1, 0x8004),
+                hit(WatchKind::Write, 1, 2, 0x8006),
+                hit(WatchKind::Change, 1, 2, 0x8006),
+            ]
+        );
+        assert_eq!(
+            hits[3].to_string(),
+            "write to $0010: $01 -> $02 by the instruction at $8006"
+        );
+    }
+
+    #[test]
+    fn test_read_through_stack_and_pointers() {
+        let hits = run(
+            "
+            lda #$42
+            pha
+            pla
+            lda ($10),y
+            jmp *+3
+            ",
+            &[
+                watchpoint(0x01fd..=0x01fd, WatchKind::Read),
+                watchpoint(0x10..=0x11, WatchKind::Read),
+                watchpoint(0x8007..=0x8008, WatchKind::Read),
+            ],
+        );
+
+        let reads: Vec<(u16, u16)> = hits.iter().map(|hit| (hit.addr, hit.pc)).collect();
+        assert_eq!(
+            reads,
+            vec![
+                (0x01fd, 0x8003),
+                (0x10, 0x8004),
+                (0x11, 0x8004),
+                (0x8007, 0x8006),
+                (0x8008, 0x8006),
+            ]
+        );
+        assert_eq!(hits[0].new, 0x42);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut watchpoints = Watchpoints::new();
+        watchpoints.add(watchpoint(0x0200..=0x02ff, WatchKind::Write));
+        watchpoints.add(watchpoint(0x0300..=0x0300, WatchKind::Read));
+
+        assert_eq!(
+            watchpoints.iter().next().unwrap().to_string(),
+            "write $0200-$02ff"
+        );
+        assert!(!watchpoints.remove(0x0400));
+        assert!(watchpoints.remove(0x0210));
+        assert_eq!(watchpoints.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_kind_from_str() {
+        assert_eq!("Change".parse(), Ok(WatchKind::Change));
+        assert_eq!(
+            "exec".parse::<WatchKind>(),
+            Err("unknown watch kind 'exec', expected one of read, write, change".to_string())
+        );
+    }
+}
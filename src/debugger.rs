@@ -0,0 +1,948 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use crate::{
+    cpu::{
+        self,
+        disassembler::DecodedInstruction,
+        status::Status,
+        watch::{WatchHit, WatchKind, Watchpoint, Watchpoints},
+        CPU,
+    },
+    rewind::Rewind,
+};
+
+/// Opcode of JSR, which `next` steps over.
+const JSR: u8 = 0x20;
+/// Opcode of RTS, which `finish` runs to.
+const RTS: u8 = 0x60;
+
+/// Number of instructions to show before and after the program counter.
+const LIST_BEFORE: u16 = 3;
+const LIST_AFTER: u16 = 6;
+/// Longest instruction, for finding where to start disassembling before the
+/// program counter.
+const MAX_INSTRUCTION_LEN: u16 = 3;
+
+/// Number of bytes to dump when no length is given.
+const DUMP_LEN: u16 = 0x40;
+const DUMP_WIDTH: u16 = 16;
+
+const PROMPT: &str = "(6502) ";
+
+const HELP: &str = "\
+Commands, with every number in hex:
+  s, step [COUNT]         run COUNT instructions, default 1
+  n, next                 run an instruction, stepping over subroutine calls
+  c, continue             run until a breakpoint, or the program halts
+  finish                  run until the current subroutine returns
+  b, break ADDR [if COND] stop at ADDR, optionally only when COND holds, e.g.
+                          `break 8000 if x == 10`, for a, x, y, s, p, or pc
+  d, delete [ADDR]        remove the breakpoint at ADDR, or all of them
+  watch ADDR[-END] [KIND] stop when the addresses are accessed, where KIND is
+                          read, write (the default), or change
+  unwatch ADDR            remove the watchpoints covering ADDR
+  i, info                 list breakpoints and watchpoints
+  r, regs                 show the registers and flags
+  set REG VALUE           set a register
+  x, dump ADDR [LEN]      show LEN bytes of memory starting at ADDR
+  w, write ADDR BYTE...   write bytes to RAM starting at ADDR
+  l, list [ADDR]          disassemble around ADDR, default the program counter
+  rw, rewind [FRAMES]     go back to a snapshot at least FRAMES frames ago,
+                          default 1
+  h, help                 show this message
+  q, quit                 stop debugging
+An empty line repeats the last command.";
+
+/// A register that can be read and set from the debugger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    PC,
+}
+
+impl Register {
+    pub fn get(&self, cpu: &CPU) -> u16 {
+        match self {
+            Self::A => cpu.accumulator.into(),
+            Self::X => cpu.index_x.into(),
+            Self::Y => cpu.index_y.into(),
+            Self::S => u8::from(cpu.stack_pointer).into(),
+            Self::P => cpu.status.bits().into(),
+            Self::PC => cpu.program_counter,
+        }
+    }
+
+    /// Set the register, which fails if the value is too big for it.
+    pub fn set(&self, cpu: &mut CPU, value: u16) -> Result<(), String> {
+        if *self == Self::PC {
+            cpu.program_counter = value;
+            return Ok(());
+        }
+
+        let value = u8::try_from(value).map_err(|_| format!("{} is an 8-bit register", self))?;
+
+        match self {
+            Self::A => cpu.accumulator = value,
+            Self::X => cpu.index_x = value,
+            Self::Y => cpu.index_y = value,
+            Self::S => cpu.stack_pointer = value.into(),
+            Self::P => cpu.status = Status::from(value),
+            Self::PC => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::A => "a",
+            Self::X => "x",
+            Self::Y => "y",
+            Self::S => "s",
+            Self::P => "p",
+            Self::PC => "pc",
+        })
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a" => Ok(Self::A),
+            "x" => Ok(Self::X),
+            "y" => Ok(Self::Y),
+            "s" | "sp" => Ok(Self::S),
+            "p" => Ok(Self::P),
+            "pc" => Ok(Self::PC),
+            _ => Err(format!(
+                "unknown register '{}', expected one of a, x, y, s, p, pc",
+                s
+            )),
+        }
+    }
+}
+
+/// A comparison between a register and a value, such as `x == 10`, that a
+/// breakpoint only stops on when it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Self); 6] = [
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+    ];
+
+    fn symbol(&self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .map_or("", |(symbol, _)| symbol)
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        let register = self.register.get(cpu);
+
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessOrEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterOrEqual => register >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ${:02x}",
+            self.register,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    /// Parse a condition like `x == 10` or `a>=$80`, with the value in hex.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, symbol, comparison) = Comparison::ALL
+            .iter()
+            .find_map(|&(symbol, comparison)| s.find(symbol).map(|i| (i, symbol, comparison)))
+            .ok_or_else(|| format!("invalid condition '{}', expected e.g. 'x == 10'", s))?;
+
+        Ok(Self {
+            register: s[..start].trim().parse()?,
+            comparison,
+            value: parse_number(s[start + symbol.len()..].trim())?,
+        })
+    }
+}
+
+/// Why the debugger stopped running the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The command finished, e.g. the instruction was stepped or the
+    /// subroutine returned.
+    Done,
+    /// The program counter reached a breakpoint whose condition held.
+    Breakpoint(u16),
+    /// The instruction made an access that triggered a watchpoint, which is
+    /// the first one if it made several.
+    Watchpoint(WatchHit),
+    /// The program halted by jumping to $0000.
+    Halted,
+    /// The CPU hit an error, and the program counter is left on the
+    /// instruction that caused it.
+    Error(cpu::Error),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Done => Ok(()),
+            Self::Breakpoint(addr) => write!(f, "breakpoint at ${:04x}", addr),
+            Self::Watchpoint(hit) => write!(f, "watchpoint: {}", hit),
+            Self::Halted => write!(f, "program halted"),
+            Self::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// An interactive debugger that runs a program on a CPU, stopping at
+/// breakpoints.
+#[derive(Debug)]
+pub struct Debugger {
+    pub cpu: CPU,
+    /// Breakpoints by address, each with the condition it needs to stop, if
+    /// any.
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    /// The last command entered, which an empty line repeats.
+    last_command: String,
+    /// Snapshots taken while running, to rewind through.
+    pub history: Rewind,
+}
+
+impl Debugger {
+    /// Debug a CPU that's already loaded and reset.
+    pub fn new(mut cpu: CPU) -> Self {
+        let mut history = Rewind::default();
+        history.record(&mut cpu);
+
+        Self {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            last_command: String::new(),
+            history,
+        }
+    }
+
+    /// Stop at the given address, if the condition holds. Replaces any
+    /// breakpoint already at the address.
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<Condition>) {
+        self.breakpoints.insert(addr, condition);
+    }
+
+    /// Remove the breakpoint at the given address, returning whether there was
+    /// one.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    /// Stop when an access matches the watchpoint.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.cpu
+            .watchpoints
+            .get_or_insert_with(Watchpoints::new)
+            .add(watchpoint);
+    }
+
+    /// Remove the watchpoints covering the given address, returning whether
+    /// there were any. Once none are left, the CPU stops checking accesses.
+    pub fn remove_watchpoint(&mut self, addr: u16) -> bool {
+        let Some(watchpoints) = self.cpu.watchpoints.as_mut() else {
+            return false;
+        };
+
+        let removed = watchpoints.remove(addr);
+        if watchpoints.is_empty() {
+            self.cpu.watchpoints = None;
+        }
+
+        removed
+    }
+
+    /// Run a single instruction.
+    pub fn step(&mut self) -> Stop {
+        self.run_until(|_| true)
+    }
+
+    /// Run a single instruction, or a whole subroutine if the instruction is a
+    /// JSR.
+    pub fn step_over(&mut self) -> Stop {
+        let pc = self.cpu.program_counter;
+        if self.cpu.peek(pc) != JSR {
+            return self.step();
+        }
+
+        let ret = pc.wrapping_add(3);
+        let sp = u8::from(self.cpu.stack_pointer);
+
+        self.run_until(move |cpu| cpu.program_counter == ret && u8::from(cpu.stack_pointer) >= sp)
+    }
+
+    /// Run until a breakpoint is reached, or the program halts.
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+
+    /// Run until the current subroutine returns, stopping at the instruction
+    /// after its caller's JSR.
+    pub fn finish(&mut self) -> Stop {
+        let sp = u8::from(self.cpu.stack_pointer);
+        let mut returning = false;
+
+        self.run_until(move |cpu| {
+            if returning {
+                return true;
+            }
+
+            // Returns from subroutines called since finishing started happen
+            // lower in the stack.
+            returning = cpu.peek(cpu.program_counter) == RTS && u8::from(cpu.stack_pointer) >= sp;
+            false
+        })
+    }
+
+    /// Run instructions until `done` returns true after one of them, a
+    /// watchpoint is triggered, or a breakpoint other than the one at the
+    /// starting address is reached.
+    fn run_until(&mut self, mut done: impl FnMut(&CPU) -> bool) -> Stop {
+        // Check before the first instruction, for commands that decide based
+        // on what's about to run.
+        done(&self.cpu);
+
+        loop {
+            if let Err(e) = self.cpu.try_step() {
+                return Stop::Error(e);
+            }
+            self.history.record(&mut self.cpu);
+
+            if let Some(watchpoints) = self.cpu.watchpoints.as_mut() {
+                if let Some(&hit) = watchpoints.take_hits().first() {
+                    return Stop::Watchpoint(hit);
+                }
+            }
+
+            let pc = self.cpu.program_counter;
+            if pc == 0 {
+                return Stop::Halted;
+            }
+
+            if done(&self.cpu) {
+                return Stop::Done;
+            }
+
+            if let Some(condition) = self.breakpoints.get(&pc) {
+                if condition.is_none_or(|condition| condition.holds(&self.cpu)) {
+                    return Stop::Breakpoint(pc);
+                }
+            }
+        }
+    }
+
+    /// Go back to the most recent snapshot at least the given number of frames
+    /// ago, or the oldest one. Returns the number of frames rewound.
+    pub fn rewind(&mut self, frames: u64) -> io::Result<u64> {
+        self.history.rewind(&mut self.cpu, frames)
+    }
+
+    /// Format the registers and flags, with set flags in uppercase, e.g.
+    /// `PC:8000 A:00 X:00 Y:00 S:FD P:24 nv-bdIzc CYC:7`.
+    pub fn registers(&self) -> String {
+        const FLAGS: [(Status, char); 8] = [
+            (Status::Negative, 'n'),
+            (Status::Overflow, 'v'),
+            (Status::Break2, '-'),
+            (Status::Break, 'b'),
+            (Status::Decimal, 'd'),
+            (Status::InterruptDisable, 'i'),
+            (Status::Zero, 'z'),
+            (Status::Carry, 'c'),
+        ];
+
+        let cpu = &self.cpu;
+        let flags: String = FLAGS
+            .iter()
+            .map(|&(flag, c)| {
+                if flag != Status::Break2 && cpu.status.contains(flag) {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+
+        format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{:02X} {} CYC:{}",
+            cpu.program_counter,
+            cpu.accumulator,
+            cpu.index_x,
+            cpu.index_y,
+            u8::from(cpu.stack_pointer),
+            cpu.status.bits(),
+            flags,
+            cpu.cycles
+        )
+    }
+
+    /// Decode the instruction at the given address.
+    pub fn instruction_at(&self, addr: u16) -> DecodedInstruction {
+        let bytes: Vec<u8> = (0..MAX_INSTRUCTION_LEN)
+            .map(|i| self.cpu.peek(addr.wrapping_add(i)))
+            .collect();
+
+        DecodedInstruction::decode(&bytes, addr)
+    }
+
+    /// Disassemble the instructions around the given address. Instructions
+    /// before it are found by starting as far back as lines up with it.
+    pub fn list(&self, addr: u16) -> Vec<DecodedInstruction> {
+        let back = LIST_BEFORE * MAX_INSTRUCTION_LEN;
+        let mut before = vec![];
+
+        for start in (1..=back).rev().map(|offset| addr.wrapping_sub(offset)) {
+            let mut instructions = vec![];
+            let mut next = start;
+
+            while next != addr && instructions.len() <= usize::from(back) {
+                let instruction = self.instruction_at(next);
+                next = instruction.next_addr();
+                instructions.push(instruction);
+            }
+
+            if next == addr {
+                before = instructions;
+                break;
+            }
+        }
+
+        let mut instructions = before.split_off(before.len().saturating_sub(LIST_BEFORE.into()));
+        let mut next = addr;
+
+        for _ in 0..=LIST_AFTER {
+            let instruction = self.instruction_at(next);
+            next = instruction.next_addr();
+            instructions.push(instruction);
+        }
+
+        instructions
+    }
+
+    /// Format the given number of bytes of memory starting at an address, 16
+    /// bytes per line, with their ASCII characters.
+    pub fn dump(&self, addr: u16, len: u16) -> Vec<String> {
+        let mut lines = vec![];
+
+        for start in (0..len).step_by(DUMP_WIDTH.into()) {
+            let line_addr = addr.wrapping_add(start);
+            let bytes: Vec<u8> = (0..DUMP_WIDTH.min(len - start))
+                .map(|i| self.cpu.peek(line_addr.wrapping_add(i)))
+                .collect();
+
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => char::from(b),
+                    _ => '.',
+                })
+                .collect();
+
+            lines.push(format!(
+                "{:04X}  {:47}  {}",
+                line_addr,
+                hex.join(" "),
+                ascii
+            ));
+        }
+
+        lines
+    }
+
+    /// Read commands from the input until it ends or `quit` is entered,
+    /// writing a prompt before each and their output after.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        self.write_location(&mut output)?;
+
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+
+            let Some(line) = lines.next().transpose()? else {
+                return Ok(());
+            };
+
+            match self.execute(&line, &mut output) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(message) => writeln!(output, "error: {}", message)?,
+            }
+        }
+    }
+
+    /// Run a single command, writing its output. Returns false if the command
+    /// was to quit. An empty command repeats the last one.
+    pub fn execute<W: Write>(&mut self, line: &str, output: &mut W) -> Result<bool, String> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command.clone_from(&line);
+
+        let io = |e: io::Error| e.to_string();
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = words.collect();
+
+        match command {
+            "s" | "step" => {
+                let count = args.first().map_or(Ok(1), |s| parse_number(s))?;
+                let mut stop = Stop::Done;
+
+                for _ in 0..count {
+                    stop = self.step();
+                    if stop != Stop::Done {
+                        break;
+                    }
+                }
+
+                self.write_stop(stop, output).map_err(io)?;
+            }
+            "n" | "next" => {
+                let stop = self.step_over();
+                self.write_stop(stop, output).map_err(io)?;
+            }
+            "c" | "continue" => {
+                let stop = self.resume();
+                self.write_stop(stop, output).map_err(io)?;
+            }
+            "finish" => {
+                let stop = self.finish();
+                self.write_stop(stop, output).map_err(io)?;
+            }
+            "b" | "break" => {
+                let addr = parse_number(args.first().ok_or("expected an address")?)?;
+                let condition = match args.get(1) {
+                    None => None,
+                    Some(&"if") => Some(args[2..].join(" ").parse()?),
+                    Some(arg) => return Err(format!("expected 'if', got '{}'", arg)),
+                };
+
+                self.add_breakpoint(addr, condition);
+                writeln!(output, "breakpoint at ${:04x}", addr).map_err(io)?;
+            }
+            "d" | "delete" => match args.first() {
+                Some(addr) => {
+                    let addr = parse_number(addr)?;
+                    if !self.remove_breakpoint(addr) {
+                        return Err(format!("no breakpoint at ${:04x}", addr));
+                    }
+                }
+                None => self.breakpoints.clear(),
+            },
+            "watch" => {
+                let range = args.first().ok_or("expected an address")?;
+                let range = match range.split_once('-') {
+                    Some((start, end)) => parse_number(start)?..=parse_number(end)?,
+                    None => parse_number(range)?..=parse_number(range)?,
+                };
+                let kind = args.get(1).map_or(Ok(WatchKind::Write), |s| s.parse())?;
+
+                let watchpoint = Watchpoint { range, kind };
+                writeln!(output, "watchpoint on {}", watchpoint).map_err(io)?;
+                self.add_watchpoint(watchpoint);
+            }
+            "unwatch" => {
+                let addr = parse_number(args.first().ok_or("expected an address")?)?;
+                if !self.remove_watchpoint(addr) {
+                    return Err(format!("no watchpoint covers ${:04x}", addr));
+                }
+            }
+            "i" | "info" => {
+                let watchpoints = self.cpu.watchpoints.iter().flat_map(Watchpoints::iter);
+
+                if self.breakpoints.is_empty() && self.cpu.watchpoints.is_none() {
+                    writeln!(output, "no breakpoints or watchpoints").map_err(io)?;
+                }
+
+                for watchpoint in watchpoints {
+                    writeln!(output, "watch {}", watchpoint).map_err(io)?;
+                }
+
+                for (addr, condition) in &self.breakpoints {
+                    match condition {
+                        Some(condition) => writeln!(output, "${:04x} if {}", addr, condition),
+                        None => writeln!(output, "${:04x}", addr),
+                    }
+                    .map_err(io)?;
+                }
+            }
+            "r" | "regs" => writeln!(output, "{}", self.registers()).map_err(io)?,
+            "set" => {
+                let [register, value] = args[..] else {
+                    return Err("expected a register and a value".to_string());
+                };
+
+                let register: Register = register.parse()?;
+                register.set(&mut self.cpu, parse_number(value)?)?;
+                writeln!(output, "{}", self.registers()).map_err(io)?;
+            }
+            "x" | "dump" => {
+                let addr = parse_number(args.first().ok_or("expected an address")?)?;
+                let len = args.get(1).map_or(Ok(DUMP_LEN), |s| parse_number(s))?;
+
+                for line in self.dump(addr, len) {
+                    writeln!(output, "{}", line).map_err(io)?;
+                }
+            }
+            "w" | "write" => {
+                let addr = parse_number(args.first().ok_or("expected an address")?)?;
+                if args.len() < 2 {
+                    return Err("expected bytes to write".to_string());
+                }
+
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let byte = u8::try_from(parse_number(byte)?)
+                        .map_err(|_| format!("'{}' is not a byte", byte))?;
+                    self.cpu.poke(addr.wrapping_add(i as u16), byte);
+                }
+            }
+            "l" | "list" => {
+                let addr = args
+                    .first()
+                    .map_or(Ok(self.cpu.program_counter), |s| parse_number(s))?;
+
+                self.write_list(addr, output).map_err(io)?;
+            }
+            "rw" | "rewind" => {
+                let frames = args.first().map_or(Ok(1), |s| parse_number(s))?;
+                let rewound = self.rewind(frames.into()).map_err(io)?;
+
+                writeln!(output, "rewound {} frames", rewound).map_err(io)?;
+                self.write_location(output).map_err(io)?;
+            }
+            "h" | "help" => writeln!(output, "{}", HELP).map_err(io)?,
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("unknown command '{}', try 'help'", command)),
+        }
+
+        Ok(true)
+    }
+
+    /// Write why the program stopped, followed by where.
+    fn write_stop<W: Write>(&self, stop: Stop, output: &mut W) -> io::Result<()> {
+        if stop != Stop::Done {
+            writeln!(output, "{}", stop)?;
+        }
+
+        self.write_location(output)
+    }
+
+    /// Write the registers and the instruction at the program counter.
+    fn write_location<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let instruction = self.instruction_at(self.cpu.program_counter);
+
+        writeln!(output, "{}", self.registers())?;
+        writeln!(output, "> {}", instruction.listing())
+    }
+
+    fn write_list<W: Write>(&self, addr: u16, output: &mut W) -> io::Result<()> {
+        for instruction in self.list(addr) {
+            let marker = match instruction.addr {
+                addr if addr == self.cpu.program_counter => '>',
+                addr if self.breakpoints.contains_key(&addr) => '*',
+                _ => ' ',
+            };
+
+            writeln!(output, "{} {}", marker, instruction.listing())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a hex number, optionally prefixed with `$` or `0x`.
+fn parse_number(s: &str) -> Result<u16, String> {
+    let hex = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+
+    u16::from_str_radix(hex, 16).map_err(|_| format!("invalid number '{}'", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    /// Debug a program assembled at $8000.
+    fn debugger(source: &str) -> Debugger {
+        let mut cpu = CPU::new();
+        cpu.load(assemble(source, 0x8000).unwrap().bytes);
+        cpu.reset();
+
+        Debugger::new(cpu)
+    }
+
+    const PROGRAM: &str = "
+                ldx #0
+        loop:   jsr sub
+                inx
+                cpx #5
+                bne loop
+                jmp 0
+        sub:    lda #1
+                jsr inner
+                rts
+        inner:  ldy #2
+                rts
+    ";
+
+    #[test]
+    fn test_step_and_next() {
+        let mut debugger = debugger(PROGRAM);
+
+        assert_eq!(debugger.step(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x8002);
+
+        // Stepping into the subroutine.
+        assert_eq!(debugger.step(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x800d);
+
+        // Stepping over a nested call and returning.
+        assert_eq!(debugger.step(), Stop::Done);
+        assert_eq!(debugger.step_over(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x8012);
+        assert_eq!(debugger.cpu.index_y, 2);
+        assert_eq!(debugger.step_over(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x8005);
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.step();
+        debugger.step();
+
+        assert_eq!(debugger.finish(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x8005);
+        assert_eq!(debugger.cpu.index_y, 2);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.add_breakpoint(0x8005, None);
+
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0x8005));
+        assert_eq!(debugger.cpu.index_x, 0);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0x8005));
+        assert_eq!(debugger.cpu.index_x, 1);
+
+        debugger.add_breakpoint(0x8005, Some("x == 3".parse().unwrap()));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0x8005));
+        assert_eq!(debugger.cpu.index_x, 3);
+
+        assert!(debugger.remove_breakpoint(0x8005));
+        assert_eq!(debugger.resume(), Stop::Halted);
+        assert_eq!(debugger.cpu.index_x, 5);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger(
+            "
+            lda #1
+            sta $10
+            lda $10
+            ",
+        );
+        debugger.add_watchpoint(Watchpoint {
+            range: 0x10..=0x10,
+            kind: WatchKind::Read,
+        });
+
+        let Stop::Watchpoint(hit) = debugger.resume() else {
+            panic!("expected a watchpoint");
+        };
+        assert_eq!(
+            (hit.kind, hit.addr, hit.new, hit.pc),
+            (WatchKind::Read, 0x10, 1, 0x8004)
+        );
+        assert_eq!(debugger.cpu.program_counter, 0x8006);
+
+        assert!(!debugger.remove_watchpoint(0x11));
+        assert!(debugger.remove_watchpoint(0x10));
+        assert!(debugger.cpu.watchpoints.is_none());
+    }
+
+    #[test]
+    fn test_error() {
+        let mut debugger = debugger(".byte $02");
+
+        assert_eq!(
+            debugger.step(),
+            Stop::Error(cpu::Error::UnknownOpcode {
+                opcode: 0x02,
+                addr: 0x8000
+            })
+        );
+    }
+
+    #[test]
+    fn test_condition() {
+        let condition: Condition = "a>=$80".parse().unwrap();
+        assert_eq!(condition.to_string(), "a >= $80");
+        assert_eq!(
+            "pc != 8000".parse::<Condition>().unwrap().comparison,
+            Comparison::NotEqual
+        );
+        assert!("q == 1".parse::<Condition>().is_err());
+        assert!("a = 1".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        let debugger = debugger(PROGRAM);
+
+        // Disassembly before $8000 lines up with it from the single-byte BRKs
+        // in empty memory.
+        let addrs: Vec<u16> = debugger.list(0x8005).iter().map(|i| i.addr).collect();
+        assert_eq!(
+            addrs,
+            vec![0x7fff, 0x8000, 0x8002, 0x8005, 0x8006, 0x8008, 0x800a, 0x800d, 0x800f, 0x8012]
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut debugger = debugger("");
+        for (i, &byte) in b"Hello, world!\n".iter().enumerate() {
+            debugger.cpu.poke(0x0200 + i as u16, byte);
+        }
+
+        assert_eq!(
+            debugger.dump(0x0200, 0x12),
+            vec![
+                "0200  48 65 6C 6C 6F 2C 20 77 6F 72 6C 64 21 0A 00 00  Hello, world!...",
+                "0210  00 00                                            ..",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut debugger = debugger(
+            "
+            @loop:  inc $10
+                    jmp @loop
+            ",
+        );
+        let frame = |debugger: &Debugger| debugger.cpu.cycles / cpu::CYCLES_PER_FRAME;
+
+        while frame(&debugger) < 5 {
+            debugger.step();
+        }
+
+        // Snapshots are taken on frames 0 and 4.
+        assert_eq!(debugger.rewind(1).unwrap(), 1);
+        assert_eq!(frame(&debugger), 4);
+        assert_ne!(debugger.cpu.peek(0x10), 0);
+
+        while frame(&debugger) < 6 {
+            debugger.step();
+        }
+
+        let mut output = vec![];
+        debugger.execute("rewind 3", &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("rewound 6 frames\nPC:8000 A:00 X:00 Y:00 S:FD"));
+        assert_eq!(debugger.cpu.peek(0x10), 0);
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = debugger(PROGRAM);
+        let input = "\
+            break 8005 if x == 2\n\
+            continue\n\
+            set a 42\n\
+            w 0200 de ad\n\
+            x 0200 2\n\
+            watch 0010-001f change\n\
+            info\n\
+            bogus\n\
+            step\n\
+            \n\
+            quit\n\
+            step\n";
+        let mut output = vec![];
+
+        debugger.repl(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("breakpoint at $8005\nPC:8005 A:01 X:02"));
+        assert!(output.contains("PC:8005 A:42 X:02"));
+        assert!(output.contains("0200  DE AD"));
+        assert!(output.contains("$8005 if x == $02"));
+        assert!(output.contains("watch change $0010-$001f"));
+        assert!(output.contains("error: unknown command 'bogus'"));
+        assert!(output.contains("> 8006  E0 05     CPX #$05"));
+        assert!(output.contains("> 8008  D0 F8     BNE $8002"));
+        assert_eq!(debugger.cpu.program_counter, 0x8008);
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    cpu::{self, assembler::assemble, mode::Mode, CPU},
+    frame::Frame,
+};
+
+/// Address updated with a new random byte before every instruction.
+pub const RANDOM: u16 = 0xfe;
+/// Address holding the ASCII code of the last key pressed.
+pub const LAST_KEY: u16 = 0xff;
+
+/// Start of the screen in memory, one byte per pixel.
+pub const SCREEN: u16 = 0x0200;
+/// Width and height of the screen, in pixels.
+pub const SCREEN_SIZE: u32 = 32;
+/// Number of pixels on the screen.
+pub const SCREEN_PIXELS: usize = (SCREEN_SIZE * SCREEN_SIZE) as usize;
+
+/// Key codes for the directions, as read by most easy6502 programs.
+pub const KEY_UP: u8 = b'w';
+pub const KEY_LEFT: u8 = b'a';
+pub const KEY_DOWN: u8 = b's';
+pub const KEY_RIGHT: u8 = b'd';
+
+/// The 16-color palette used by the screen, indexed by the low nibble of each
+/// screen byte.
+pub const PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff],
+    [0x88, 0x00, 0x00],
+    [0xaa, 0xff, 0xee],
+    [0xcc, 0x44, 0xcc],
+    [0x00, 0xcc, 0x55],
+    [0x00, 0x00, 0xaa],
+    [0xee, 0xee, 0x77],
+    [0xdd, 0x88, 0x55],
+    [0x66, 0x44, 0x00],
+    [0xff, 0x77, 0x77],
+    [0x33, 0x33, 0x33],
+    [0x77, 0x77, 0x77],
+    [0xaa, 0xff, 0x66],
+    [0x00, 0x88, 0xff],
+    [0xbb, 0xbb, 0xbb],
+];
+
+/// Source of the snake game from the easy6502 tutorial.
+pub const SNAKE_SOURCE: &str = include_str!("easy6502/snake.asm");
+
+/// The snake game, assembled from [`SNAKE_SOURCE`] to run from $0600.
+pub fn snake() -> Vec<u8> {
+    assemble(SNAKE_SOURCE, Mode::Mos6502.program_rom() as u16)
+        .expect("the snake game should assemble")
+        .bytes
+}
+
+/// Seed for the random number generator, taken from the current time.
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Runs programs written for the easy6502 environment: a plain 6502 with a
+/// random number generator at $FE, the last key pressed at $FF, and a 32x32
+/// screen at $0200-$05FF.
+#[derive(Debug)]
+pub struct Easy6502 {
+    pub cpu: CPU,
+    rng: u64,
+}
+
+impl Easy6502 {
+    /// Load a program at $0600 and reset the CPU to run it, seeding the random
+    /// number generator from the clock.
+    pub fn new(program: Vec<u8>) -> Self {
+        Self::with_seed(program, clock_seed())
+    }
+
+    /// Load a program at $0600 and reset the CPU to run it, with a fixed seed
+    /// for the random number generator so that runs are repeatable.
+    pub fn with_seed(program: Vec<u8>, seed: u64) -> Self {
+        let mut cpu = CPU::new();
+        cpu.mode = Mode::Mos6502;
+        cpu.load(program);
+        cpu.reset();
+
+        Self { cpu, rng: seed }
+    }
+
+    /// Host a CPU that has already been loaded with a program, seeding the
+    /// random number generator from the clock.
+    pub fn with_cpu(cpu: CPU) -> Self {
+        Self {
+            cpu,
+            rng: clock_seed(),
+        }
+    }
+
+    /// Whether the program has halted.
+    pub fn halted(&self) -> bool {
+        self.cpu.program_counter == 0
+    }
+
+    /// Store a new random byte at $FE and execute a single instruction. Returns
+    /// false if the program has halted, or an error if it hit an unrecognized
+    /// opcode.
+    pub fn step(&mut self) -> Result<bool, cpu::Error> {
+        if self.halted() {
+            return Ok(false);
+        }
+
+        let random = self.next_random();
+        self.cpu.poke(RANDOM, random);
+        self.cpu.try_step()?;
+
+        Ok(!self.halted())
+    }
+
+    /// Execute up to the given number of instructions. Returns false if the
+    /// program halted before then, or an error if it hit an unrecognized
+    /// opcode.
+    pub fn run(&mut self, steps: u64) -> Result<bool, cpu::Error> {
+        for _ in 0..steps {
+            if !self.step()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Record a keypress as the last key pressed, e.g. [`KEY_UP`].
+    pub fn press_key(&mut self, key: u8) {
+        self.cpu.poke(LAST_KEY, key);
+    }
+
+    /// The last key pressed.
+    pub fn last_key(&self) -> u8 {
+        self.cpu.peek(LAST_KEY)
+    }
+
+    /// The palette index of each pixel on the screen, in row-major order.
+    pub fn screen(&self) -> [u8; SCREEN_PIXELS] {
+        let mut screen = [0; SCREEN_PIXELS];
+
+        for (i, pixel) in screen.iter_mut().enumerate() {
+            *pixel = self.cpu.peek(SCREEN + i as u16) & 0x0f;
+        }
+
+        screen
+    }
+
+    /// Render the screen into a frame.
+    pub fn frame(&self) -> Frame {
+        Frame::from_easy6502(&self.cpu)
+    }
+
+    /// Advance the random number generator (SplitMix64) and return the top
+    /// byte of its output.
+    fn next_random(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        (z >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Find the pixels of the given color on the screen.
+    fn find(screen: &[u8; SCREEN_PIXELS], color: u8) -> Vec<usize> {
+        (0..SCREEN_PIXELS).filter(|&i| screen[i] == color).collect()
+    }
+
+    #[test]
+    fn test_random() {
+        // Store two reads of $FE into $00 and $01.
+        let program = vec![0xa5, 0xfe, 0x85, 0x00, 0xa5, 0xfe, 0x85, 0x01, 0x00];
+
+        let mut a = Easy6502::with_seed(program.clone(), 1);
+        let mut b = Easy6502::with_seed(program.clone(), 1);
+        let mut c = Easy6502::with_seed(program, 2);
+        a.run(100).unwrap();
+        b.run(100).unwrap();
+        c.run(100).unwrap();
+
+        assert_eq!(a.cpu.peek(0x00), b.cpu.peek(0x00));
+        assert_eq!(a.cpu.peek(0x01), b.cpu.peek(0x01));
+        assert_ne!(a.cpu.peek(0x00), a.cpu.peek(0x01));
+        assert_ne!(
+            [a.cpu.peek(0x00), a.cpu.peek(0x01)],
+            [c.cpu.peek(0x00), c.cpu.peek(0x01)]
+        );
+    }
+
+    #[test]
+    fn test_press_key() {
+        // Copy $FF into $00.
+        let mut host = Easy6502::with_seed(vec![0xa5, 0xff, 0x85, 0x00, 0x00], 0);
+        host.press_key(KEY_LEFT);
+
+        assert!(!host.run(10).unwrap());
+        assert_eq!(host.last_key(), b'a');
+        assert_eq!(host.cpu.peek(0x00), b'a');
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut host = Easy6502::with_seed(vec![0xe8, 0x02], 0);
+
+        assert_eq!(
+            host.run(10),
+            Err(cpu::Error::UnknownOpcode {
+                opcode: 0x02,
+                addr: 0x0601
+            })
+        );
+        assert_eq!(host.cpu.index_x, 1);
+    }
+
+    #[test]
+    fn test_screen() {
+        let mut host = Easy6502::with_seed(
+            vec![
+                0xa9, 0x01, // load white into the accumulator
+                0x8d, 0x21, 0x02, // store it at (1, 1)
+                0xa9, 0xf2, // load red, with the high nibble set
+                0x8d, 0xff, 0x05, // store it in the bottom right pixel
+                0x00,
+            ],
+            0,
+        );
+        host.run(10).unwrap();
+
+        let screen = host.screen();
+        assert_eq!(find(&screen, 1), vec![33]);
+        assert_eq!(find(&screen, 2), vec![SCREEN_PIXELS - 1]);
+        assert_eq!(host.frame().pixel(1, 1), PALETTE[1]);
+    }
+
+    #[test]
+    fn test_snake_source() {
+        // The same bytes as the tutorial's own assembler produces.
+        let bytes = snake();
+        assert_eq!(bytes.len(), 309);
+        assert_eq!(crc32fast::hash(&bytes), 0xc838_f0f6);
+    }
+
+    #[test]
+    fn test_snake() {
+        let mut host = Easy6502::with_seed(snake(), 6502);
+        let head = |host: &Easy6502| u16::from_le_bytes([host.cpu.peek(0x10), host.cpu.peek(0x11)]);
+
+        // The snake starts out moving right, and is drawn in white.
+        assert!(host.run(2_000).unwrap());
+        let start = head(&host);
+        assert!(host.run(2_000).unwrap());
+        assert!(head(&host) > start);
+        assert_eq!(host.screen()[usize::from(head(&host) - SCREEN)], 1);
+
+        // Steer it down, so that it moves a whole row at a time until it hits
+        // the bottom wall and the game ends.
+        host.press_key(KEY_DOWN);
+        assert!(host.run(2_000).unwrap());
+        let start = head(&host);
+        assert!(host.run(2_000).unwrap());
+        assert!(head(&host) > start);
+        assert_eq!((head(&host) - start) % 32, 0);
+
+        assert!(!host.run(1_000_000).unwrap());
+    }
+}
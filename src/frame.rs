@@ -0,0 +1,278 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cpu::CPU,
+    easy6502::{PALETTE, SCREEN, SCREEN_SIZE},
+};
+
+/// A single rendered frame, stored as 8-bit RGB pixels in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Create a black frame with the given dimensions.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+        }
+    }
+
+    /// Create a frame from a buffer of RGB pixels.
+    pub fn from_rgb(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 3) as usize,
+            "pixel buffer does not match a {}x{} frame",
+            width,
+            height
+        );
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Capture the easy6502 32x32 screen stored at $0200-$05FF.
+    pub fn from_easy6502(cpu: &CPU) -> Self {
+        let mut frame = Self::new(SCREEN_SIZE, SCREEN_SIZE);
+
+        for y in 0..SCREEN_SIZE {
+            for x in 0..SCREEN_SIZE {
+                let addr = SCREEN + (y * SCREEN_SIZE + x) as u16;
+                let color = PALETTE[usize::from(cpu.peek(addr) & 0x0f)];
+                frame.set_pixel(x, y, color);
+            }
+        }
+
+        frame
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The raw RGB pixel data of the frame.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Get the color of the pixel at the given coordinates.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = self.index(x, y);
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Set the color of the pixel at the given coordinates.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        let i = self.index(x, y);
+        self.pixels[i..i + 3].copy_from_slice(&color);
+    }
+
+    /// Encode the frame as a PNG image into the given writer.
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+
+    /// Encode the frame as a PNG image and save it to the given path.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_png(&mut file)?;
+        file.flush()
+    }
+
+    /// Load a frame from the PNG image at the given path, converting it to 8-bit
+    /// RGB.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut buf = vec![
+            0;
+            reader
+                .output_buffer_size()
+                .ok_or_else(|| io::Error::other("PNG image is too large"))?
+        ];
+        let info = reader.next_frame(&mut buf).map_err(io::Error::other)?;
+
+        let pixels = buf[..info.buffer_size()]
+            .chunks_exact(info.color_type.samples())
+            .flat_map(|px| match info.color_type {
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => [px[0], px[0], px[0]],
+                _ => [px[0], px[1], px[2]],
+            })
+            .collect();
+
+        Ok(Self::from_rgb(info.width, info.height, pixels))
+    }
+
+    /// Count the pixels that differ between this frame and another of the same
+    /// size, or return None if the sizes don't match.
+    pub fn diff(&self, other: &Frame) -> Option<usize> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+
+        Some(
+            self.pixels
+                .chunks_exact(3)
+                .zip(other.pixels.chunks_exact(3))
+                .filter(|(a, b)| a != b)
+                .count(),
+        )
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        ((y * self.width + x) * 3) as usize
+    }
+}
+
+/// Compare a frame against the reference PNG at the given path. If the
+/// reference is missing or doesn't match, write the frame next to it with an
+/// `.actual.png` extension and panic with a description of the difference.
+pub fn assert_reference_frame<P: AsRef<Path>>(frame: &Frame, reference: P) {
+    let reference = reference.as_ref();
+
+    let failure = match Frame::load_png(reference) {
+        Ok(expected) => match frame.diff(&expected) {
+            Some(0) => return,
+            Some(n) => format!("{} of {} pixels differ", n, frame.width * frame.height),
+            None => format!(
+                "expected a {}x{} frame, got {}x{}",
+                expected.width, expected.height, frame.width, frame.height
+            ),
+        },
+        Err(e) => format!("could not load reference image: {}", e),
+    };
+
+    let actual = actual_path(reference);
+    frame
+        .save_png(&actual)
+        .unwrap_or_else(|e| panic!("could not write {}: {}", actual.display(), e));
+
+    panic!(
+        "frame does not match {}: {}; actual frame written to {}",
+        reference.display(),
+        failure,
+        actual.display()
+    );
+}
+
+/// Get the path to write a mismatched frame to, e.g. `title.png` becomes
+/// `title.actual.png`.
+fn actual_path(reference: &Path) -> PathBuf {
+    let stem = reference
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    reference.with_file_name(format!("{}.actual.png", stem))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, panic};
+
+    use super::*;
+
+    fn checkerboard() -> Frame {
+        let mut frame = Frame::new(4, 3);
+
+        for y in 0..3 {
+            for x in 0..4 {
+                if (x + y) % 2 == 0 {
+                    frame.set_pixel(x, y, [0xff, 0x80, x as u8]);
+                }
+            }
+        }
+
+        frame
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let frame = checkerboard();
+        let path = env::temp_dir().join("nes799_test_png_round_trip.png");
+
+        frame.save_png(&path).unwrap();
+        let loaded = Frame::load_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, frame);
+    }
+
+    #[test]
+    fn test_diff() {
+        let frame = checkerboard();
+        let mut other = frame.clone();
+        other.set_pixel(1, 1, [1, 2, 3]);
+
+        assert_eq!(frame.diff(&frame), Some(0));
+        assert_eq!(frame.diff(&other), Some(1));
+        assert_eq!(frame.diff(&Frame::new(3, 4)), None);
+    }
+
+    #[test]
+    fn test_from_easy6502() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![
+            0xa9, 0x01, // load white into the accumulator
+            0x8d, 0x00, 0x02, // store it in the top left pixel
+            0xa9, 0x02, // load red into the accumulator
+            0x8d, 0xff, 0x05, // store it in the bottom right pixel
+            0x00,
+        ]);
+
+        let frame = Frame::from_easy6502(&cpu);
+
+        assert_eq!(frame.pixel(0, 0), PALETTE[1]);
+        assert_eq!(frame.pixel(31, 31), PALETTE[2]);
+        assert_eq!(frame.pixel(1, 0), PALETTE[0]);
+    }
+
+    #[test]
+    fn test_assert_reference_frame() {
+        let frame = checkerboard();
+        let reference = env::temp_dir().join("nes799_test_reference.png");
+        let actual = actual_path(&reference);
+
+        frame.save_png(&reference).unwrap();
+        assert_reference_frame(&frame, &reference);
+        assert!(!actual.exists());
+
+        let mut other = frame.clone();
+        other.set_pixel(0, 0, [1, 2, 3]);
+
+        let result = panic::catch_unwind(|| assert_reference_frame(&other, &reference));
+        assert!(result.is_err());
+        assert_eq!(Frame::load_png(&actual).unwrap(), other);
+
+        fs::remove_file(&reference).unwrap();
+        fs::remove_file(&actual).unwrap();
+    }
+}
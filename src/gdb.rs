@@ -0,0 +1,730 @@
+//! A stub for GDB's remote serial protocol, which lets GDB-compatible
+//! debuggers control a [`CPU`] over TCP.
+//!
+//! The registers are described to the debugger by a target description in the
+//! order A, X, Y, SP, PC, P, with PC 16 bits wide and the rest 8 bits wide.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::cpu::{
+    status::Status,
+    watch::{WatchKind, Watchpoint, Watchpoints},
+    CPU,
+};
+
+/// Byte a debugger sends to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+/// Number of instructions to run between checks for an interrupt.
+const INTERRUPT_CHECK: u64 = 4096;
+
+/// Largest packet the stub accepts, which it reports to the debugger.
+const PACKET_SIZE: usize = 0x1000;
+
+/// Signal numbers used in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes799.6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// What to do after handling a packet.
+#[derive(Debug)]
+enum Action {
+    Reply(String),
+    /// Run the program, stopping after a single instruction if stepping.
+    Resume {
+        step: bool,
+    },
+    /// Acknowledge, then close the connection.
+    Detach,
+    /// Close the connection without replying.
+    Kill,
+}
+
+/// A packet read from the debugger, or the interrupt byte sent between
+/// packets.
+enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+/// A connection to a debugger, which reads and writes packets with their
+/// checksums and acknowledgements.
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    /// Whether the debugger asked to stop acknowledging packets.
+    no_ack: bool,
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if !self.buffer.is_empty() {
+            return Ok(Some(self.buffer.remove(0)));
+        }
+
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Read the next packet, skipping acknowledgements, or `None` once the
+    /// debugger disconnects.
+    fn read(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+
+            let mut data = vec![];
+            let mut sum = 0u8;
+
+            loop {
+                let Some(byte) = self.read_byte()? else {
+                    return Ok(None);
+                };
+
+                match byte {
+                    b'#' => break,
+                    b'}' => {
+                        let Some(escaped) = self.read_byte()? else {
+                            return Ok(None);
+                        };
+                        sum = sum.wrapping_add(byte).wrapping_add(escaped);
+                        data.push(escaped ^ 0x20);
+                    }
+                    _ => {
+                        sum = sum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                *digit = self
+                    .read_byte()?
+                    .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+            }
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                == Some(sum);
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            // A corrupted packet is sent again, so read the next one instead.
+            if valid {
+                return Ok(Some(Incoming::Packet(
+                    String::from_utf8_lossy(&data).into_owned(),
+                )));
+            }
+        }
+    }
+
+    /// Send a packet, escaping the characters the protocol reserves.
+    fn write(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        let mut sum = 0u8;
+
+        for byte in data.bytes() {
+            let bytes = match byte {
+                b'$' | b'#' | b'}' | b'*' => vec![b'}', byte ^ 0x20],
+                _ => vec![byte],
+            };
+
+            for byte in bytes {
+                sum = sum.wrapping_add(byte);
+                packet.push(byte);
+            }
+        }
+
+        write!(packet, "#{:02x}", sum)?;
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+
+    /// Whether the debugger has sent the interrupt byte, without blocking.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Ok(false),
+            Ok(_) if byte[0] == INTERRUPT => Ok(true),
+            Ok(_) => {
+                self.buffer.push(byte[0]);
+                Ok(false)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Serves a CPU to GDB-compatible debuggers.
+#[derive(Debug)]
+pub struct GdbServer {
+    pub cpu: CPU,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl GdbServer {
+    /// Serve a CPU that's already loaded and reset.
+    pub fn new(cpu: CPU) -> Self {
+        Self {
+            cpu,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Wait for a debugger to connect on the given address, and serve it until
+    /// it detaches or disconnects.
+    pub fn listen<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        eprintln!("waiting for a debugger on {}", listener.local_addr()?);
+
+        let (stream, peer) = listener.accept()?;
+        eprintln!("debugger connected from {}", peer);
+
+        self.serve(stream)
+    }
+
+    /// Serve a debugger over a connected stream until it detaches or
+    /// disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        let mut connection = Connection {
+            stream,
+            buffer: vec![],
+            no_ack: false,
+        };
+
+        while let Some(incoming) = connection.read()? {
+            let Incoming::Packet(packet) = incoming else {
+                // Interrupting a program that isn't running just reports that
+                // it's stopped.
+                connection.write(&format!("S{:02x}", SIGINT))?;
+                continue;
+            };
+
+            match self.handle(&packet) {
+                Action::Reply(reply) => connection.write(&reply)?,
+                Action::Resume { step } => {
+                    let reply = self.resume(&mut connection, step)?;
+                    connection.write(&reply)?;
+                }
+                Action::Detach => {
+                    connection.write("OK")?;
+                    return Ok(());
+                }
+                Action::Kill => return Ok(()),
+            }
+
+            if packet == "QStartNoAckMode" {
+                connection.no_ack = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a packet, other than running the program.
+    fn handle(&mut self, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+        let error = || reply("E01");
+
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        match command {
+            "?" => Action::Reply(format!("S{:02x}", SIGTRAP)),
+            "g" => Action::Reply(self.read_registers()),
+            "G" => match decode_hex(args) {
+                Some(bytes) if bytes.len() == REGISTERS.len() + 1 => {
+                    let mut bytes = bytes.into_iter();
+                    for register in REGISTERS {
+                        let value = match register {
+                            Register::PC => u16::from_le_bytes([
+                                bytes.next().unwrap_or_default(),
+                                bytes.next().unwrap_or_default(),
+                            ]),
+                            _ => bytes.next().unwrap_or_default().into(),
+                        };
+                        register.set(&mut self.cpu, value);
+                    }
+                    reply("OK")
+                }
+                _ => error(),
+            },
+            "p" => match usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| REGISTERS.get(n))
+            {
+                Some(register) => Action::Reply(register.encode(&self.cpu)),
+                None => error(),
+            },
+            "P" => {
+                let register = args.split_once('=').and_then(|(n, value)| {
+                    let register = REGISTERS.get(usize::from_str_radix(n, 16).ok()?)?;
+                    Some((register, decode_hex(value)?))
+                });
+
+                match register {
+                    Some((register, bytes)) if !bytes.is_empty() && bytes.len() <= 2 => {
+                        let value = u16::from_le_bytes([
+                            bytes[0],
+                            bytes.get(1).copied().unwrap_or_default(),
+                        ]);
+                        register.set(&mut self.cpu, value);
+                        reply("OK")
+                    }
+                    _ => error(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let mut hex = String::new();
+                    for i in 0..len {
+                        let _ = write!(hex, "{:02x}", self.cpu.peek(addr.wrapping_add(i)));
+                    }
+                    Action::Reply(hex)
+                }
+                None => error(),
+            },
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let bytes = decode_hex(data)?;
+                    (bytes.len() == usize::from(len)).then_some((addr, bytes))
+                });
+
+                match write {
+                    Some((addr, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            self.cpu.poke(addr.wrapping_add(i as u16), byte);
+                        }
+                        reply("OK")
+                    }
+                    None => error(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    self.cpu.program_counter = addr;
+                }
+
+                Action::Resume {
+                    step: command == "s",
+                }
+            }
+            "Z" | "z" => self.set_breakpoint(command == "Z", args),
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            "H" => reply("OK"),
+            _ => self.query(packet),
+        }
+    }
+
+    /// Handle a query or other multi-letter packet, replying with an empty
+    /// packet to those that aren't supported.
+    fn query(&self, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = range.split_once(',').and_then(|(offset, len)| {
+                Some((
+                    usize::from_str_radix(offset, 16).ok()?,
+                    usize::from_str_radix(len, 16).ok()?,
+                ))
+            }) else {
+                return reply("E01");
+            };
+
+            let start = offset.min(TARGET_XML.len());
+            let end = start.saturating_add(len).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+
+            return Action::Reply(format!("{}{}", marker, &TARGET_XML[start..end]));
+        }
+
+        match packet.split(':').next().unwrap_or_default() {
+            "qSupported" => Action::Reply(format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+",
+                PACKET_SIZE
+            )),
+            "QStartNoAckMode" | "qSymbol" => reply("OK"),
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            _ => reply(""),
+        }
+    }
+
+    /// Insert or remove a software breakpoint or watchpoint from a `Z` or `z`
+    /// packet.
+    fn set_breakpoint(&mut self, insert: bool, args: &str) -> Action {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Action::Reply("E01".to_string());
+        };
+        let (Ok(addr), Ok(len)) = (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16))
+        else {
+            return Action::Reply("E01".to_string());
+        };
+
+        let watch_kind = match kind {
+            "0" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return Action::Reply("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            // Access watchpoints are treated as read watchpoints, since there's
+            // no kind for both.
+            "4" => WatchKind::Read,
+            _ => return Action::Reply(String::new()),
+        };
+
+        if insert {
+            let end = addr.saturating_add(len.max(1) - 1);
+            self.cpu
+                .watchpoints
+                .get_or_insert_with(Watchpoints::new)
+                .add(Watchpoint {
+                    range: addr..=end,
+                    kind: watch_kind,
+                });
+        } else if let Some(watchpoints) = self.cpu.watchpoints.as_mut() {
+            watchpoints.remove(addr);
+            if watchpoints.is_empty() {
+                self.cpu.watchpoints = None;
+            }
+        }
+
+        Action::Reply("OK".to_string())
+    }
+
+    /// Run the program until it's interrupted, or stops on its own, returning
+    /// the stop reply.
+    fn resume(&mut self, connection: &mut Connection, step: bool) -> io::Result<String> {
+        let mut instructions = 0u64;
+
+        loop {
+            instructions += 1;
+
+            if self.cpu.try_step().is_err() {
+                return Ok(format!("S{:02x}", SIGILL));
+            }
+
+            if self.cpu.program_counter == 0 {
+                return Ok("W00".to_string());
+            }
+
+            if let Some(watchpoints) = self.cpu.watchpoints.as_mut() {
+                if let Some(hit) = watchpoints.take_hits().first() {
+                    let name = match hit.kind {
+                        WatchKind::Read => "rwatch",
+                        WatchKind::Write | WatchKind::Change => "watch",
+                    };
+                    return Ok(format!("T{:02x}{}:{:04x};", SIGTRAP, name, hit.addr));
+                }
+            }
+
+            if step {
+                return Ok(format!("S{:02x}", SIGTRAP));
+            }
+
+            if self.breakpoints.contains(&self.cpu.program_counter) {
+                return Ok(format!("T{:02x}swbreak:;", SIGTRAP));
+            }
+
+            if instructions.is_multiple_of(INTERRUPT_CHECK) && connection.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    /// Encode every register for a `g` packet.
+    fn read_registers(&self) -> String {
+        REGISTERS
+            .iter()
+            .map(|register| register.encode(&self.cpu))
+            .collect()
+    }
+}
+
+/// The registers in the order of the target description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+    P,
+}
+
+const REGISTERS: [Register; 6] = [
+    Register::A,
+    Register::X,
+    Register::Y,
+    Register::SP,
+    Register::PC,
+    Register::P,
+];
+
+impl Register {
+    /// Encode the register's value as little-endian hex.
+    fn encode(&self, cpu: &CPU) -> String {
+        match self {
+            Self::A => format!("{:02x}", cpu.accumulator),
+            Self::X => format!("{:02x}", cpu.index_x),
+            Self::Y => format!("{:02x}", cpu.index_y),
+            Self::SP => format!("{:02x}", u8::from(cpu.stack_pointer)),
+            Self::PC => {
+                let [lo, hi] = cpu.program_counter.to_le_bytes();
+                format!("{:02x}{:02x}", lo, hi)
+            }
+            Self::P => format!("{:02x}", cpu.status.bits()),
+        }
+    }
+
+    /// Set the register, truncating the value to 8 bits for all but PC.
+    fn set(&self, cpu: &mut CPU, value: u16) {
+        let [lo, _] = value.to_le_bytes();
+
+        match self {
+            Self::A => cpu.accumulator = lo,
+            Self::X => cpu.index_x = lo,
+            Self::Y => cpu.index_y = lo,
+            Self::SP => cpu.stack_pointer = lo.into(),
+            Self::PC => cpu.program_counter = value,
+            Self::P => cpu.status = Status::from(lo),
+        }
+    }
+}
+
+/// Decode a string of hex byte pairs.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse the `addr,length` of a memory packet.
+fn parse_range(range: &str) -> Option<(u16, u16)> {
+    let (addr, len) = range.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, thread};
+
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    /// A scripted debugger connected to a server on another thread.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        /// Start a server for a program assembled at $8000, and connect to it.
+        fn connect(source: &'static str) -> (Self, thread::JoinHandle<()>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr: SocketAddr = listener.local_addr().unwrap();
+
+            // The CPU isn't Send, so it's created on the server's thread.
+            let server = thread::spawn(move || {
+                let mut cpu = CPU::new();
+                cpu.load(assemble(source, 0x8000).unwrap().bytes);
+                cpu.reset();
+
+                let (stream, _) = listener.accept().unwrap();
+                GdbServer::new(cpu).serve(stream).unwrap();
+            });
+
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            (Self { stream }, server)
+        }
+
+        fn send(&mut self, data: &str) {
+            let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        /// Read a reply, checking its checksum and acknowledging it.
+        fn reply(&mut self) -> String {
+            while self.byte() != b'$' {}
+
+            let mut data = vec![];
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+
+            let checksum = [self.byte(), self.byte()];
+            let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+            assert_eq!(
+                std::str::from_utf8(&checksum).unwrap(),
+                format!("{:02x}", sum)
+            );
+            self.stream.write_all(b"+").unwrap();
+
+            String::from_utf8(data).unwrap()
+        }
+
+        /// Send a packet, check that it's acknowledged, and return the reply.
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            assert_eq!(self.byte(), b'+');
+            self.reply()
+        }
+    }
+
+    const PROGRAM: &str = "
+                ldx #0
+        loop:   inx
+                stx $10
+                cpx #3
+                bne loop
+                jmp 0
+    ";
+
+    #[test]
+    fn test_session() {
+        let (mut client, server) = Client::connect(PROGRAM);
+
+        assert!(client
+            .request("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert!(client
+            .request("qXfer:features:read:target.xml:0,1000")
+            .starts_with("l<?xml"));
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("g"), "000000fd008034");
+        assert_eq!(client.request("m8000,3"), "a200e8");
+
+        // Memory writes.
+        assert_eq!(client.request("M0200,2:dead"), "OK");
+        assert_eq!(client.request("m0200,2"), "dead");
+
+        // Single-stepping and register writes.
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p4"), "0280");
+        assert_eq!(client.request("P0=42"), "OK");
+        assert_eq!(client.request("p0"), "42");
+
+        // Breakpoints.
+        assert_eq!(client.request("Z0,8002,1"), "OK");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("p1"), "01");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("p1"), "02");
+        assert_eq!(client.request("z0,8002,1"), "OK");
+
+        // Watchpoints.
+        assert_eq!(client.request("Z2,0010,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:0010;");
+        assert_eq!(client.request("m0010,1"), "03");
+        assert_eq!(client.request("z2,0010,1"), "OK");
+
+        assert_eq!(client.request("c"), "W00");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        assert_eq!(client.request("D"), "OK");
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_malformed_packets() {
+        let (mut client, server) = Client::connect(PROGRAM);
+
+        // A corrupted packet is rejected, and the next one read.
+        client.stream.write_all(b"$g#00").unwrap();
+        assert_eq!(client.byte(), b'-');
+        assert_eq!(client.request("?"), "S05");
+
+        // Unknown commands get an empty reply, even if they aren't ASCII.
+        client.stream.write_all(b"$\xff#ff").unwrap();
+        assert_eq!(client.byte(), b'+');
+        assert_eq!(client.reply(), "");
+        assert_eq!(client.request("m8000,1"), "a2");
+
+        client.send("k");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let (mut client, server) = Client::connect("loop: jmp loop");
+
+        assert_eq!(client.request("QStartNoAckMode"), "OK");
+
+        // Without acknowledgements, replies follow packets directly.
+        client.send("c");
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(client.reply(), "S02");
+
+        client.send("g");
+        assert_eq!(client.reply(), "000000fd008034");
+
+        client.send("k");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+//! Running a program for a number of frames without any output, hashing the
+//! state of the machine at chosen frames so that regressions can be caught by
+//! comparing hashes instead of images.
+//!
+//! Input comes from a script of the buttons held from each frame onward, like:
+//!
+//! ```text
+//! # frame  controller 1  controller 2
+//! 0        -
+//! 30       start
+//! 32       -
+//! 60       a+right       b
+//! ```
+
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+
+use crate::{
+    controller::Button,
+    cpu::{self, mode::Mode, CPU},
+    frame::Frame,
+    movie::{FrameClock, MovieFrame},
+};
+
+/// Size of the CPU's internal RAM at $0000-$07FF, which is hashed.
+const RAM_SIZE: u16 = 0x0800;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Controller input for a headless run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputScript {
+    /// The buttons held on each controller, by the frame they're first held on.
+    changes: BTreeMap<u64, [Button; 2]>,
+}
+
+impl InputScript {
+    /// Load and parse the input script at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse an input script, where each line is a frame followed by the
+    /// buttons held on controller 1 and optionally controller 2 from that
+    /// frame onward. Buttons are joined with `+`, `-` means no buttons, and
+    /// `#` starts a comment.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut script = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| invalid(format!("line {}: {}", i + 1, message));
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            let (frame, controllers) = match words[..] {
+                [] => continue,
+                [frame, ref controllers @ ..] if controllers.len() <= 2 => (frame, controllers),
+                _ => return Err(error("expected a frame and up to two controllers".into())),
+            };
+
+            let frame = frame
+                .parse()
+                .map_err(|_| error(format!("invalid frame '{}'", frame)))?;
+
+            let mut buttons = [Button::none(); 2];
+            for (buttons, names) in buttons.iter_mut().zip(controllers) {
+                *buttons = parse_buttons(names).map_err(error)?;
+            }
+
+            if script.changes.insert(frame, buttons).is_some() {
+                return Err(error(format!("frame {} is already set", frame)));
+            }
+        }
+
+        Ok(script)
+    }
+
+    /// The buttons held on each controller on the given frame.
+    pub fn buttons(&self, frame: u64) -> [Button; 2] {
+        self.changes
+            .range(..=frame)
+            .next_back()
+            .map_or([Button::none(); 2], |(_, &buttons)| buttons)
+    }
+}
+
+/// Parse buttons like `a+start`, or `-` for none.
+fn parse_buttons(names: &str) -> Result<Button, String> {
+    if names == "-" {
+        return Ok(Button::none());
+    }
+
+    names.split('+').try_fold(Button::none(), |buttons, name| {
+        Button::NAMES
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|&(button, _)| buttons | button)
+            .ok_or_else(|| {
+                let names: Vec<_> = Button::NAMES.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "unknown button '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    })
+}
+
+/// CRC32 hashes of the machine's output and RAM after a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHashes {
+    /// The number of frames run before the hashes were taken.
+    pub frame: u64,
+    /// The easy6502 screen, which is the only framebuffer there is, so it's
+    /// only hashed in the 6502 mode.
+    pub screen: Option<u32>,
+    /// The CPU's internal RAM at $0000-$07FF.
+    pub ram: u32,
+    /// Every audio sample mixed since the run started.
+    pub audio: u32,
+}
+
+impl fmt::Display for FrameHashes {
+    /// Format the hashes as one line, e.g.
+    /// `frame 60: screen -------- ram 1a2b3c4d audio 5e6f7a8b`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frame {}: screen ", self.frame)?;
+
+        match self.screen {
+            Some(screen) => write!(f, "{:08x}", screen)?,
+            None => f.write_str("--------")?,
+        }
+
+        write!(f, " ram {:08x} audio {:08x}", self.ram, self.audio)
+    }
+}
+
+/// Run the CPU with input from the script until the last of the given frames,
+/// hashing its state after each of them. Frame 0 is the state before running.
+pub fn run(
+    cpu: &mut CPU,
+    script: &InputScript,
+    frames: &[u64],
+) -> Result<Vec<FrameHashes>, cpu::Error> {
+    let mut clock = FrameClock::new(cpu);
+    let mut audio = crc32fast::Hasher::new();
+    let mut hashes = Vec::with_capacity(frames.len());
+    let Some(&last) = frames.iter().max() else {
+        return Ok(hashes);
+    };
+
+    cpu.apu.take_samples();
+
+    for frame in 0..=last {
+        if frames.contains(&frame) {
+            hashes.push(FrameHashes {
+                frame,
+                screen: (cpu.mode == Mode::Mos6502)
+                    .then(|| crc32fast::hash(Frame::from_easy6502(cpu).pixels())),
+                ram: crc32fast::hash(&(0..RAM_SIZE).map(|addr| cpu.peek(addr)).collect::<Vec<_>>()),
+                audio: audio.clone().finalize(),
+            });
+        }
+
+        if frame < last {
+            let input = MovieFrame {
+                buttons: script.buttons(frame),
+                ..Default::default()
+            };
+            clock.run_frame(cpu, &input)?;
+
+            for sample in cpu.apu.take_samples() {
+                audio.update(&sample.to_le_bytes());
+            }
+        }
+    }
+
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    /// A CPU running a program that stores controller 1's buttons at $10 each
+    /// frame until start is pressed, then plays a tone.
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load(
+            assemble(
+                "
+                @frame: lda #1
+                        sta $4016
+                        lda #0
+                        sta $4016
+                        ldx #8
+                @read:  lda $4016
+                        lsr
+                        ror $10
+                        dex
+                        bne @read
+                        lda $10
+                        and #%00001000
+                        beq @frame
+                        lda #1
+                        sta $4015
+                        lda #$bf
+                        sta $4000
+                        lda #$fd
+                        sta $4002
+                        lda #$f8
+                        sta $4003
+                @play:  jmp @play
+                ",
+                0x8000,
+            )
+            .unwrap()
+            .bytes,
+        );
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let script = InputScript::parse(
+            "
+            # frame  1       2
+            10       a+Right
+            20       -       select
+            5        b       # comment
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(script.buttons(0), [Button::none(); 2]);
+        assert_eq!(script.buttons(5), [Button::B, Button::none()]);
+        assert_eq!(
+            script.buttons(19),
+            [Button::A | Button::Right, Button::none()]
+        );
+        assert_eq!(script.buttons(100), [Button::none(), Button::Select]);
+    }
+
+    #[test]
+    fn test_parse_script_errors() {
+        let error = |text: &str| InputScript::parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("0 a\n1 a+turbo"),
+            "line 2: unknown button 'turbo', expected one of a, b, select, start, up, down, left, right"
+        );
+        assert_eq!(error("x a"), "line 1: invalid frame 'x'");
+        assert_eq!(error("1 a\n1 b"), "line 2: frame 1 is already set");
+        assert_eq!(
+            error("1 a b c"),
+            "line 1: expected a frame and up to two controllers"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let script = InputScript::parse("2 a\n4 start").unwrap();
+        let hashes = run(&mut cpu(), &script, &[6, 0, 1, 3]).unwrap();
+
+        let frames: Vec<u64> = hashes.iter().map(|hashes| hashes.frame).collect();
+        assert_eq!(frames, vec![0, 1, 3, 6]);
+
+        // RAM changes once A is pressed.
+        assert_eq!(hashes[0].ram, hashes[1].ram);
+        assert_ne!(hashes[1].ram, hashes[2].ram);
+        assert_eq!(hashes[0].screen, None);
+
+        // Audio changes once start is pressed.
+        let silent = run(&mut cpu(), &InputScript::parse("2 a").unwrap(), &[3, 6]).unwrap();
+        assert_eq!(silent[0].audio, hashes[2].audio);
+        assert_ne!(silent[1].audio, hashes[3].audio);
+
+        // Runs are repeatable.
+        assert_eq!(run(&mut cpu(), &script, &[0, 1, 3, 6]).unwrap(), hashes);
+        assert!(hashes[3].to_string().starts_with(&format!(
+            "frame 6: screen -------- ram {:08x}",
+            hashes[3].ram
+        )));
+    }
+}
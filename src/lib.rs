@@ -0,0 +1,26 @@
+#![deny(
+    clippy::cast_lossless,
+    clippy::cast_ptr_alignment,
+    clippy::char_lit_as_u8,
+    clippy::checked_conversions,
+    clippy::unnecessary_cast
+)]
+
+pub mod apu;
+pub mod battery;
+pub mod blargg;
+pub mod cartridge;
+pub mod controller;
+pub mod cpu;
+pub mod debugger;
+pub mod easy6502;
+pub mod frame;
+pub mod gdb;
+pub mod headless;
+pub mod mapper;
+pub mod movie;
+pub mod nsf;
+pub mod rewind;
+pub mod state;
+pub mod terminal;
+pub mod wav;
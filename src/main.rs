@@ -6,39 +6,614 @@
     clippy::unnecessary_cast
 )]
 
-use cpu::mode::Mode;
-
-use crate::cpu::CPU;
-
-pub mod cpu;
-
-fn main() {
-    let game_code = vec![
-        0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
-        0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
-        0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
-        0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
-        0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
-        0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
-        0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
-        0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
-        0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
-        0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
-        0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
-        0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
-        0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
-        0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
-        0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
-        0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
-        0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
-        0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
-        0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
-        0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
-        0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
-    ];
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+
+use nes799::{
+    apu::DEFAULT_SAMPLE_RATE,
+    battery::{self, Battery},
+    cartridge::{self, Cartridge},
+    cpu::{
+        disassembler::disassemble,
+        memory::{MEMORY_SIZE, RESET},
+        mode::Mode,
+        trace::{TraceFormat, TraceWriter},
+        CPU, CYCLES_PER_FRAME,
+    },
+    debugger::Debugger,
+    easy6502::{self, Easy6502},
+    gdb::GdbServer,
+    headless::{self, InputScript},
+    movie::{self, Movie, MovieFrame, Playback, Recorder},
+    nsf::{self, Nsf, NsfPlayer},
+    state::{self, MAX_SLOT},
+    terminal::{self, Stop},
+    wav::WavWriter,
+};
+
+/// Number of frames to record when writing a WAV file without a cycle limit, or
+/// a movie without another to play.
+const DEFAULT_FRAMES: u64 = 600;
+/// Frames between checkpoints in a recorded movie when
+/// `--checkpoint-interval` isn't given.
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 60;
+/// Instructions per second to run easy6502 programs at when `--speed` isn't
+/// given.
+const DEFAULT_SPEED: u32 = 10_000;
+
+/// Address to disassemble a raw binary at when `--origin` isn't given.
+const DEFAULT_ORIGIN: u16 = 0x8000;
+
+/// Number of samples to buffer before writing them to a WAV file.
+const WAV_BUFFER: usize = 4096;
+
+/// Exit status when an instruction or cycle limit is reached before the
+/// program halts.
+const EXIT_LIMIT: u8 = 3;
+
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  the program halted by jumping to $0000, or was quit
+  1  the program could not be loaded, hit an unrecognized opcode, or desynced
+     from a movie
+  2  the arguments were invalid
+  3  --max-cycles or --max-instructions was reached first";
+
+/// Run 6502 programs, NES ROMs, and NSF music.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    after_help = EXIT_STATUS_HELP,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// An iNES ROM, NSF file, or raw binary to run. Runs the built-in snake
+    /// game if omitted. A ROM with battery-backed RAM keeps it in a .sav file
+    /// next to the ROM.
+    program: Option<PathBuf>,
+
+    /// CPU mode: mos6502 or nes2a03. Defaults to mos6502 with --easy6502, and
+    /// nes2a03 otherwise.
+    #[arg(long)]
+    mode: Option<Mode>,
+
+    /// Address to load a raw binary at, in hex. Defaults to the start of
+    /// program ROM for the mode.
+    #[arg(long, value_parser = parse_address, value_name = "ADDR")]
+    load_addr: Option<u16>,
+
+    /// Address to start running from, in hex, instead of the reset vector.
+    #[arg(long, value_parser = parse_address, value_name = "ADDR")]
+    entry: Option<u16>,
+
+    /// Stop after this many CPU cycles.
+    #[arg(long, value_name = "COUNT")]
+    max_cycles: Option<u64>,
+
+    /// Stop after this many instructions.
+    #[arg(long, value_name = "COUNT")]
+    max_instructions: Option<u64>,
+
+    /// Write a trace to stdout: instruction for a line per instruction,
+    /// nestest for a line per instruction formatted like nestest.log, or fetch
+    /// for a line per byte fetched.
+    #[arg(long, value_name = "FORMAT")]
+    trace: Option<TraceFormat>,
+
+    /// Run in the easy6502 environment, drawing its screen in the terminal.
+    #[arg(long)]
+    easy6502: bool,
+
+    /// Run the program in an interactive debugger, stopped at the entry point.
+    #[arg(long, conflicts_with = "easy6502")]
+    debug: bool,
+
+    /// Wait for a GDB-compatible debugger to connect on this address, e.g.
+    /// 127.0.0.1:6502, and let it control the program.
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["easy6502", "debug"])]
+    gdb: Option<String>,
+
+    /// Restore the machine from a numbered save slot next to the program
+    /// before running it.
+    #[arg(long, value_name = "SLOT", value_parser = parse_slot)]
+    load_state: Option<u8>,
+
+    /// Save the machine to a numbered save slot next to the program once it
+    /// stops.
+    #[arg(
+        long,
+        value_name = "SLOT",
+        value_parser = parse_slot,
+        conflicts_with_all = ["easy6502", "debug", "gdb", "movie", "record_movie", "hash_frames"]
+    )]
+    save_state: Option<u8>,
+
+    /// Play an FM2 movie, failing if the frame hash at any of its checkpoints
+    /// doesn't match.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["easy6502", "debug", "gdb"])]
+    movie: Option<PathBuf>,
+
+    /// Write an FM2 movie of the run with a checkpoint every
+    /// --checkpoint-interval frames, re-recording the input of --movie, or
+    /// recording --frames frames with no input.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["easy6502", "debug", "gdb"])]
+    record_movie: Option<PathBuf>,
+
+    /// Frames between checkpoints written with --record-movie.
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = DEFAULT_CHECKPOINT_INTERVAL,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    checkpoint_interval: u64,
+
+    /// Run until the last of these frames without any output, printing CRC32
+    /// hashes of the screen, RAM, and audio after each of them, e.g. 0,60,600.
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        value_delimiter = ',',
+        conflicts_with_all = ["easy6502", "debug", "gdb", "movie", "record_movie"]
+    )]
+    hash_frames: Vec<u64>,
+
+    /// Input script for --hash-frames, with a line per change of buttons like
+    /// `30 a+start`, giving the frame and the buttons held on each controller
+    /// from then on.
+    #[arg(long, value_name = "PATH", requires = "hash_frames")]
+    input: Option<PathBuf>,
+
+    /// Instructions per second to run at with --easy6502.
+    #[arg(long, value_name = "IPS", default_value_t = DEFAULT_SPEED)]
+    speed: u32,
+
+    /// Write the APU's output to a WAV file. Only the nes2a03 mode has an APU.
+    #[arg(long, value_name = "PATH", conflicts_with = "easy6502")]
+    wav: Option<PathBuf>,
+
+    /// Number of frames to record with --wav, unless --max-cycles is given, or
+    /// with --record-movie, unless --movie is given.
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_FRAMES)]
+    frames: u64,
+
+    /// Song to play from an NSF file, starting from 1.
+    #[arg(long, value_name = "NUMBER")]
+    song: Option<u8>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Disassemble an iNES ROM's program banks, or a raw binary.
+    Disasm(DisasmArgs),
+}
+
+#[derive(Debug, Args)]
+struct DisasmArgs {
+    /// The iNES ROM or raw binary to disassemble.
+    path: PathBuf,
+
+    /// Address of the first byte, in hex. Defaults to $8000 for a raw binary,
+    /// and to where each bank is most likely mapped for an iNES ROM.
+    #[arg(long, value_parser = parse_address, value_name = "ADDR")]
+    origin: Option<u16>,
+
+    /// Only disassemble this bank of an iNES ROM's program ROM, starting from
+    /// 0.
+    #[arg(long, value_name = "NUMBER")]
+    bank: Option<usize>,
+}
+
+/// How a program stopped running without an error.
+enum Outcome {
+    Halted,
+    Limit,
+}
+
+/// Parse a hex address, optionally prefixed with `$` or `0x`.
+fn parse_address(s: &str) -> Result<u16, String> {
+    let hex = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+
+    u16::from_str_radix(hex, 16).map_err(|_| format!("invalid address '{}'", s))
+}
+
+/// Parse a save slot number from 0 to [`MAX_SLOT`].
+fn parse_slot(s: &str) -> Result<u8, String> {
+    s.parse()
+        .ok()
+        .filter(|&slot| slot <= MAX_SLOT)
+        .ok_or_else(|| format!("invalid save slot '{}', expected 0-{}", s, MAX_SLOT))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(Outcome::Halted) => ExitCode::SUCCESS,
+        Ok(Outcome::Limit) => ExitCode::from(EXIT_LIMIT),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<Outcome, Box<dyn Error>> {
+    if let Some(Command::Disasm(args)) = &cli.command {
+        disasm(args)?;
+        return Ok(Outcome::Halted);
+    }
+
+    let Some(path) = &cli.program else {
+        check_wav_mode(cli, Mode::Mos6502)?;
+        return run_easy6502(Easy6502::new(easy6502::snake()), cli);
+    };
+
+    let bytes = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    if bytes.starts_with(nsf::MAGIC) {
+        return play_nsf(&Nsf::parse(&bytes)?, cli);
+    }
 
     let mut cpu = CPU::new();
+    cpu.mode = cli.mode.unwrap_or(if cli.easy6502 {
+        Mode::Mos6502
+    } else {
+        Mode::default()
+    });
+    check_wav_mode(cli, cpu.mode)?;
+
+    let checksum = if bytes.starts_with(cartridge::MAGIC) {
+        let cartridge = Cartridge::parse(&bytes)?;
+        cpu.mapper = Some(cartridge.create_mapper()?);
+        movie::rom_checksum(&cartridge)
+    } else {
+        let load_addr = cli.load_addr.unwrap_or(cpu.mode.program_rom() as u16);
+        let end = usize::from(load_addr) + bytes.len();
+
+        if end > MEMORY_SIZE {
+            return Err(format!("program does not fit in memory at ${:04x}", load_addr).into());
+        }
+
+        cpu.load_at(&bytes, load_addr);
+
+        // Point the reset vector at the program, unless it provides its own.
+        if end <= usize::from(RESET) {
+            cpu.load_at(&load_addr.to_le_bytes(), RESET);
+        }
+
+        md5::compute(&bytes).0
+    };
+
+    if let Some(format) = cli.trace {
+        cpu.tracer = Some(Box::new(TraceWriter::new(
+            BufWriter::new(io::stdout()),
+            format,
+        )));
+    }
+
+    cpu.reset();
+
+    if let Some(entry) = cli.entry {
+        cpu.program_counter = entry;
+    }
+
+    // Headless runs and movies start with cleared cartridge RAM so that they're
+    // repeatable, and the easy6502 screen has nowhere to save it on exit.
+    let mut battery = if cli.hash_frames.is_empty()
+        && cli.movie.is_none()
+        && cli.record_movie.is_none()
+        && !cli.easy6502
+    {
+        Battery::open(&mut cpu, path, battery::DEFAULT_FLUSH_INTERVAL)?
+    } else {
+        None
+    };
+
+    if let Some(slot) = cli.load_state {
+        state::load_slot(&mut cpu, path, slot)?;
+    }
+
+    if !cli.hash_frames.is_empty() {
+        run_headless(&mut cpu, cli)
+    } else if cli.movie.is_some() || cli.record_movie.is_some() {
+        run_movie(&mut cpu, cli, path, checksum)
+    } else if let Some(addr) = &cli.gdb {
+        let mut server = GdbServer::new(cpu);
+        let result = server.listen(addr);
+        flush_battery(battery.as_mut(), &server.cpu)?;
+        result?;
+        Ok(Outcome::Halted)
+    } else if cli.debug {
+        let mut debugger = Debugger::new(cpu);
+        let result = debugger.repl(io::stdin().lock(), io::stdout());
+        flush_battery(battery.as_mut(), &debugger.cpu)?;
+        result?;
+        Ok(Outcome::Halted)
+    } else if cli.easy6502 {
+        run_easy6502(Easy6502::with_cpu(cpu), cli)
+    } else {
+        let outcome = run_cpu(&mut cpu, cli, battery.as_mut())?;
+
+        if let Some(slot) = cli.save_state {
+            let path = state::save_slot(&cpu, path, slot)?;
+            eprintln!("saved state to {}", path.display());
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Write a disassembly listing of a ROM or raw binary to stdout, with a header
+/// before each bank of an iNES ROM's program ROM.
+fn disasm(args: &DisasmArgs) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(&args.path)
+        .map_err(|e| format!("could not read {}: {}", args.path.display(), e))?;
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    if !bytes.starts_with(cartridge::MAGIC) {
+        if args.bank.is_some() {
+            return Err("--bank requires an iNES ROM".into());
+        }
+
+        for instruction in disassemble(&bytes, args.origin.unwrap_or(DEFAULT_ORIGIN)) {
+            writeln!(out, "{}", instruction.listing())?;
+        }
+
+        return Ok(out.flush()?);
+    }
+
+    let cartridge = Cartridge::parse(&bytes)?;
+    let banks = cartridge.prg_banks().len();
+
+    if let Some(bank) = args.bank.filter(|&bank| bank >= banks) {
+        return Err(format!("bank {} is out of range, the ROM has {} banks", bank, banks).into());
+    }
+
+    for (bank, prg) in cartridge.prg_banks().enumerate() {
+        if args.bank.is_some_and(|only| only != bank) {
+            continue;
+        }
+
+        let origin = args
+            .origin
+            .unwrap_or_else(|| cartridge.prg_bank_origin(bank));
+
+        writeln!(out, "; PRG bank {} at ${:04X}", bank, origin)?;
+        for instruction in disassemble(prg, origin) {
+            writeln!(out, "{}", instruction.listing())?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(out.flush()?)
+}
+
+/// Fail if --wav is given for a mode with no APU, which would only write an
+/// empty file.
+fn check_wav_mode(cli: &Cli, mode: Mode) -> Result<(), String> {
+    match &cli.wav {
+        Some(_) if !mode.has_apu() => Err(format!(
+            "--wav needs the nes2a03 mode, since the {} mode has no APU",
+            mode
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Write the cartridge's battery-backed RAM to its `.sav` file if it changed.
+fn flush_battery(battery: Option<&mut Battery>, cpu: &CPU) -> io::Result<()> {
+    if let Some(battery) = battery {
+        if battery.flush(cpu)? {
+            eprintln!("saved {}", battery.path().display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a program until it halts, hits an unrecognized opcode, or reaches a
+/// limit, optionally writing the APU's output to a WAV file.
+fn run_cpu(
+    cpu: &mut CPU,
+    cli: &Cli,
+    mut battery: Option<&mut Battery>,
+) -> Result<Outcome, Box<dyn Error>> {
+    let max_cycles = cli
+        .max_cycles
+        .or_else(|| cli.wav.as_ref().map(|_| cli.frames * CYCLES_PER_FRAME));
+
+    let mut wav = match &cli.wav {
+        Some(path) => Some(WavWriter::create(path, cpu.apu.sample_rate())?),
+        None => None,
+    };
+
+    let mut instructions = 0;
+    let result = loop {
+        if max_cycles.is_some_and(|max| cpu.cycles >= max)
+            || cli.max_instructions.is_some_and(|max| instructions >= max)
+        {
+            break Ok(Outcome::Limit);
+        }
+
+        if let Err(e) = cpu.try_step() {
+            break Err(e);
+        }
+        instructions += 1;
+
+        if let Some(battery) = battery.as_mut() {
+            battery.update(cpu)?;
+        }
+
+        if let Some(wav) = wav.as_mut() {
+            if cpu.apu.samples().len() >= WAV_BUFFER {
+                wav.write_samples(&cpu.apu.take_samples())?;
+            }
+        }
+
+        if cpu.program_counter == 0 {
+            break Ok(Outcome::Halted);
+        }
+    };
+
+    if let Some(mut wav) = wav {
+        wav.write_samples(&cpu.apu.take_samples())?;
+        wav.finish()?;
+    }
+
+    flush_battery(battery, cpu)?;
+
+    eprintln!(
+        "{} after {} instructions and {} cycles",
+        match &result {
+            Ok(Outcome::Halted) => "halted",
+            Ok(Outcome::Limit) => "stopped",
+            Err(_) => "failed",
+        },
+        instructions,
+        cpu.cycles
+    );
+
+    Ok(result?)
+}
+
+/// Run the CPU without any output, printing the hashes at each of the requested
+/// frames.
+fn run_headless(cpu: &mut CPU, cli: &Cli) -> Result<Outcome, Box<dyn Error>> {
+    let script = match &cli.input {
+        Some(path) => InputScript::load(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?,
+        None => InputScript::default(),
+    };
+
+    let mut out = io::stdout().lock();
+    for hashes in headless::run(cpu, &script, &cli.hash_frames)? {
+        writeln!(out, "{}", hashes)?;
+    }
+
+    Ok(Outcome::Halted)
+}
+
+/// Play back or record a movie, writing the recording with checkpoints at the
+/// configured interval.
+fn run_movie(
+    cpu: &mut CPU,
+    cli: &Cli,
+    rom: &Path,
+    checksum: [u8; 16],
+) -> Result<Outcome, Box<dyn Error>> {
+    let interval = cli.checkpoint_interval as usize;
+
+    let recording = match &cli.movie {
+        Some(path) => {
+            let movie = Movie::load(path)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+            if movie.rom_checksum != checksum {
+                eprintln!("warning: the movie was recorded with a different ROM");
+            }
+
+            // A movie played from a save slot starts from that slot's state.
+            let savestate = cli.load_state.map(|_| cpu.save_state());
+            let mut playback = Playback::new(&movie, cpu)?;
+            let mut checkpoints = BTreeMap::new();
+
+            loop {
+                if playback.frame() % interval == 0 || playback.is_finished() {
+                    checkpoints.insert(playback.frame(), movie::frame_hash(cpu));
+                }
+
+                if !playback.run_frame(cpu)? {
+                    break;
+                }
+            }
+
+            eprintln!(
+                "played {} frames, matching {} checkpoints",
+                playback.frame(),
+                movie.checkpoints.len()
+            );
+
+            Movie {
+                checkpoints,
+                savestate: movie.savestate.clone().or(savestate),
+                ..movie
+            }
+        }
+        None => {
+            let name = rom.file_stem().unwrap_or_default().to_string_lossy();
+            let movie = Movie::new(&name, checksum);
+            let mut recorder = match cli.load_state {
+                Some(_) => Recorder::from_save_state(movie, cpu),
+                None => Recorder::new(movie, cpu),
+            };
+
+            for frame in 0..cli.frames as usize {
+                if frame % interval == 0 {
+                    recorder.checkpoint(cpu);
+                }
+                recorder.run_frame(cpu, MovieFrame::default())?;
+            }
+            recorder.checkpoint(cpu);
+
+            eprintln!("recorded {} frames", recorder.frame());
+            recorder.finish()
+        }
+    };
+
+    if let Some(path) = &cli.record_movie {
+        recording.save(path)?;
+    }
+
+    Ok(Outcome::Halted)
+}
+
+/// Run a program in the easy6502 environment, drawing its screen in the
+/// terminal.
+fn run_easy6502(mut host: Easy6502, cli: &Cli) -> Result<Outcome, Box<dyn Error>> {
+    match terminal::run(&mut host, cli.speed)? {
+        Stop::Halted | Stop::Quit => Ok(Outcome::Halted),
+        Stop::Error(e) => Err(e.into()),
+    }
+}
+
+/// Play a song from an NSF file, recording it to a WAV file.
+fn play_nsf(nsf: &Nsf, cli: &Cli) -> Result<Outcome, Box<dyn Error>> {
+    let wav_path = cli
+        .wav
+        .as_ref()
+        .ok_or("playing an NSF file requires --wav")?;
+
+    let song = cli.song.unwrap_or(nsf.starting_song);
+    if !nsf.has_song(song) {
+        return Err(format!(
+            "--song must be from 1 to {}, the number of songs in the file",
+            nsf.total_songs
+        )
+        .into());
+    }
+
+    let plays = cli.frames * CYCLES_PER_FRAME / nsf.cycles_per_play();
+    let mut player = NsfPlayer::new(nsf, song, DEFAULT_SAMPLE_RATE);
+
+    player
+        .record(plays, wav_path)
+        .map_err(|e| format!("could not write {}: {}", wav_path.display(), e))?;
 
-    cpu.mode = Mode::Mos6502;
-    cpu.load_and_run(game_code);
+    Ok(Outcome::Halted)
 }
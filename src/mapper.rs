@@ -0,0 +1,39 @@
+use std::{fmt::Debug, io};
+
+use crate::state::{StateReader, StateWriter};
+
+/// Cartridge hardware that maps program ROM, RAM, and bank switching registers
+/// into the CPU's address space, taking precedence over plain memory.
+pub trait Mapper: Debug {
+    /// Read the byte at the given address, or None if the address isn't mapped
+    /// by the cartridge.
+    fn read(&self, addr: u16) -> Option<u8>;
+
+    /// Write a byte to the given address, returning false if the address isn't
+    /// mapped by the cartridge.
+    fn write(&mut self, addr: u16, value: u8) -> bool;
+
+    /// Save any mutable state, such as bank registers or RAM. Cartridges with
+    /// only ROM have nothing to save.
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    /// Restore the state written by [`Mapper::save_state`].
+    fn load_state(&mut self, _state: &mut StateReader) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The contents of battery-backed RAM, which should persist between runs,
+    /// or None if the cartridge has no battery.
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Replace the contents of battery-backed RAM, failing if the cartridge has
+    /// no battery or the size doesn't match.
+    fn load_battery_ram(&mut self, _bytes: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the cartridge has no battery-backed RAM",
+        ))
+    }
+}
@@ -0,0 +1,775 @@
+//! Recording and playing back controller input a frame at a time, in the FCEUX
+//! FM2 movie format.
+//!
+//! An FM2 file is a text header of `key value` lines followed by a line of
+//! input per frame, like `|0|R..U...A|........||`, with the commands for the
+//! frame and the buttons held on each controller. Movies start from power-on,
+//! or from the save state in the header.
+//!
+//! Frames are timed by the CPU cycles since the movie started, so the same
+//! input always lands on the same cycles. The frame hash of the machine can be
+//! stored at checkpoints, which are written as `comment checkpoint FRAME HASH`
+//! lines so that FCEUX still reads the file.
+
+use std::{collections::BTreeMap, error, fmt, fs, io, path::Path, time::SystemTime};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitmask_enum::bitmask;
+
+use crate::{
+    cartridge::Cartridge,
+    controller::Button,
+    cpu::{self, mode::Mode, CPU, CYCLES_PER_FRAME},
+    frame::Frame,
+};
+
+/// The FM2 format version this module reads and writes.
+pub const FM2_VERSION: u32 = 3;
+
+/// Subject of the comments that hold checkpoints.
+const CHECKPOINT: &str = "checkpoint";
+/// Buttons in the order FM2 lists them, each with the character FCEUX writes
+/// when it is held.
+const BUTTONS: [(Button, char); 8] = [
+    (Button::Right, 'R'),
+    (Button::Left, 'L'),
+    (Button::Down, 'D'),
+    (Button::Up, 'U'),
+    (Button::Start, 'T'),
+    (Button::Select, 'S'),
+    (Button::B, 'B'),
+    (Button::A, 'A'),
+];
+/// Size of the CPU's internal RAM, which a hard reset clears and the frame hash
+/// covers.
+const RAM_SIZE: u16 = 0x0800;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Commands issued at the start of a frame, in FM2's bit order.
+#[bitmask(u8)]
+pub enum FrameCommand {
+    SoftReset,
+    HardReset,
+    FdsInsert,
+    FdsSelect,
+    VsInsertCoin,
+}
+
+impl Default for FrameCommand {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// The input for a single frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: FrameCommand,
+    pub buttons: [Button; 2],
+}
+
+/// A movie of controller input.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// Version of the emulator the movie was recorded with.
+    pub emu_version: u32,
+    pub rerecord_count: u32,
+    /// Name of the ROM the movie was recorded with, without its extension.
+    pub rom_filename: String,
+    /// MD5 of the ROM the movie was recorded with, from [`rom_checksum`].
+    pub rom_checksum: [u8; 16],
+    pub guid: String,
+    pub comments: Vec<String>,
+    /// Save state the movie starts from, or None to start from power-on.
+    pub savestate: Option<Vec<u8>>,
+    /// Expected frame hashes, by the number of frames played before them.
+    pub checkpoints: BTreeMap<usize, u32>,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Create an empty movie for the given ROM, with a new GUID.
+    pub fn new(rom_filename: &str, rom_checksum: [u8; 16]) -> Self {
+        Self {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum,
+            guid: new_guid(),
+            ..Default::default()
+        }
+    }
+
+    /// Load and parse the FM2 file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse a movie in the FM2 text format.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut movie = Self::default();
+        let mut version = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: &str| invalid(format!("line {}: {}", i + 1, message));
+            let line = line.trim_end_matches('\r');
+
+            if line.starts_with('|') {
+                let frame = parse_frame(line).map_err(|e| error(&e))?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || value.parse::<u32>().map_err(|_| error("expected a number"));
+            let flag = |supported: u32, name: &str| match number()? {
+                n if n == supported => Ok(()),
+                _ => Err(error(&format!("{} is not supported", name))),
+            };
+
+            match key {
+                "" => (),
+                "version" => version = Some(number()?),
+                "emuVersion" => movie.emu_version = number()?,
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag" => flag(0, "PAL timing")?,
+                "binary" => flag(0, "binary input")?,
+                "fourscore" => flag(0, "the Four Score")?,
+                "port0" | "port1" => flag(1, "input devices other than gamepads")?,
+                "port2" => flag(0, "expansion port devices")?,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    movie.rom_checksum = parse_binary(value)
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| error("expected a 16 byte checksum"))?;
+                }
+                "guid" => movie.guid = value.to_string(),
+                "comment" => match parse_checkpoint(value) {
+                    Some((frame, hash)) => {
+                        movie.checkpoints.insert(frame, hash);
+                    }
+                    None => movie.comments.push(value.to_string()),
+                },
+                "savestate" => {
+                    movie.savestate = Some(parse_binary(value).map_err(|e| error(&e))?);
+                }
+                // Other keys, like subtitles, don't affect playback.
+                _ => (),
+            }
+        }
+
+        match version {
+            Some(FM2_VERSION) => Ok(movie),
+            Some(version) => Err(invalid(format!(
+                "FM2 version {} is not supported, expected version {}",
+                version, FM2_VERSION
+            ))),
+            None => Err(invalid("not an FM2 movie".to_string())),
+        }
+    }
+
+    /// Write the movie to a file in the FM2 text format.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Movie {
+    /// Format the movie as an FM2 file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", FM2_VERSION)?;
+        writeln!(f, "emuVersion {}", self.emu_version)?;
+        writeln!(f, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(f, "palFlag 0")?;
+        writeln!(f, "romFilename {}", self.rom_filename)?;
+        writeln!(f, "romChecksum base64:{}", BASE64.encode(self.rom_checksum))?;
+        writeln!(f, "guid {}", self.guid)?;
+        writeln!(f, "fourscore 0")?;
+        writeln!(f, "microphone 0")?;
+        writeln!(f, "port0 1")?;
+        writeln!(f, "port1 1")?;
+        writeln!(f, "port2 0")?;
+        writeln!(f, "FDS 0")?;
+        writeln!(f, "NewPPU 0")?;
+
+        for comment in &self.comments {
+            writeln!(f, "comment {}", comment)?;
+        }
+
+        for (frame, hash) in &self.checkpoints {
+            writeln!(f, "comment {} {} {:08x}", CHECKPOINT, frame, hash)?;
+        }
+
+        if let Some(savestate) = &self.savestate {
+            writeln!(f, "savestate base64:{}", BASE64.encode(savestate))?;
+        }
+
+        for frame in &self.frames {
+            write!(f, "|{}|", frame.commands.bits())?;
+
+            for buttons in frame.buttons {
+                for (button, c) in BUTTONS {
+                    write!(f, "{}", if buttons.contains(button) { c } else { '.' })?;
+                }
+                f.write_str("|")?;
+            }
+
+            writeln!(f, "|")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a line of input like `|0|R..U...A|........||`.
+fn parse_frame(line: &str) -> Result<MovieFrame, String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let [_, commands, port0, port1, ..] = fields[..] else {
+        return Err("expected input for two controllers".to_string());
+    };
+
+    let commands = commands
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| format!("invalid commands '{}'", commands))?;
+
+    let mut buttons = [Button::none(); 2];
+    for (buttons, port) in buttons.iter_mut().zip([port0, port1]) {
+        if port.chars().count() != BUTTONS.len() {
+            return Err(format!("expected 8 buttons, got '{}'", port));
+        }
+
+        for ((button, _), c) in BUTTONS.iter().zip(port.chars()) {
+            if c != '.' && c != ' ' {
+                *buttons |= *button;
+            }
+        }
+    }
+
+    Ok(MovieFrame {
+        commands: FrameCommand::from(commands),
+        buttons,
+    })
+}
+
+/// Parse an FM2 binary value, written as `base64:...` or `0x...`.
+fn parse_binary(value: &str) -> Result<Vec<u8>, String> {
+    if let Some(base64) = value.strip_prefix("base64:") {
+        return BASE64
+            .decode(base64)
+            .map_err(|e| format!("invalid base64: {}", e));
+    }
+
+    let hex = value
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("expected base64: or 0x, got '{}'", value))?;
+
+    if hex.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_string());
+    }
+
+    // Split the bytes rather than the string, since slicing a string panics
+    // if a non-ASCII character straddles a pair.
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("invalid hex '{}'", hex))
+}
+
+/// Parse a `checkpoint FRAME HASH` comment.
+fn parse_checkpoint(comment: &str) -> Option<(usize, u32)> {
+    let mut words = comment.split_whitespace();
+    if words.next()? != CHECKPOINT {
+        return None;
+    }
+
+    let frame = words.next()?.parse().ok()?;
+    let hash = u32::from_str_radix(words.next()?, 16).ok()?;
+    words.next().is_none().then_some((frame, hash))
+}
+
+/// Generate a GUID for a new movie from the clock.
+fn new_guid() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let hex: String = md5::compute(nanos.to_le_bytes())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// The MD5 of a cartridge's PRG and CHR ROM, which FCEUX uses to check that a
+/// movie is played with the ROM it was recorded with.
+pub fn rom_checksum(cartridge: &Cartridge) -> [u8; 16] {
+    let mut context = md5::Context::new();
+    context.consume(&cartridge.prg_rom);
+    context.consume(&cartridge.chr_rom);
+    context.finalize().0
+}
+
+/// A hash of the machine's emulated output, to check that playback hasn't
+/// diverged from the recording: the CPU's internal RAM, the easy6502 screen in
+/// the mos6502 mode, and the APU's output level. Unlike a save state, it leaves
+/// out host settings such as the sample rate, so a movie plays back the same
+/// wherever it was recorded.
+pub fn frame_hash(cpu: &CPU) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+
+    for addr in 0..RAM_SIZE {
+        hasher.update(&[cpu.peek(addr)]);
+    }
+    if cpu.mode == Mode::Mos6502 {
+        hasher.update(Frame::from_easy6502(cpu).pixels());
+    }
+    hasher.update(&cpu.apu.output().to_le_bytes());
+
+    hasher.finalize()
+}
+
+/// An error that stops a movie from playing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaybackError {
+    /// The CPU failed to execute the program.
+    Cpu(cpu::Error),
+    /// The frame hash after the given number of frames wasn't the one stored in
+    /// the movie.
+    Desync {
+        frame: usize,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cpu(e) => e.fmt(f),
+            Self::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "movie desynced at frame {}: expected hash {:08x}, got {:08x}",
+                frame, expected, actual
+            ),
+        }
+    }
+}
+
+impl error::Error for PlaybackError {}
+
+impl From<cpu::Error> for PlaybackError {
+    fn from(e: cpu::Error) -> Self {
+        Self::Cpu(e)
+    }
+}
+
+/// Runs the CPU a frame of input at a time, ending each frame a whole frame's
+/// worth of cycles after the end of the last one, so that frames don't drift
+/// with the cycles each instruction overruns them by.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameClock {
+    /// The cycle the current frame ends on.
+    end: u64,
+}
+
+impl FrameClock {
+    /// Start counting frames from the CPU's current cycle.
+    pub fn new(cpu: &CPU) -> Self {
+        Self { end: cpu.cycles }
+    }
+
+    /// Apply a frame's commands and input, and run the CPU until the end of
+    /// the frame. A halted CPU is left where it is.
+    pub fn run_frame(&mut self, cpu: &mut CPU, frame: &MovieFrame) -> Result<(), cpu::Error> {
+        if frame.commands.contains(FrameCommand::HardReset) {
+            for addr in 0..RAM_SIZE {
+                cpu.poke(addr, 0);
+            }
+        }
+
+        if frame
+            .commands
+            .intersects(FrameCommand::SoftReset | FrameCommand::HardReset)
+        {
+            cpu.reset();
+            self.end = cpu.cycles;
+        }
+
+        for (controller, buttons) in cpu.controllers.iter_mut().zip(frame.buttons) {
+            controller.set_buttons(buttons);
+        }
+
+        self.end += CYCLES_PER_FRAME;
+        while cpu.cycles < self.end && cpu.program_counter != 0 {
+            cpu.try_step()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records the input of each frame into a movie as the CPU runs.
+#[derive(Debug)]
+pub struct Recorder {
+    movie: Movie,
+    clock: FrameClock,
+}
+
+impl Recorder {
+    /// Start recording from power-on, on a CPU that has just been loaded and
+    /// reset.
+    pub fn new(movie: Movie, cpu: &CPU) -> Self {
+        Self {
+            movie,
+            clock: FrameClock::new(cpu),
+        }
+    }
+
+    /// Start recording from the CPU's current state, which is saved in the
+    /// movie.
+    pub fn from_save_state(mut movie: Movie, cpu: &CPU) -> Self {
+        movie.savestate = Some(cpu.save_state());
+        Self::new(movie, cpu)
+    }
+
+    /// Run a frame with the given input, and record it.
+    pub fn run_frame(&mut self, cpu: &mut CPU, frame: MovieFrame) -> Result<(), cpu::Error> {
+        self.movie.frames.push(frame);
+        self.clock.run_frame(cpu, &frame)
+    }
+
+    /// Store the frame hash at the current frame, for playback to check.
+    pub fn checkpoint(&mut self, cpu: &CPU) {
+        self.movie
+            .checkpoints
+            .insert(self.movie.frames.len(), frame_hash(cpu));
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frame(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays a movie back a frame at a time, checking the frame hash at each of its
+/// checkpoints.
+#[derive(Debug)]
+pub struct Playback<'a> {
+    movie: &'a Movie,
+    frame: usize,
+    clock: FrameClock,
+}
+
+impl<'a> Playback<'a> {
+    /// Start playing a movie on a CPU that has just been loaded and reset,
+    /// restoring the movie's save state if it has one.
+    pub fn new(movie: &'a Movie, cpu: &mut CPU) -> io::Result<Self> {
+        if let Some(savestate) = &movie.savestate {
+            cpu.load_state(savestate)?;
+        }
+
+        Ok(Self {
+            movie,
+            frame: 0,
+            clock: FrameClock::new(cpu),
+        })
+    }
+
+    /// Number of frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    /// Play the next frame, if there is one. Returns false once every frame has
+    /// been played.
+    pub fn run_frame(&mut self, cpu: &mut CPU) -> Result<bool, PlaybackError> {
+        if self.frame == 0 {
+            self.check(cpu)?;
+        }
+
+        let Some(frame) = self.movie.frames.get(self.frame) else {
+            return Ok(false);
+        };
+
+        self.clock.run_frame(cpu, frame)?;
+        self.frame += 1;
+        self.check(cpu)?;
+
+        Ok(true)
+    }
+
+    /// Play every remaining frame.
+    pub fn run(&mut self, cpu: &mut CPU) -> Result<(), PlaybackError> {
+        while self.run_frame(cpu)? {}
+        Ok(())
+    }
+
+    fn check(&self, cpu: &CPU) -> Result<(), PlaybackError> {
+        match self.movie.checkpoints.get(&self.frame) {
+            Some(&expected) => match frame_hash(cpu) {
+                actual if actual != expected => Err(PlaybackError::Desync {
+                    frame: self.frame,
+                    expected,
+                    actual,
+                }),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    const FM2: &str = "\
+version 3
+emuVersion 22020
+rerecordCount 7
+palFlag 0
+romFilename game
+romChecksum base64:AAECAwQFBgcICQoLDA0ODw==
+guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B
+fourscore 0
+port0 1
+port1 1
+port2 0
+comment author someone
+comment checkpoint 2 0000beef
+subtitle 1 hello
+|1|........|........||
+|0|R..U...A|.......A||
+|0|..D.T.B.|.....S..||
+";
+
+    /// A CPU running a program that adds up the buttons read from controller 1
+    /// each frame.
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load(
+            assemble(
+                "
+                @frame: lda #1
+                        sta $4016
+                        lda #0
+                        sta $4016
+                        ldx #8
+                @read:  lda $4016
+                        and #1
+                        clc
+                        adc $10
+                        sta $10
+                        dex
+                        bne @read
+                        inc $11
+                        jmp @frame
+                ",
+                0x8000,
+            )
+            .unwrap()
+            .bytes,
+        );
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_parse() {
+        let movie = Movie::parse(FM2).unwrap();
+
+        assert_eq!(movie.rerecord_count, 7);
+        assert_eq!(movie.rom_filename, "game");
+        assert_eq!(movie.rom_checksum, std::array::from_fn(|i| i as u8));
+        assert_eq!(movie.comments, vec!["author someone"]);
+        assert_eq!(movie.checkpoints, BTreeMap::from([(2, 0xbeef)]));
+        assert_eq!(
+            movie.frames,
+            vec![
+                MovieFrame {
+                    commands: FrameCommand::SoftReset,
+                    buttons: [Button::none(); 2],
+                },
+                MovieFrame {
+                    commands: FrameCommand::none(),
+                    buttons: [Button::Right | Button::Up | Button::A, Button::A],
+                },
+                MovieFrame {
+                    commands: FrameCommand::none(),
+                    buttons: [Button::Down | Button::Start | Button::B, Button::Select],
+                },
+            ]
+        );
+
+        // Writing the movie back out keeps everything that affects playback.
+        assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+        assert!(movie.to_string().contains("|0|R..U...A|.......A||\n"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| Movie::parse(text).unwrap_err().to_string();
+
+        assert_eq!(error("|0|........|........||"), "not an FM2 movie");
+        assert_eq!(
+            error("version 2"),
+            "FM2 version 2 is not supported, expected version 3"
+        );
+        assert_eq!(
+            error("version 3\npalFlag 1"),
+            "line 2: PAL timing is not supported"
+        );
+        assert_eq!(
+            error("version 3\n|0|....|........||"),
+            "line 2: expected 8 buttons, got '....'"
+        );
+        assert_eq!(
+            error("version 3\nsavestate 0xaé1"),
+            "line 2: invalid hex 'aé1'"
+        );
+        assert_eq!(
+            error("version 3\nsavestate 0x123"),
+            "line 2: odd number of hex digits"
+        );
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let mut cpu = cpu();
+        let mut recorder = Recorder::new(Movie::new("test", [0; 16]), &cpu);
+
+        for i in 0..10 {
+            let buttons = if i % 3 == 0 {
+                Button::A | Button::Start
+            } else {
+                Button::none()
+            };
+
+            recorder
+                .run_frame(
+                    &mut cpu,
+                    MovieFrame {
+                        buttons: [buttons, Button::none()],
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+
+            if i % 4 == 3 {
+                recorder.checkpoint(&cpu);
+            }
+        }
+
+        let movie = Movie::parse(&recorder.finish().to_string()).unwrap();
+        let expected = cpu.save_state();
+        assert_eq!(movie.checkpoints.len(), 2);
+
+        let mut cpu = self::cpu();
+        let mut playback = Playback::new(&movie, &mut cpu).unwrap();
+        playback.run(&mut cpu).unwrap();
+        assert_eq!(playback.frame(), 10);
+        assert!(playback.is_finished());
+        assert_eq!(cpu.save_state(), expected);
+
+        // Different input changes the hash at the next checkpoint.
+        let mut altered = movie.clone();
+        altered.frames[1].buttons[0] = Button::B;
+
+        let mut cpu = self::cpu();
+        let mut playback = Playback::new(&altered, &mut cpu).unwrap();
+        assert!(matches!(
+            playback.run(&mut cpu),
+            Err(PlaybackError::Desync { frame: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_frame_hash() {
+        let mut cpu = cpu();
+        cpu.run_frames(2);
+        let hash = frame_hash(&cpu);
+
+        // Host settings and bookkeeping aren't part of the output.
+        cpu.apu.set_sample_rate(48_000);
+        cpu.cycles += 1;
+        assert_eq!(frame_hash(&cpu), hash);
+
+        cpu.poke(0x07ff, 1);
+        assert_ne!(frame_hash(&cpu), hash);
+    }
+
+    #[test]
+    fn test_from_save_state() {
+        let mut cpu = cpu();
+        cpu.run_frames(3);
+        cpu.poke(0x12, 0x34);
+
+        let mut recorder = Recorder::from_save_state(Movie::default(), &cpu);
+        recorder.run_frame(&mut cpu, MovieFrame::default()).unwrap();
+        recorder.checkpoint(&cpu);
+        let movie = recorder.finish();
+
+        // Playback restores the save state, whatever the CPU was doing.
+        let mut other = self::cpu();
+        let mut playback = Playback::new(&movie, &mut other).unwrap();
+        assert_eq!(other.peek(0x12), 0x34);
+        playback.run(&mut other).unwrap();
+        assert_eq!(other.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn test_reset_commands() {
+        let mut cpu = cpu();
+        let mut clock = FrameClock::new(&cpu);
+        clock.run_frame(&mut cpu, &MovieFrame::default()).unwrap();
+        clock.run_frame(&mut cpu, &MovieFrame::default()).unwrap();
+        cpu.poke(0x0700, 0x55);
+
+        let reset = |commands| MovieFrame {
+            commands,
+            ..Default::default()
+        };
+
+        // A soft reset keeps RAM and restarts the frame timing.
+        clock
+            .run_frame(&mut cpu, &reset(FrameCommand::SoftReset))
+            .unwrap();
+        assert_eq!(cpu.peek(0x0700), 0x55);
+        assert_eq!(clock.end, CYCLES_PER_FRAME);
+        assert!(cpu.cycles >= clock.end && cpu.cycles < 2 * CYCLES_PER_FRAME);
+
+        // A hard reset also clears RAM.
+        clock
+            .run_frame(&mut cpu, &reset(FrameCommand::HardReset))
+            .unwrap();
+        assert_eq!(cpu.peek(0x0700), 0);
+    }
+}
@@ -0,0 +1,363 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    apu::{self, CPU_FREQUENCY},
+    cpu::{mode::Mode, CPU, CYCLES_PER_FRAME},
+    mapper::Mapper,
+    wav::WavWriter,
+};
+
+/// Magic bytes at the start of every NSF file.
+pub const MAGIC: &[u8; 5] = b"NESM\x1a";
+
+const HEADER_SIZE: usize = 0x80;
+const BANK_SIZE: usize = 0x1000;
+
+/// Start of the bank switching registers, one for each 4 KiB bank from $8000
+/// to $FFFF.
+pub const BANK_REGISTERS: u16 = 0x5ff8;
+
+/// A parsed NSF music file.
+#[derive(Debug, Clone)]
+pub struct Nsf {
+    pub version: u8,
+    pub total_songs: u8,
+    /// The song to play by default, starting from 1.
+    pub starting_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+    /// Microseconds between calls to the play routine on NTSC.
+    pub ntsc_speed: u16,
+    /// Initial values of the bank switching registers.
+    pub bankswitch: [u8; 8],
+    data: Vec<u8>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read a null-padded string from a fixed-size header field.
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl Nsf {
+    /// Load and parse the NSF file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parse an NSF file from its raw bytes.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..5] != MAGIC {
+            return Err(invalid("not an NSF file"));
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+
+        let nsf = Self {
+            version: bytes[0x05],
+            total_songs: bytes[0x06],
+            starting_song: bytes[0x07],
+            load_address: word(0x08),
+            init_address: word(0x0a),
+            play_address: word(0x0c),
+            name: header_string(&bytes[0x0e..0x2e]),
+            artist: header_string(&bytes[0x2e..0x4e]),
+            copyright: header_string(&bytes[0x4e..0x6e]),
+            ntsc_speed: word(0x6e),
+            bankswitch: bytes[0x70..0x78].try_into().unwrap(),
+            data: bytes[HEADER_SIZE..].to_vec(),
+        };
+
+        if nsf.load_address < 0x8000 {
+            return Err(invalid("NSF load addresses below $8000 are not supported"));
+        }
+        if !nsf.has_song(nsf.starting_song) {
+            return Err(invalid("NSF starting song is out of range"));
+        }
+
+        Ok(nsf)
+    }
+
+    /// Whether the file has a song with the given number, counting from 1.
+    pub fn has_song(&self, song: u8) -> bool {
+        (1..=self.total_songs).contains(&song)
+    }
+
+    /// Whether the music driver uses the bank switching registers.
+    pub fn uses_bankswitching(&self) -> bool {
+        self.bankswitch.iter().any(|&bank| bank != 0)
+    }
+
+    /// Number of CPU cycles between calls to the play routine.
+    pub fn cycles_per_play(&self) -> u64 {
+        match self.ntsc_speed {
+            0 => CYCLES_PER_FRAME,
+            speed => u64::from(speed) * u64::from(CPU_FREQUENCY) / 1_000_000,
+        }
+    }
+}
+
+/// Maps the NSF's program data into $8000-$FFFF in eight switchable 4 KiB
+/// banks.
+#[derive(Debug, Clone)]
+pub struct NsfMapper {
+    banks: Vec<u8>,
+    registers: [u8; 8],
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        // Without bank switching, the data is loaded at the load address and
+        // the banks are laid out linearly from $8000.
+        let (padding, registers) = if nsf.uses_bankswitching() {
+            (usize::from(nsf.load_address & 0x0fff), nsf.bankswitch)
+        } else {
+            (
+                usize::from(nsf.load_address - 0x8000),
+                [0, 1, 2, 3, 4, 5, 6, 7],
+            )
+        };
+
+        let mut banks = vec![0; padding];
+        banks.extend_from_slice(&nsf.data);
+        banks.resize(banks.len().div_ceil(BANK_SIZE).max(1) * BANK_SIZE, 0);
+
+        Self { banks, registers }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn read(&self, addr: u16) -> Option<u8> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let slot = usize::from((addr - 0x8000) >> 12);
+        let bank = usize::from(self.registers[slot]) % (self.banks.len() / BANK_SIZE);
+
+        Some(self.banks[bank * BANK_SIZE + usize::from(addr & 0x0fff)])
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            BANK_REGISTERS..=0x5fff => {
+                self.registers[usize::from(addr - BANK_REGISTERS)] = value;
+                true
+            }
+            // Program ROM is read-only.
+            0x8000..=0xffff => true,
+            _ => false,
+        }
+    }
+}
+
+/// Plays a song from an NSF file by calling its init routine once, then its
+/// play routine at the rate given in the header.
+#[derive(Debug)]
+pub struct NsfPlayer {
+    pub cpu: CPU,
+    play_address: u16,
+    cycles_per_play: u64,
+}
+
+impl NsfPlayer {
+    /// Load the NSF and initialize the given song, numbered from 1. Panics if
+    /// the file has no such song.
+    pub fn new(nsf: &Nsf, song: u8, sample_rate: u32) -> Self {
+        assert!(nsf.has_song(song), "song {} is out of range", song);
+
+        let mut cpu = CPU::new();
+        cpu.mode = Mode::Nes2A03;
+        cpu.apu.set_sample_rate(sample_rate);
+        cpu.mapper = Some(Box::new(NsfMapper::new(nsf)));
+        cpu.reset();
+
+        for addr in apu::REGISTERS..=apu::REGISTERS_END {
+            cpu.apu.write(addr, 0x00);
+        }
+        cpu.apu.write(apu::STATUS, 0x0f);
+        cpu.apu.write(apu::FRAME_COUNTER, 0x40);
+
+        cpu.accumulator = song - 1;
+        cpu.index_x = 0; // NTSC
+        cpu.run_subroutine(nsf.init_address, CPU_FREQUENCY.into());
+
+        Self {
+            cpu,
+            play_address: nsf.play_address,
+            cycles_per_play: nsf.cycles_per_play(),
+        }
+    }
+
+    /// Call the play routine, and let the rest of the play period pass.
+    pub fn play(&mut self) {
+        let end = self.cpu.cycles + self.cycles_per_play;
+        self.cpu
+            .run_subroutine(self.play_address, self.cycles_per_play);
+
+        if self.cpu.cycles < end {
+            self.cpu.idle(end - self.cpu.cycles);
+        }
+    }
+
+    /// Play the song for the given number of play periods, and return the
+    /// mixed samples.
+    pub fn render(&mut self, plays: u64) -> Vec<f32> {
+        for _ in 0..plays {
+            self.play();
+        }
+
+        self.cpu.apu.take_samples()
+    }
+
+    /// Play the song for the given number of play periods, writing the audio to
+    /// a WAV file at the given path.
+    pub fn record<P: AsRef<Path>>(&mut self, plays: u64, path: P) -> io::Result<()> {
+        let mut wav = WavWriter::create(path, self.cpu.apu.sample_rate())?;
+
+        for _ in 0..plays {
+            self.play();
+            wav.write_samples(&self.cpu.apu.take_samples())?;
+        }
+
+        wav.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Build an NSF file from a header template and program data.
+    fn build_nsf(load: u16, init: u16, play: u16, bankswitch: [u8; 8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[0..5].copy_from_slice(MAGIC);
+        bytes[0x05] = 1;
+        bytes[0x06] = 2;
+        bytes[0x07] = 1;
+        bytes[0x08..0x0a].copy_from_slice(&load.to_le_bytes());
+        bytes[0x0a..0x0c].copy_from_slice(&init.to_le_bytes());
+        bytes[0x0c..0x0e].copy_from_slice(&play.to_le_bytes());
+        bytes[0x0e..0x13].copy_from_slice(b"Title");
+        bytes[0x6e..0x70].copy_from_slice(&16639_u16.to_le_bytes());
+        bytes[0x70..0x78].copy_from_slice(&bankswitch);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// A driver that stores the song number at $00 on init, and increments $01
+    /// on every play.
+    const DRIVER: [u8; 8] = [
+        0x85, 0x00, // init: STA $00
+        0x60, // RTS
+        0xe6, 0x01, // play: INC $01
+        0x60, // RTS
+        0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_parse() {
+        let nsf = Nsf::parse(&build_nsf(0x8000, 0x8000, 0x8003, [0; 8], &DRIVER)).unwrap();
+
+        assert_eq!(nsf.total_songs, 2);
+        assert_eq!(nsf.load_address, 0x8000);
+        assert_eq!(nsf.play_address, 0x8003);
+        assert_eq!(nsf.name, "Title");
+        assert_eq!(nsf.artist, "");
+        assert!(!nsf.uses_bankswitching());
+        assert!(!nsf.has_song(0));
+        assert!(nsf.has_song(2));
+        assert!(!nsf.has_song(3));
+        assert_eq!(nsf.cycles_per_play(), 29780);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Nsf::parse(b"NESM").is_err());
+        assert!(Nsf::parse(&build_nsf(0x6000, 0x6000, 0x6000, [0; 8], &[])).is_err());
+
+        // The starting song must be one of the songs in the file.
+        for song in [0, 3] {
+            let mut bytes = build_nsf(0x8000, 0x8000, 0x8003, [0; 8], &DRIVER);
+            bytes[0x07] = song;
+            assert!(Nsf::parse(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_init_and_play() {
+        let nsf = Nsf::parse(&build_nsf(0x8000, 0x8000, 0x8003, [0; 8], &DRIVER)).unwrap();
+        let mut player = NsfPlayer::new(&nsf, 2, 44_100);
+
+        assert_eq!(player.cpu.peek(0x00), 1);
+
+        let samples = player.render(3);
+        assert_eq!(player.cpu.peek(0x01), 3);
+        assert_eq!(samples.len(), 3 * 29780 * 44_100 / CPU_FREQUENCY as usize);
+    }
+
+    #[test]
+    fn test_load_address_without_bankswitching() {
+        let nsf = Nsf::parse(&build_nsf(0xc000, 0xc000, 0xc003, [0; 8], &DRIVER)).unwrap();
+        let player = NsfPlayer::new(&nsf, 1, 44_100);
+
+        assert_eq!(player.cpu.peek(0xc000), 0x85);
+        assert_eq!(player.cpu.peek(0xc003), 0xe6);
+    }
+
+    #[test]
+    fn test_bankswitching() {
+        // Two banks, loaded at an offset of $100 into the first.
+        let mut data = vec![0xea; BANK_SIZE * 2 - 0x100];
+        data[0] = 0x11;
+        data[BANK_SIZE - 0x100] = 0x22;
+
+        let nsf = Nsf::parse(&build_nsf(
+            0x8100,
+            0x8100,
+            0x8100,
+            [1, 0, 0, 0, 0, 0, 0, 0],
+            &data,
+        ))
+        .unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+
+        assert_eq!(mapper.read(0x8000), Some(0x22));
+        assert_eq!(mapper.read(0x9100), Some(0x11));
+
+        assert!(mapper.write(BANK_REGISTERS, 0));
+        assert_eq!(mapper.read(0x8100), Some(0x11));
+        assert_eq!(mapper.read(0x7fff), None);
+    }
+
+    #[test]
+    fn test_play_drives_apu() {
+        let driver = [
+            0xa9, 0xbf, // init: 50% duty, constant volume 15, halted
+            0x8d, 0x00, 0x40, // STA $4000
+            0xa9, 0xfd, // ~440 Hz
+            0x8d, 0x02, 0x40, // STA $4002
+            0xa9, 0x08, // load the length counter
+            0x8d, 0x03, 0x40, // STA $4003
+            0x60, // RTS
+            0x60, // play: RTS
+        ];
+
+        let nsf = Nsf::parse(&build_nsf(0x8000, 0x8000, 0x8010, [0; 8], &driver)).unwrap();
+        let mut player = NsfPlayer::new(&nsf, 1, 44_100);
+        let samples = player.render(2);
+
+        let max = samples.iter().cloned().fold(0.0, f32::max);
+        let min = samples.iter().cloned().fold(1.0, f32::min);
+        assert!(max - min > 0.1);
+    }
+}
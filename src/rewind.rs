@@ -0,0 +1,385 @@
+//! Rewinding, by periodically saving the machine's state into a ring buffer
+//! with a fixed memory budget.
+//!
+//! Only the most recent snapshot is kept in full. Each older snapshot is kept
+//! as the XOR of it and the snapshot after it, which is mostly zeros since
+//! little changes between frames, and then compressed. Rewinding undoes the
+//! deltas one at a time from the most recent, and once the buffer is over its
+//! budget the oldest deltas are dropped.
+//!
+//! Memory is left out of the snapshots, since most of it doesn't change between
+//! them. Instead the history keeps its own copy of memory, and each delta only
+//! holds the pages the CPU wrote to since the snapshot before.
+
+use std::{collections::VecDeque, io};
+
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+
+use crate::cpu::{
+    memory::{Pages, MEMORY_SIZE, PAGE_SIZE},
+    CPU, CYCLES_PER_FRAME,
+};
+
+/// Frames between snapshots unless another interval is configured.
+pub const DEFAULT_INTERVAL: u64 = 4;
+/// Memory budget unless another is configured, in bytes.
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+
+/// Deflate level for deltas, favoring speed since the deltas are mostly zeros
+/// that compress well at any level.
+const COMPRESSION_LEVEL: u8 = 1;
+
+/// A snapshot, stored as the difference from the snapshot after it.
+#[derive(Debug)]
+struct Delta {
+    /// The frame the snapshot was taken on.
+    frame: u64,
+    /// The length of the snapshot, which may be shorter than the delta if the
+    /// snapshot after it was longer.
+    len: usize,
+    /// The pages of memory written to between the snapshot and the one after
+    /// it, whose deltas follow the snapshot's.
+    pages: Pages,
+    compressed: Vec<u8>,
+}
+
+/// A history of the machine's state that can be rewound through.
+#[derive(Debug)]
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    /// The most recent snapshot, in full.
+    latest: Vec<u8>,
+    /// The contents of memory at the most recent snapshot.
+    memory: Box<[u8; MEMORY_SIZE]>,
+    /// The frame the most recent snapshot was taken on, if there is one.
+    latest_frame: Option<u64>,
+    /// Older snapshots, oldest first.
+    deltas: VecDeque<Delta>,
+    /// Total compressed size of the deltas.
+    deltas_size: usize,
+    /// Buffer for saving new snapshots into, reused between snapshots.
+    scratch: Vec<u8>,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_BUDGET)
+    }
+}
+
+impl Rewind {
+    /// Create an empty history that takes a snapshot every `interval` frames,
+    /// and keeps as many as fit in `budget` bytes. The most recent snapshot is
+    /// always kept, even if it alone is over budget.
+    pub fn new(interval: u64, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            latest: Vec::new(),
+            memory: Box::new([0; MEMORY_SIZE]),
+            latest_frame: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Number of snapshots in the history.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.latest_frame.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest_frame.is_none()
+    }
+
+    /// Memory used by the snapshots, in bytes.
+    pub fn size(&self) -> usize {
+        let memory = if self.is_empty() { 0 } else { MEMORY_SIZE };
+        self.latest.len() + memory + self.deltas_size
+    }
+
+    /// The frame of the oldest snapshot, which is as far back as the history
+    /// can rewind.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.deltas
+            .front()
+            .map(|delta| delta.frame)
+            .or(self.latest_frame)
+    }
+
+    /// Forget every snapshot.
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.latest_frame = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+
+    /// Take a snapshot if the interval has passed since the last one. Call this
+    /// at least once a frame. Returns whether a snapshot was taken.
+    pub fn record(&mut self, cpu: &mut CPU) -> bool {
+        let frame = cpu.cycles / CYCLES_PER_FRAME;
+        let due = self
+            .latest_frame
+            .is_none_or(|latest| frame >= latest + self.interval || frame < latest);
+
+        if due {
+            self.capture(cpu);
+        }
+
+        due
+    }
+
+    /// Take a snapshot now. If the machine is on an earlier frame than the most
+    /// recent snapshot, as after a reset, the history is cleared first.
+    ///
+    /// This takes the CPU's dirty pages, so nothing else should use them.
+    pub fn capture(&mut self, cpu: &mut CPU) {
+        let frame = cpu.cycles / CYCLES_PER_FRAME;
+        let pages = cpu.take_dirty_pages();
+        cpu.save_state_without_memory_into(&mut self.scratch);
+
+        match self.latest_frame {
+            Some(latest_frame) if frame >= latest_frame => {
+                let len = self.latest.len();
+                let new_len = self.scratch.len();
+                let delta_len = len.max(new_len);
+
+                // Turn the new snapshot into the delta from the latest one, then
+                // apply the delta to the latest to bring it up to date.
+                self.latest.resize(delta_len, 0);
+                self.scratch.resize(delta_len, 0);
+                xor(&mut self.scratch, &self.latest);
+                xor(&mut self.latest, &self.scratch);
+                self.latest.truncate(new_len);
+
+                // Do the same for each page of memory that was written to.
+                for page in pages.iter() {
+                    let start = self.scratch.len();
+                    let copy = &mut self.memory[page * PAGE_SIZE..(page + 1) * PAGE_SIZE];
+
+                    self.scratch.extend_from_slice(cpu.memory().page(page));
+                    xor(&mut self.scratch[start..], copy);
+                    xor(copy, &self.scratch[start..]);
+                }
+
+                let compressed = compress_to_vec(&self.scratch, COMPRESSION_LEVEL);
+                self.deltas_size += compressed.len();
+                self.deltas.push_back(Delta {
+                    frame: latest_frame,
+                    len,
+                    pages,
+                    compressed,
+                });
+            }
+            _ => {
+                self.clear();
+                std::mem::swap(&mut self.latest, &mut self.scratch);
+                self.memory.copy_from_slice(cpu.memory().bytes());
+            }
+        }
+
+        self.latest_frame = Some(frame);
+
+        while self.size() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.compressed.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Restore the CPU to the most recent snapshot at least `frames` frames
+    /// before its current frame, or the oldest snapshot if none are that old,
+    /// discarding every snapshot after it. Returns the number of frames
+    /// actually rewound, which is 0 if there are no snapshots.
+    pub fn rewind(&mut self, cpu: &mut CPU, frames: u64) -> io::Result<u64> {
+        let Some(mut latest_frame) = self.latest_frame else {
+            return Ok(0);
+        };
+
+        let frame = cpu.cycles / CYCLES_PER_FRAME;
+        let target = frame.saturating_sub(frames);
+
+        while latest_frame > target {
+            let Some(delta) = self.deltas.pop_back() else {
+                break;
+            };
+            self.deltas_size -= delta.compressed.len();
+
+            let corrupt = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("corrupt rewind snapshot: {}", message),
+                )
+            };
+            let bytes = decompress_to_vec(&delta.compressed).map_err(|e| corrupt(e.to_string()))?;
+            let state_len = self.latest.len().max(delta.len);
+            let (state, pages) = bytes
+                .split_at_checked(state_len)
+                .filter(|(_, pages)| pages.len() == delta.pages.len() * PAGE_SIZE)
+                .ok_or_else(|| corrupt(format!("unexpected length {}", bytes.len())))?;
+
+            self.latest.resize(state_len, 0);
+            xor(&mut self.latest, state);
+            self.latest.truncate(delta.len);
+
+            for (page, bytes) in delta.pages.iter().zip(pages.chunks(PAGE_SIZE)) {
+                xor(
+                    &mut self.memory[page * PAGE_SIZE..(page + 1) * PAGE_SIZE],
+                    bytes,
+                );
+            }
+
+            latest_frame = delta.frame;
+        }
+
+        self.latest_frame = Some(latest_frame);
+        cpu.load_state_with_memory(&self.latest, &self.memory)?;
+        // The copy of memory matches the CPU's again.
+        cpu.take_dirty_pages();
+
+        Ok(frame.saturating_sub(latest_frame))
+    }
+}
+
+/// XOR each byte of `bytes` with the byte at the same position in `other`.
+fn xor(bytes: &mut [u8], other: &[u8]) {
+    for (byte, other) in bytes.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    /// A CPU running a program that changes a little memory every instruction.
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load(
+            assemble(
+                "
+                @loop:  inx
+                        stx $10
+                        inc $0200,x
+                        jmp @loop
+                ",
+                0x8000,
+            )
+            .unwrap()
+            .bytes,
+        );
+        cpu.reset();
+        cpu
+    }
+
+    fn frame(cpu: &CPU) -> u64 {
+        cpu.cycles / CYCLES_PER_FRAME
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut cpu = cpu();
+        let mut rewind = Rewind::new(2, DEFAULT_BUDGET);
+        let mut states = Vec::new();
+
+        while frame(&cpu) < 10 {
+            if rewind.record(&mut cpu) {
+                states.push((frame(&cpu), cpu.save_state()));
+            }
+            cpu.step();
+        }
+
+        assert_eq!(rewind.len(), 5);
+        assert_eq!(rewind.oldest_frame(), Some(0));
+        assert!(rewind.size() < 2 * states[0].1.len());
+
+        // Rewinding 3 frames from frame 10 lands on the snapshot from frame 6.
+        assert_eq!(rewind.rewind(&mut cpu, 3).unwrap(), 4);
+        assert_eq!(cpu.save_state(), states[3].1);
+        assert_eq!(rewind.len(), 4);
+
+        // Rewinding further than the history goes stops at the oldest.
+        assert_eq!(rewind.rewind(&mut cpu, 100).unwrap(), 6);
+        assert_eq!(cpu.save_state(), states[0].1);
+        assert_eq!(rewind.len(), 1);
+
+        // Recording picks up again from the restored snapshot.
+        while frame(&cpu) < 2 {
+            cpu.step();
+        }
+        assert!(rewind.record(&mut cpu));
+        assert_eq!(rewind.rewind(&mut cpu, 0).unwrap(), 0);
+        assert_eq!(rewind.len(), 2);
+    }
+
+    #[test]
+    fn test_capture_copies_written_pages() {
+        let mut cpu = cpu();
+        let mut rewind = Rewind::new(1, DEFAULT_BUDGET);
+
+        rewind.capture(&mut cpu);
+        while frame(&cpu) < 1 {
+            cpu.step();
+        }
+        rewind.capture(&mut cpu);
+
+        // Only the pages the program wrote to are copied, not all of memory.
+        let delta = rewind.deltas.back().unwrap();
+        assert_eq!(delta.pages.iter().collect::<Vec<_>>(), [0x00, 0x02]);
+        assert_eq!(rewind.scratch.len(), rewind.latest.len() + 2 * PAGE_SIZE);
+
+        // Nothing is copied when nothing was written.
+        rewind.capture(&mut cpu);
+        assert!(rewind.deltas.back().unwrap().pages.is_empty());
+        assert_eq!(rewind.scratch.len(), rewind.latest.len());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut cpu = cpu();
+        let mut rewind = Rewind::new(1, 0);
+
+        assert_eq!(rewind.rewind(&mut cpu, 1).unwrap(), 0);
+
+        while frame(&cpu) < 5 {
+            rewind.record(&mut cpu);
+            cpu.step();
+        }
+
+        // Only the most recent snapshot is kept when nothing else fits.
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.oldest_frame(), Some(4));
+
+        let budget = rewind.size() + 1024;
+        let mut rewind = Rewind::new(1, budget);
+        while frame(&cpu) < 50 {
+            rewind.record(&mut cpu);
+            cpu.step();
+        }
+
+        assert!(rewind.size() <= budget);
+        assert!(rewind.len() > 1);
+        assert!(rewind.oldest_frame().unwrap() > 5);
+    }
+
+    #[test]
+    fn test_reset_clears_history() {
+        let mut cpu = cpu();
+        let mut rewind = Rewind::new(1, DEFAULT_BUDGET);
+
+        while frame(&cpu) < 3 {
+            rewind.record(&mut cpu);
+            cpu.step();
+        }
+        assert_eq!(rewind.len(), 3);
+
+        cpu.reset();
+        assert!(rewind.record(&mut cpu));
+        assert_eq!(rewind.len(), 1);
+    }
+}
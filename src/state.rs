@@ -0,0 +1,324 @@
+//! A versioned binary format for save states, which capture the entire state
+//! of the machine so that it can be restored later.
+//!
+//! A save state starts with [`MAGIC`] and a little-endian u16 version, followed
+//! by each component's state in a fixed order. Every multi-byte value is
+//! little-endian.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::cpu::CPU;
+
+/// Magic bytes at the start of every save state.
+pub const MAGIC: &[u8; 4] = b"799S";
+/// Version of the format written by this build. Increase it whenever the
+/// layout changes, and either convert or reject older versions when loading.
+///
+/// - Version 2 moved battery-backed PRG-RAM from CPU memory into the mapper.
+pub const VERSION: u16 = 2;
+/// Oldest version of the format this build can still load.
+pub const MIN_VERSION: u16 = 1;
+
+/// Highest numbered save slot.
+pub const MAX_SLOT: u8 = 9;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A component whose state can be saved and restored.
+pub trait SaveState {
+    fn save_state(&self, state: &mut StateWriter);
+
+    /// Restore the component from the state written by
+    /// [`SaveState::save_state`].
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()>;
+}
+
+/// Writes values to a save state.
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start a save state with its header.
+    pub fn with_header() -> Self {
+        Self::with_header_in(Vec::new())
+    }
+
+    /// Start a save state with its header in an existing buffer, replacing its
+    /// contents but reusing its allocation.
+    pub fn with_header_in(mut bytes: Vec<u8>) -> Self {
+        bytes.clear();
+
+        let mut state = Self { bytes };
+        state.bytes(MAGIC);
+        state.u16(VERSION);
+        state
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Write bytes preceded by their length, so they can be read without
+    /// knowing it.
+    pub fn sized_bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads values from a save state.
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::with_version(bytes, VERSION)
+    }
+
+    /// Read part of a save state written in the given version of the format,
+    /// such as a component's state nested inside it.
+    pub fn with_version(bytes: &'a [u8], version: u16) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            version,
+        }
+    }
+
+    /// The version of the format the state was written in, so that older
+    /// layouts can be converted.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Start reading a save state, checking its header. Fails if the state was
+    /// written in a version of the format this build can't load.
+    pub fn with_header(bytes: &'a [u8]) -> io::Result<Self> {
+        let mut state = Self::new(bytes);
+
+        if !bytes.starts_with(MAGIC) {
+            return Err(invalid("not a save state".to_string()));
+        }
+        state.pos = MAGIC.len();
+
+        state.version = match state.u16()? {
+            version @ MIN_VERSION..=VERSION => version,
+            version if version > VERSION => {
+                return Err(invalid(format!(
+                    "save state version {} is newer than the supported version {}",
+                    version, VERSION
+                )))
+            }
+            version => {
+                return Err(invalid(format!(
+                    "save state version {} is no longer supported, expected version {}-{}",
+                    version, MIN_VERSION, VERSION
+                )))
+            }
+        };
+
+        Ok(state)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(invalid(format!("invalid boolean {} in save state", value))),
+        }
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("save state is truncated".to_string()))?;
+
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Read bytes written by [`StateWriter::sized_bytes`].
+    pub fn sized_bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()?;
+        self.bytes(len as usize)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    /// Check that every byte of the state has been read.
+    pub fn finish(&self) -> io::Result<()> {
+        match self.bytes.len() - self.pos {
+            0 => Ok(()),
+            extra => Err(invalid(format!(
+                "save state has {} unexpected bytes at the end",
+                extra
+            ))),
+        }
+    }
+}
+
+/// The path of a numbered save slot for a ROM, next to the ROM with the slot
+/// number in its extension, e.g. `game.ss1` for `game.nes`.
+pub fn slot_path<P: AsRef<Path>>(rom: P, slot: u8) -> io::Result<PathBuf> {
+    if slot > MAX_SLOT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("save slot {} is out of range 0-{}", slot, MAX_SLOT),
+        ));
+    }
+
+    Ok(rom.as_ref().with_extension(format!("ss{}", slot)))
+}
+
+/// Save the CPU's state to a numbered slot for a ROM, returning the path it
+/// was written to.
+pub fn save_slot<P: AsRef<Path>>(cpu: &CPU, rom: P, slot: u8) -> io::Result<PathBuf> {
+    let path = slot_path(rom, slot)?;
+    fs::write(&path, cpu.save_state())?;
+    Ok(path)
+}
+
+/// Restore the CPU's state from a numbered slot for a ROM.
+pub fn load_slot<P: AsRef<Path>>(cpu: &mut CPU, rom: P, slot: u8) -> io::Result<()> {
+    let path = slot_path(rom, slot)?;
+    let bytes = fs::read(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    cpu.load_state(&bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut state = StateWriter::with_header();
+        state.u8(0x12);
+        state.bool(true);
+        state.u16(0x3456);
+        state.u64(u64::MAX - 1);
+        state.f32(-0.5);
+        state.sized_bytes(b"abc");
+        let bytes = state.into_bytes();
+
+        let mut state = StateReader::with_header(&bytes).unwrap();
+        assert_eq!(state.version(), VERSION);
+        assert_eq!(state.u8().unwrap(), 0x12);
+        assert!(state.bool().unwrap());
+        assert_eq!(state.u16().unwrap(), 0x3456);
+        assert_eq!(state.u64().unwrap(), u64::MAX - 1);
+        assert_eq!(state.f32().unwrap(), -0.5);
+        assert_eq!(state.sized_bytes().unwrap(), b"abc");
+        state.finish().unwrap();
+    }
+
+    #[test]
+    fn test_header() {
+        let error = |bytes: &[u8]| StateReader::with_header(bytes).unwrap_err().to_string();
+
+        assert_eq!(error(b"NES\x1a"), "not a save state");
+        assert_eq!(
+            error(b"799S\x03\x00"),
+            "save state version 3 is newer than the supported version 2"
+        );
+        assert_eq!(
+            error(b"799S\x00\x00"),
+            "save state version 0 is no longer supported, expected version 1-2"
+        );
+        assert_eq!(
+            StateReader::with_header(b"799S\x01\x00").unwrap().version(),
+            1
+        );
+        assert_eq!(error(b"799S\x01"), "save state is truncated");
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut state = StateReader::new(&[1, 2, 3]);
+        state.u16().unwrap();
+
+        assert_eq!(
+            state.finish().unwrap_err().to_string(),
+            "save state has 1 unexpected bytes at the end"
+        );
+        assert!(state.bool().is_err());
+    }
+
+    #[test]
+    fn test_slot_path() {
+        assert_eq!(
+            slot_path("roms/game.nes", 3).unwrap(),
+            PathBuf::from("roms/game.ss3")
+        );
+        assert!(slot_path("game.nes", MAX_SLOT + 1).is_err());
+    }
+}
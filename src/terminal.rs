@@ -0,0 +1,231 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Stdout, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, terminal,
+};
+
+use crate::{
+    cpu,
+    easy6502::{
+        Easy6502, KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP, PALETTE, SCREEN_PIXELS, SCREEN_SIZE,
+    },
+};
+
+/// Number of times per second the screen is redrawn and input is read.
+const REFRESH_RATE: u32 = 60;
+
+/// Upper half block, drawn with the top pixel as the foreground color and the
+/// bottom pixel as the background color.
+const HALF_BLOCK: char = '\u{2580}';
+
+/// Why a program stopped running in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The program halted by jumping to $0000.
+    Halted,
+    /// The user quit with Escape or Ctrl-C.
+    Quit,
+    /// The CPU hit an error.
+    Error(cpu::Error),
+}
+
+/// A keypress read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// A key code to store in the last key pressed.
+    Key(u8),
+    /// Stop running the program.
+    Quit,
+}
+
+/// Translate a terminal key event into input for the program. Arrow keys are
+/// mapped to their WASD equivalents, and any other ASCII character is passed
+/// through as is.
+pub fn translate_key(key: KeyEvent) -> Option<Input> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Input::Quit),
+        KeyCode::Esc => Some(Input::Quit),
+        KeyCode::Up => Some(Input::Key(KEY_UP)),
+        KeyCode::Left => Some(Input::Key(KEY_LEFT)),
+        KeyCode::Down => Some(Input::Key(KEY_DOWN)),
+        KeyCode::Right => Some(Input::Key(KEY_RIGHT)),
+        KeyCode::Char(c) if c.is_ascii() => Some(Input::Key(c as u8)),
+        _ => None,
+    }
+}
+
+/// Render a screen of palette indices as rows of half blocks in ANSI true
+/// color, two pixels high per line, starting from the top left corner of the
+/// terminal.
+pub fn render(screen: &[u8; SCREEN_PIXELS]) -> String {
+    let width = SCREEN_SIZE as usize;
+    let mut out = String::from("\x1b[H");
+
+    for (row, pixels) in screen.chunks_exact(width * 2).enumerate() {
+        if row > 0 {
+            out.push_str("\r\n");
+        }
+
+        let (top, bottom) = pixels.split_at(width);
+        for (&top, &bottom) in top.iter().zip(bottom) {
+            let [tr, tg, tb] = PALETTE[usize::from(top & 0x0f)];
+            let [br, bg, bb] = PALETTE[usize::from(bottom & 0x0f)];
+            let _ = write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m{}",
+                tr, tg, tb, br, bg, bb, HALF_BLOCK
+            );
+        }
+
+        out.push_str("\x1b[0m");
+    }
+
+    out
+}
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it
+/// when dropped.
+#[derive(Debug)]
+pub struct Terminal {
+    stdout: Stdout,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+
+        terminal::enable_raw_mode()?;
+        execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::Hide
+        )?;
+
+        Ok(Self { stdout })
+    }
+
+    /// Draw a screen of palette indices.
+    pub fn draw(&mut self, screen: &[u8; SCREEN_PIXELS]) -> io::Result<()> {
+        self.stdout.write_all(render(screen).as_bytes())?;
+        self.stdout.flush()
+    }
+
+    /// Read all pending input without blocking.
+    pub fn poll_input(&mut self) -> io::Result<Vec<Input>> {
+        let mut input = vec![];
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                input.extend(translate_key(key));
+            }
+        }
+
+        Ok(input)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Run an easy6502 program in the terminal at the given number of
+/// instructions per second, until it halts, hits an error, or the user quits
+/// with Escape or Ctrl-C.
+pub fn run(host: &mut Easy6502, instructions_per_second: u32) -> io::Result<Stop> {
+    let mut term = Terminal::new()?;
+
+    let refresh = Duration::from_secs(1) / REFRESH_RATE;
+    let steps = u64::from((instructions_per_second / REFRESH_RATE).max(1));
+    let mut screen = host.screen();
+    let mut next = Instant::now();
+
+    term.draw(&screen)?;
+
+    loop {
+        for input in term.poll_input()? {
+            match input {
+                Input::Key(key) => host.press_key(key),
+                Input::Quit => return Ok(Stop::Quit),
+            }
+        }
+
+        let result = host.run(steps);
+
+        let current = host.screen();
+        if current != screen {
+            screen = current;
+            term.draw(&screen)?;
+        }
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => return Ok(Stop::Halted),
+            Err(e) => return Ok(Stop::Error(e)),
+        }
+
+        // Sleep off the rest of the refresh period, without trying to catch up
+        // if we've fallen behind.
+        next += refresh;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            next = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate_key() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        assert_eq!(translate_key(key(KeyCode::Up)), Some(Input::Key(b'w')));
+        assert_eq!(translate_key(key(KeyCode::Right)), Some(Input::Key(b'd')));
+        assert_eq!(
+            translate_key(key(KeyCode::Char('s'))),
+            Some(Input::Key(b's'))
+        );
+        assert_eq!(translate_key(key(KeyCode::Esc)), Some(Input::Quit));
+        assert_eq!(
+            translate_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Input::Quit)
+        );
+        assert_eq!(translate_key(key(KeyCode::F(1))), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut screen = [0; SCREEN_PIXELS];
+        screen[0] = 1; // white at the top left
+        screen[32] = 0x12; // red just below it, with the high nibble set
+
+        let out = render(&screen);
+        let lines: Vec<&str> = out.split("\r\n").collect();
+
+        assert_eq!(lines.len(), 16);
+        assert!(lines[0].starts_with("\x1b[H\x1b[38;2;255;255;255m\x1b[48;2;136;0;0m\u{2580}"));
+        assert!(lines[0].ends_with("\x1b[0m"));
+        assert!(lines
+            .iter()
+            .all(|line| line.matches(HALF_BLOCK).count() == 32));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::cpu::CPU;
+
+/// Size of the RIFF, fmt, and data chunk headers preceding the samples.
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+/// Writer for mono 16-bit PCM WAV files. The chunk sizes in the header are
+/// filled in when the writer is finished.
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    samples_written: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Create a WAV file at the given path.
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write a WAV header with the given sample rate to the writer.
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let byte_rate = sample_rate * u32::from(BLOCK_ALIGN);
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;
+        writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            samples_written: 0,
+        })
+    }
+
+    /// Convert samples from -1.0 to 1.0 into 16-bit PCM and write them out.
+    /// Samples outside of that range are clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            self.writer.write_all(&pcm.to_le_bytes())?;
+        }
+
+        self.samples_written += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Fill in the chunk sizes in the header, flush the writer, and return it.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples_written * u32::from(BLOCK_ALIGN);
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer
+            .seek(SeekFrom::Start(u64::from(HEADER_SIZE) - 4))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Run the CPU for the given number of frames, writing the APU's output to a
+/// WAV file at the given path. Stops early if the program halts. Fails if the
+/// CPU's mode has no APU, since the file would have no samples.
+pub fn record<P: AsRef<Path>>(cpu: &mut CPU, frames: u64, path: P) -> io::Result<()> {
+    if !cpu.mode.has_apu() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the {} mode has no APU to record", cpu.mode),
+        ));
+    }
+
+    let mut wav = WavWriter::create(path, cpu.apu.sample_rate())?;
+
+    for _ in 0..frames {
+        let running = cpu.run_frames(1);
+        wav.write_samples(&cpu.apu.take_samples())?;
+
+        if !running {
+            break;
+        }
+    }
+
+    wav.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::cpu::mode::Mode;
+
+    #[test]
+    fn test_header() {
+        let mut wav = WavWriter::new(Cursor::new(vec![]), 44_100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 42);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes(bytes[22..24].try_into().unwrap()), 1);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            44_100
+        );
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut wav = WavWriter::new(Cursor::new(vec![]), 44_100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0, 2.0, 0.5]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        let samples: Vec<i16> = bytes[44..]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        assert_eq!(samples, vec![0, 32767, -32767, 32767, 16383]);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![
+            0xa9, 0x01, // enable pulse 1
+            0x8d, 0x15, 0x40, // STA $4015
+            0xa9, 0xbf, // 50% duty, constant volume 15, halted
+            0x8d, 0x00, 0x40, // STA $4000
+            0xa9, 0xfd, // ~440 Hz
+            0x8d, 0x02, 0x40, // STA $4002
+            0xa9, 0x08, // load the length counter
+            0x8d, 0x03, 0x40, // STA $4003
+            0x4c, 0x14, 0x80, // JMP $8014
+        ]);
+        cpu.reset();
+
+        let path = std::env::temp_dir().join("nes799_test_record.wav");
+        record(&mut cpu, 2, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let data_size = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        assert_eq!(data_size as usize, bytes.len() - 44);
+        assert_eq!(data_size / 2, 1467); // 2 * 29781 cycles at 44.1 kHz
+        assert!(bytes[44..].chunks_exact(2).any(|b| b != [0, 0]));
+    }
+
+    #[test]
+    fn test_record_without_apu() {
+        let mut cpu = CPU::new();
+        cpu.mode = Mode::Mos6502;
+        let path = std::env::temp_dir().join("nes799_test_record_without_apu.wav");
+
+        let err = record(&mut cpu, 1, &path).unwrap_err();
+        assert_eq!(err.to_string(), "the mos6502 mode has no APU to record");
+        assert!(!path.exists());
+    }
+}
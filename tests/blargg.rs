@@ -0,0 +1,69 @@
+//! Runs every blargg test ROM in `tests/roms/blargg` and checks the result
+//! each reports through the $6000 status protocol.
+//!
+//! Only NROM (mapper 0) cartridges can be loaded and there's no PPU, so this
+//! covers CPU and APU tests built for NROM. Most instr_test and apu_test ROMs
+//! use MMC1, and PPU tests such as ppu_vbl_nmi can't pass, so they don't
+//! belong in the directory. No ROMs have been run through this yet. See the
+//! status in `tests/roms/README.md`.
+
+use std::fs;
+
+use nes799::{blargg, cartridge::Cartridge, cpu::CPU};
+
+mod common;
+
+/// Frames to run each ROM for before giving up on it. The longest tests take
+/// around 30 seconds.
+const MAX_FRAMES: u64 = 60 * 60;
+
+#[test]
+#[ignore = "needs NROM test ROMs in tests/roms/blargg"]
+fn blargg() {
+    let dir = common::rom_path("blargg");
+    let entries = fs::read_dir(&dir).unwrap_or_else(|e| {
+        panic!("{}: {}, see tests/roms/README.md", dir.display(), e);
+    });
+
+    let mut roms: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "{} has no .nes files", dir.display());
+
+    let mut failures = vec![];
+
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy();
+
+        let mapper = Cartridge::load(rom).and_then(|cartridge| cartridge.create_mapper());
+        let mapper = match mapper {
+            Ok(mapper) => mapper,
+            Err(e) => {
+                eprintln!("{}: could not load: {}", name, e);
+                failures.push(name);
+                continue;
+            }
+        };
+
+        let mut cpu = CPU::new();
+        cpu.mapper = Some(mapper);
+        cpu.reset();
+
+        let outcome = blargg::run(&mut cpu, MAX_FRAMES);
+        eprintln!("{}: {}", name, outcome);
+
+        if !outcome.passed() {
+            failures.push(name);
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} ROMs failed: {}",
+        failures.len(),
+        roms.len(),
+        failures.join(", ")
+    );
+}
@@ -0,0 +1,26 @@
+//! Helpers shared by the integration tests that run third-party test ROMs.
+
+// Each test crate includes this module, but not all of them use every helper.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// The path of a file in `tests/roms`.
+pub fn rom_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "roms", name]
+        .iter()
+        .collect()
+}
+
+/// The paths of the given files in `tests/roms`, panicking if any of them are
+/// missing. Tests that use this are ignored by default, since test ROMs aren't
+/// distributed with the repository, so they only run when asked to.
+pub fn require_roms<const N: usize>(names: [&str; N]) -> [PathBuf; N] {
+    let paths = names.map(rom_path);
+
+    if let Some(missing) = paths.iter().find(|path| !path.exists()) {
+        panic!("{} is missing, see tests/roms/README.md", missing.display());
+    }
+
+    paths
+}
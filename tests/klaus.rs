@@ -0,0 +1,113 @@
+//! Runs Klaus Dormann's 6502 functional and decimal tests on the 6502 CPU
+//! mode. Both signal their result by trapping in a loop that jumps or
+//! branches to itself, or by stopping on an opcode the 6502 lacks.
+//!
+//! Neither test has been run against the real binaries yet, which aren't
+//! distributed with the repository, so passing here is unverified until it has
+//! been. See the status in `tests/roms/README.md`.
+
+use std::fs;
+
+use nes799::cpu::{memory::MEMORY_SIZE, mode::Mode, Error, CPU};
+
+mod common;
+
+/// Where the functional test starts, after the data it loads into the zero
+/// page and stack.
+const FUNCTIONAL_ENTRY: u16 = 0x0400;
+/// Address of the trap the functional test reaches when every test passes,
+/// in the build distributed as `6502_functional_test.bin`. Taken from the
+/// test's listing, and not yet checked against a run of the binary.
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+
+/// Origin and entry point of the decimal test.
+const DECIMAL_ORIGIN: u16 = 0x0200;
+/// Address the decimal test stores 0 at if every result was correct.
+const DECIMAL_ERROR: u16 = 0x000b;
+
+/// More instructions than either test takes, so that a runaway program is
+/// reported instead of hanging the test.
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+/// Why a test program stopped.
+#[derive(Debug, PartialEq, Eq)]
+enum Stop {
+    /// An instruction at the address jumped or branched to itself.
+    Trap(u16),
+    /// An opcode the CPU doesn't recognize was reached.
+    Stopped(u16),
+    /// The program was still running after [`MAX_INSTRUCTIONS`].
+    Timeout(u16),
+}
+
+/// Load a test binary into memory at its origin, or at $0000 if it's a full
+/// 64 KiB image, and start running it from the given address on the 6502.
+fn load(bytes: &[u8], origin: u16, entry: u16) -> CPU {
+    let origin = if bytes.len() == MEMORY_SIZE {
+        0
+    } else {
+        origin
+    };
+
+    let mut cpu = CPU::new();
+    cpu.mode = Mode::Mos6502;
+    cpu.load_at(bytes, origin);
+    cpu.reset();
+    cpu.program_counter = entry;
+    cpu
+}
+
+/// Run until the program counter stops changing, or the program hits an
+/// unrecognized opcode.
+fn run_until_trap(cpu: &mut CPU) -> Stop {
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = cpu.program_counter;
+
+        match cpu.try_step() {
+            Ok(()) if cpu.program_counter == pc => return Stop::Trap(pc),
+            Ok(()) => {}
+            Err(Error::UnknownOpcode { addr, .. }) => return Stop::Stopped(addr),
+        }
+    }
+
+    Stop::Timeout(cpu.program_counter)
+}
+
+#[test]
+#[ignore = "needs 6502_functional_test.bin in tests/roms"]
+fn functional_test() {
+    let [path] = common::require_roms(["6502_functional_test.bin"]);
+
+    let mut cpu = load(&fs::read(path).unwrap(), 0, FUNCTIONAL_ENTRY);
+    let stop = run_until_trap(&mut cpu);
+
+    assert_eq!(
+        stop,
+        Stop::Trap(FUNCTIONAL_SUCCESS),
+        "functional test failed after {} cycles, see the listing at the trap address \
+         (test case number ${:02x})",
+        cpu.cycles,
+        cpu.peek(0x0200)
+    );
+}
+
+#[test]
+#[ignore = "needs 6502_decimal_test.bin in tests/roms"]
+fn decimal_test() {
+    let [path] = common::require_roms(["6502_decimal_test.bin"]);
+
+    let mut cpu = load(&fs::read(path).unwrap(), DECIMAL_ORIGIN, DECIMAL_ORIGIN);
+    let stop = run_until_trap(&mut cpu);
+
+    assert!(
+        matches!(stop, Stop::Trap(_) | Stop::Stopped(_)),
+        "decimal test didn't finish: {:?}",
+        stop
+    );
+    assert_eq!(
+        cpu.peek(DECIMAL_ERROR),
+        0,
+        "decimal test reported an error, ending at {:?}",
+        stop
+    );
+}
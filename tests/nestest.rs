@@ -0,0 +1,65 @@
+//! Runs nestest in its automated mode, starting at $C000, and compares every
+//! instruction against the reference log from Nintendulator.
+//!
+//! This hasn't been run against the real ROM and log yet, so it isn't evidence
+//! of conformance until it has been. See the status in `tests/roms/README.md`.
+
+use std::fs;
+
+use nes799::{
+    cartridge::Cartridge,
+    cpu::{trace::format_nestest, CPU},
+};
+
+mod common;
+
+/// Address of the automated test entry point, which runs without a PPU.
+const ENTRY: u16 = 0xc000;
+/// Cycles taken by the reset sequence before the first instruction.
+const RESET_CYCLES: u64 = 7;
+
+/// Addresses nestest stores the result codes of the official and unofficial
+/// opcode tests at, which are zero if every test passed.
+const RESULT_OFFICIAL: u16 = 0x02;
+const RESULT_UNOFFICIAL: u16 = 0x03;
+
+#[test]
+#[ignore = "needs nestest.nes and nestest.log in tests/roms"]
+fn nestest() {
+    let [rom, log] = common::require_roms(["nestest.nes", "nestest.log"]);
+
+    let cartridge = Cartridge::load(&rom).unwrap();
+    let log = fs::read_to_string(&log).unwrap();
+
+    let mut cpu = CPU::new();
+    cpu.mapper = Some(cartridge.create_mapper().unwrap());
+    cpu.reset();
+    cpu.program_counter = ENTRY;
+    cpu.cycles = RESET_CYCLES;
+
+    let mut previous = String::new();
+    for (number, expected) in log.lines().map(str::trim_end).enumerate() {
+        let actual = format_nestest(&cpu);
+
+        assert!(
+            actual == expected,
+            "nestest.log diverges at line {}\n previous: {}\n expected: {}\n   actual: {}",
+            number + 1,
+            previous,
+            expected,
+            actual
+        );
+
+        if let Err(e) = cpu.try_step() {
+            panic!("{} after line {} of nestest.log", e, number + 1);
+        }
+
+        previous = actual;
+    }
+
+    assert_eq!(
+        (cpu.peek(RESULT_OFFICIAL), cpu.peek(RESULT_UNOFFICIAL)),
+        (0, 0),
+        "nestest reported failure codes"
+    );
+}
@@ -0,0 +1,389 @@
+//! Runs the single-step ProcessorTests for the 6502 and the NES's 2A03, which
+//! give the state before and after a single instruction along with every bus
+//! access it makes. Each case runs on a CPU whose entire address space is a
+//! recording bus.
+//!
+//! The tests are read from `tests/roms/ProcessorTests`, or the directory in
+//! the `PROCESSOR_TESTS_DIR` environment variable, which should contain the
+//! `6502` and `nes6502` directories of the ProcessorTests repository. They're
+//! slow in debug builds, so consider running them with `--release`.
+//!
+//! The runner hasn't been run against the real data yet, so it isn't evidence
+//! of conformance until it has been. See the status in `tests/roms/README.md`.
+
+use std::{
+    cell::RefCell,
+    env, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use nes799::{
+    cpu::{disassembler::DecodedInstruction, mode::Mode, status::Status, Error, CPU},
+    mapper::Mapper,
+};
+use serde::Deserialize;
+
+mod common;
+
+/// Registers the 2A03 handles itself rather than passing to the bus, so cases
+/// that touch them can't be checked against plain RAM.
+const IO_REGISTERS: RangeInclusive<u16> = 0x4000..=0x4017;
+
+/// Status bits that only exist in copies of the status pushed onto the stack.
+const BREAK_BITS: u8 = 0x30;
+
+/// Opcodes the CPU doesn't implement, which each suite must report as
+/// unsupported, so that one being lost or added is noticed.
+#[rustfmt::skip]
+const UNSUPPORTED: &[u8] = &[
+    // JAM, which locks up the CPU.
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xb2, 0xd2, 0xf2,
+    // ANC, ALR, ARR, XAA, LXA, and SBX, which combine immediate operations.
+    0x0b, 0x2b, 0x4b, 0x6b, 0x8b, 0xab, 0xcb,
+    // SHA, TAS, SHY, SHX, and LAS, which depend on the address bus.
+    0x93, 0x9f, 0x9b, 0x9c, 0x9e, 0xbb,
+];
+
+/// CPU state at the start or end of a case.
+#[derive(Debug, Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// A single instruction to run, and the state and bus accesses expected from
+/// it.
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+impl Case {
+    /// Whether any address the case touches is one of the 2A03's I/O
+    /// registers.
+    fn touches_io(&self) -> bool {
+        self.initial
+            .ram
+            .iter()
+            .chain(&self.expected.ram)
+            .map(|&(addr, _)| addr)
+            .chain(self.cycles.iter().map(|&(addr, _, _)| addr))
+            .any(|addr| IO_REGISTERS.contains(&addr))
+    }
+
+    /// The length of the instruction the case runs, in bytes.
+    fn instruction_len(&self) -> u16 {
+        let byte = |addr: u16| {
+            self.initial
+                .ram
+                .iter()
+                .find(|&&(a, _)| a == addr)
+                .map_or(0, |&(_, value)| value)
+        };
+        let bytes: Vec<u8> = (0..3)
+            .map(|i| byte(self.initial.pc.wrapping_add(i)))
+            .collect();
+
+        DecodedInstruction::decode(&bytes, self.initial.pc)
+            .bytes
+            .len() as u16
+    }
+}
+
+/// A bus access, as recorded by [`RecordingBus`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Access {
+    addr: u16,
+    value: u8,
+    kind: &'static str,
+}
+
+/// 64 KiB of RAM that records every access made to it.
+#[derive(Debug)]
+struct Bus {
+    ram: Vec<u8>,
+    accesses: Vec<Access>,
+}
+
+/// A mapper that claims the entire address space, so that every read and write
+/// the CPU makes goes through a shared [`Bus`].
+#[derive(Debug)]
+struct RecordingBus(Rc<RefCell<Bus>>);
+
+impl Mapper for RecordingBus {
+    fn read(&self, addr: u16) -> Option<u8> {
+        let mut bus = self.0.borrow_mut();
+        let value = bus.ram[usize::from(addr)];
+
+        bus.accesses.push(Access {
+            addr,
+            value,
+            kind: "read",
+        });
+
+        Some(value)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> bool {
+        let mut bus = self.0.borrow_mut();
+        bus.ram[usize::from(addr)] = value;
+
+        bus.accesses.push(Access {
+            addr,
+            value,
+            kind: "write",
+        });
+
+        true
+    }
+}
+
+/// How a case turned out.
+enum Outcome {
+    Passed,
+    /// The opcode isn't implemented.
+    Unsupported,
+    /// The case touches registers that aren't on the bus in this mode.
+    Skipped,
+    /// Each way the result differed from what was expected.
+    Failed(Vec<String>),
+}
+
+/// Run a single case, and compare the registers, RAM, number of cycles, and
+/// bus accesses to those expected. The CPU doesn't make the dummy accesses of
+/// the real hardware, so its bus accesses are checked in order against the
+/// expected log with the ones it doesn't make skipped, and only skipped
+/// accesses that can't be dummy accesses are reported as missing.
+fn run_case(case: &Case, mode: Mode) -> Outcome {
+    if mode == Mode::Nes2A03 && case.touches_io() {
+        return Outcome::Skipped;
+    }
+
+    let bus = Rc::new(RefCell::new(Bus {
+        ram: vec![0; 0x10000],
+        accesses: vec![],
+    }));
+
+    for &(addr, value) in &case.initial.ram {
+        bus.borrow_mut().ram[usize::from(addr)] = value;
+    }
+
+    let mut cpu = CPU::new();
+    cpu.mode = mode;
+    cpu.mapper = Some(Box::new(RecordingBus(bus.clone())));
+    cpu.program_counter = case.initial.pc;
+    cpu.stack_pointer = case.initial.s.into();
+    cpu.accumulator = case.initial.a;
+    cpu.index_x = case.initial.x;
+    cpu.index_y = case.initial.y;
+    cpu.status = Status::from(case.initial.p);
+
+    match cpu.try_step() {
+        Ok(()) => {}
+        Err(Error::UnknownOpcode { .. }) => return Outcome::Unsupported,
+    }
+
+    let bus = bus.borrow();
+    let expected = &case.expected;
+    let mut mismatches = vec![];
+
+    let registers = [
+        ("pc", expected.pc, cpu.program_counter),
+        ("s", expected.s.into(), u8::from(cpu.stack_pointer).into()),
+        ("a", expected.a.into(), cpu.accumulator.into()),
+        ("x", expected.x.into(), cpu.index_x.into()),
+        ("y", expected.y.into(), cpu.index_y.into()),
+        (
+            "p",
+            (expected.p | BREAK_BITS).into(),
+            (cpu.status.bits() | BREAK_BITS).into(),
+        ),
+    ];
+
+    for (name, expected, actual) in registers {
+        if expected != actual {
+            mismatches.push(format!(
+                "{}: expected ${:02x}, got ${:02x}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    for &(addr, value) in &expected.ram {
+        let actual = bus.ram[usize::from(addr)];
+        if actual != value {
+            mismatches.push(format!(
+                "${:04x}: expected ${:02x}, got ${:02x}",
+                addr, value, actual
+            ));
+        }
+    }
+
+    if cpu.cycles != case.cycles.len() as u64 {
+        mismatches.push(format!(
+            "cycles: expected {}, got {}",
+            case.cycles.len(),
+            cpu.cycles
+        ));
+    }
+
+    let mut matched = vec![false; case.cycles.len()];
+    let mut next = 0;
+
+    for access in &bus.accesses {
+        let found = case.cycles[next..].iter().position(|(addr, value, kind)| {
+            *addr == access.addr && *value == access.value && kind == access.kind
+        });
+
+        match found {
+            Some(offset) => {
+                matched[next + offset] = true;
+                next += offset + 1;
+            }
+            None => mismatches.push(format!(
+                "unexpected or out of order {} of ${:02x} at ${:04x}",
+                access.kind, access.value, access.addr
+            )),
+        }
+    }
+
+    // Fetches of the instruction's own bytes are never dummy reads. Every
+    // write is real too, except the write of the unmodified value that
+    // read-modify-write instructions make just before writing the result.
+    let len = case.instruction_len();
+    for (i, (addr, value, kind)) in case.cycles.iter().enumerate() {
+        let fetch = kind == "read" && addr.wrapping_sub(case.initial.pc) < len;
+        let write = kind == "write"
+            && case
+                .cycles
+                .get(i + 1)
+                .is_none_or(|(next, _, kind)| kind != "write" || next != addr);
+
+        if !matched[i] && (fetch || write) {
+            mismatches.push(format!(
+                "missing {} of ${:02x} at ${:04x}",
+                kind, value, addr
+            ));
+        }
+    }
+
+    if mismatches.is_empty() {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(mismatches)
+    }
+}
+
+/// Run every case for every opcode in the given directory, printing a line per
+/// opcode with failures, and return the number of opcodes that failed along
+/// with the opcodes that were unsupported.
+fn run_suite(dir: &Path, mode: Mode) -> (usize, Vec<u8>) {
+    let mut failed_opcodes = 0;
+    let mut unsupported = vec![];
+    let mut skipped = 0;
+
+    for opcode in 0..=u8::MAX {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let Ok(json) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let cases: Vec<Case> = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("could not parse {}: {}", path.display(), e));
+
+        let mut failures = 0;
+        let mut first_failure = None;
+
+        for case in &cases {
+            match run_case(case, mode) {
+                Outcome::Passed => {}
+                Outcome::Unsupported => {
+                    unsupported.push(opcode);
+                    break;
+                }
+                Outcome::Skipped => skipped += 1,
+                Outcome::Failed(mismatches) => {
+                    failures += 1;
+                    first_failure.get_or_insert((&case.name, mismatches));
+                }
+            }
+        }
+
+        if let Some((name, mismatches)) = first_failure {
+            failed_opcodes += 1;
+            eprintln!(
+                "{} ${:02x}: {} of {} cases failed, first \"{}\": {}",
+                mode,
+                opcode,
+                failures,
+                cases.len(),
+                name,
+                mismatches.join(", ")
+            );
+        }
+    }
+
+    eprintln!(
+        "{}: {} opcodes failed, {} cases touching I/O skipped, {} unsupported",
+        mode,
+        failed_opcodes,
+        skipped,
+        unsupported.len()
+    );
+
+    (failed_opcodes, unsupported)
+}
+
+/// Run a suite, and check that only the known opcodes were unsupported and
+/// that no others failed.
+fn check_suite(dir: &Path, mode: Mode) {
+    let (failed_opcodes, unsupported) = run_suite(dir, mode);
+
+    let mut expected = UNSUPPORTED.to_vec();
+    expected.sort();
+    let hex = |opcodes: &[u8]| -> Vec<String> {
+        opcodes.iter().map(|op| format!("{:02x}", op)).collect()
+    };
+    assert_eq!(
+        hex(&unsupported),
+        hex(&expected),
+        "unsupported opcodes changed"
+    );
+    assert_eq!(failed_opcodes, 0, "opcodes failed");
+}
+
+/// The directory of tests for the given CPU, panicking if it's missing.
+fn suite_dir(name: &str) -> PathBuf {
+    let root = env::var_os("PROCESSOR_TESTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| common::rom_path("ProcessorTests"));
+    let dir = root.join(name).join("v1");
+
+    if !dir.is_dir() {
+        panic!("{} is missing, see tests/roms/README.md", dir.display());
+    }
+
+    dir
+}
+
+#[test]
+#[ignore = "needs the ProcessorTests 6502 data in tests/roms or PROCESSOR_TESTS_DIR"]
+fn processor_tests_6502() {
+    check_suite(&suite_dir("6502"), Mode::Mos6502);
+}
+
+#[test]
+#[ignore = "needs the ProcessorTests nes6502 data in tests/roms or PROCESSOR_TESTS_DIR"]
+fn processor_tests_2a03() {
+    check_suite(&suite_dir("nes6502"), Mode::Nes2A03);
+}
@@ -0,0 +1,125 @@
+//! Runs the command line with `--wav` to check that it records the APU's
+//! output, or refuses to when the mode has no APU, and that it plays NSF files.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+/// A raw binary that plays a tone on pulse 1 forever.
+const TONE: &[u8] = &[
+    0xa9, 0x01, // enable pulse 1
+    0x8d, 0x15, 0x40, // STA $4015
+    0xa9, 0xbf, // 50% duty, constant volume 15, halted
+    0x8d, 0x00, 0x40, // STA $4000
+    0xa9, 0xfd, // ~440 Hz
+    0x8d, 0x02, 0x40, // STA $4002
+    0xa9, 0x08, // load the length counter
+    0x8d, 0x03, 0x40, // STA $4003
+    0x4c, 0x14, 0x80, // JMP $8014
+];
+
+/// Write the tone program to a temporary file, returning its path and the path
+/// to record a WAV file to.
+fn paths(test: &str) -> (PathBuf, PathBuf) {
+    let program = env::temp_dir().join(format!("nes799_test_{}.bin", test));
+    let wav = program.with_extension("wav");
+    fs::write(&program, TONE).unwrap();
+    let _ = fs::remove_file(&wav);
+    (program, wav)
+}
+
+fn nes799() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_nes799"))
+}
+
+#[test]
+fn records_samples() {
+    let (program, wav) = paths("cli_wav");
+    let output = nes799()
+        .arg(&program)
+        .arg("--wav")
+        .arg(&wav)
+        .args(["--frames", "2"])
+        .output()
+        .unwrap();
+
+    // Recording stops at the frame limit.
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+
+    let bytes = fs::read(&wav).unwrap();
+    let data_size = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+    assert!(data_size > 0);
+    assert_eq!(data_size as usize, bytes.len() - 44);
+
+    fs::remove_file(program).unwrap();
+    fs::remove_file(wav).unwrap();
+}
+
+#[test]
+fn refuses_mode_without_apu() {
+    let (program, wav) = paths("cli_wav_mos6502");
+    let output = nes799()
+        .arg(&program)
+        .args(["--mode", "mos6502", "--wav"])
+        .arg(&wav)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--wav needs the nes2a03 mode, since the mos6502 mode has no APU"));
+    assert!(!wav.exists());
+
+    // The built-in snake game runs on the 6502 too.
+    let output = nes799().arg("--wav").arg(&wav).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!wav.exists());
+
+    fs::remove_file(program).unwrap();
+}
+
+/// An NSF file with two songs, whose init and play routines return at once.
+fn nsf() -> Vec<u8> {
+    let mut bytes = vec![0; 0x80];
+    bytes[0..5].copy_from_slice(b"NESM\x1a");
+    bytes[0x05] = 1; // version
+    bytes[0x06] = 2; // total songs
+    bytes[0x07] = 1; // starting song
+    bytes[0x08..0x0a].copy_from_slice(&0x8000_u16.to_le_bytes()); // load
+    bytes[0x0a..0x0c].copy_from_slice(&0x8000_u16.to_le_bytes()); // init
+    bytes[0x0c..0x0e].copy_from_slice(&0x8000_u16.to_le_bytes()); // play
+    bytes.push(0x60); // RTS
+    bytes
+}
+
+#[test]
+fn checks_song_number() {
+    let (program, wav) = paths("cli_wav_nsf");
+    fs::write(&program, nsf()).unwrap();
+
+    for song in ["0", "3"] {
+        let output = nes799()
+            .arg(&program)
+            .arg("--wav")
+            .arg(&wav)
+            .args(["--song", song])
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("--song must be from 1 to 2, the number of songs in the file"));
+        assert!(!wav.exists());
+    }
+
+    let output = nes799()
+        .arg(&program)
+        .arg("--wav")
+        .arg(&wav)
+        .args(["--song", "2", "--frames", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(wav.exists());
+
+    fs::remove_file(program).unwrap();
+    fs::remove_file(wav).unwrap();
+}